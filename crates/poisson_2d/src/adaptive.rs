@@ -0,0 +1,480 @@
+//! Module that adapts the mesh to the solution with a solve-estimate-mark-refine loop.
+//!
+//! The error is estimated per element with the Zienkiewicz-Zhu recovery estimator, the
+//! elements carrying the bulk of the estimate are selected with Dörfler marking, and the
+//! marked triangles are refined by newest vertex bisection, which keeps the mesh conforming
+//! and shape regular.
+use crate::direct::{Reordering, SparseCholesky};
+use crate::element::{Element, ElementType};
+use crate::mesh::{Edge, Mesh2d, MeshError};
+use crate::postprocess::nodal_gradients;
+use crate::quadrature::QuadRule;
+use crate::solver::{PoissonError, apply_dirichlet_sparse, assemble_system_sparse, check_problem};
+use nalgebra::{DVector, Point2, Vector2};
+use std::collections::{HashMap, HashSet};
+
+// ANCHOR: zz_estimator
+/// Zienkiewicz-Zhu error indicators `η_K = ‖G(u_h) - ∇u_h‖_K` of each element.
+///
+/// `G(u_h)` is the continuous gradient recovered by `nodal_gradients` and interpolated with
+/// the shape functions. The global estimate of the energy error is `(Σ η_K²)^½`. Panics if
+/// `u` does not have one value per mesh vertex.
+pub fn zz_indicators(mesh: &Mesh2d, u: &DVector<f64>) -> Vec<f64> {
+    let recovered = nodal_gradients(mesh, u);
+    let mut indicators = Vec::with_capacity(mesh.elements().len());
+    for (element, element_type) in mesh.elements().iter().zip(mesh.element_types()) {
+        let ref_element = element_type.reference_element();
+        let quad_rule = QuadRule::for_element(element_type);
+        let nodes: Vec<Point2<f64>> = element
+            .indices
+            .iter()
+            .map(|&i| mesh.vertices()[i])
+            .collect();
+        let mut eta2 = 0.0;
+        for (p, w) in quad_rule.points.iter().zip(&quad_rule.weights) {
+            let jac = ref_element.jacobian(&nodes, p);
+            let jac_inv_t = jac
+                .try_inverse()
+                .expect("degenerate element, see Mesh2d::validate")
+                .transpose();
+            let mut difference = Vector2::zeros();
+            for ((val, grad_ref), &i) in ref_element
+                .shape_functions(p)
+                .iter()
+                .zip(ref_element.shape_gradients(p))
+                .zip(&element.indices)
+            {
+                difference += recovered[i] * *val - jac_inv_t * grad_ref * u[i];
+            }
+            eta2 += difference.norm_squared() * w * jac.determinant().abs();
+        }
+        indicators.push(eta2.sqrt());
+    }
+    indicators
+}
+// ANCHOR_END: zz_estimator
+
+// ANCHOR: dorfler_marking
+/// Dörfler (bulk) marking: the smallest set of elements whose squared indicators add up
+/// to at least `theta` times the total, with `0 < theta <= 1`.
+///
+/// The marked elements are returned by decreasing indicator.
+pub fn dorfler_marking(indicators: &[f64], theta: f64) -> Vec<usize> {
+    assert!(
+        0.0 < theta && theta <= 1.0,
+        "the marking parameter must satisfy 0 < theta <= 1"
+    );
+    let mut order: Vec<usize> = (0..indicators.len()).collect();
+    order.sort_by(|&i, &j| indicators[j].total_cmp(&indicators[i]));
+    let total: f64 = indicators.iter().map(|eta| eta * eta).sum();
+    let mut marked = Vec::new();
+    let mut sum = 0.0;
+    for e in order {
+        if sum >= theta * total {
+            break;
+        }
+        sum += indicators[e] * indicators[e];
+        marked.push(e);
+    }
+    marked
+}
+// ANCHOR_END: dorfler_marking
+
+/// Orientation-independent key of the edge between `a` and `b`.
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+// ANCHOR: refine_marked
+impl Mesh2d {
+    /// Refines the marked triangles by newest vertex bisection and returns the new mesh.
+    ///
+    /// The refinement edge of a triangle is the edge between its first two nodes, and the
+    /// midpoint inserted on it becomes the third node of both children. Neighbours are
+    /// bisected as well until no hanging node remains, so a few unmarked elements are
+    /// usually refined too. Boundary groups and regions are inherited by the children.
+    /// Only meshes of `P1` triangles are supported.
+    pub fn refine_marked(&self, marked: &[usize]) -> Result<Mesh2d, MeshError> {
+        for (e, element_type) in self.element_types().iter().enumerate() {
+            if *element_type != ElementType::P1 {
+                return Err(MeshError::UnsupportedElementType {
+                    element: e,
+                    element_type: element_type.clone(),
+                });
+            }
+        }
+
+        // Mark the refinement edges of the marked elements, then close the set: an element
+        // with a marked edge must also have its refinement edge marked.
+        let elements = self.elements();
+        let mut edges: HashSet<(usize, usize)> = marked
+            .iter()
+            .map(|&e| edge_key(elements[e].indices[0], elements[e].indices[1]))
+            .collect();
+        let mut changed = !edges.is_empty();
+        while changed {
+            changed = false;
+            for element in elements {
+                let [a, b, c] = [element.indices[0], element.indices[1], element.indices[2]];
+                let refinement_edge = edge_key(a, b);
+                if !edges.contains(&refinement_edge)
+                    && (edges.contains(&edge_key(b, c)) || edges.contains(&edge_key(c, a)))
+                {
+                    edges.insert(refinement_edge);
+                    changed = true;
+                }
+            }
+        }
+
+        // Insert the midpoints in a deterministic order.
+        let mut vertices = self.vertices().to_vec();
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        for element in elements {
+            for k in 0..3 {
+                let key = edge_key(element.indices[k], element.indices[(k + 1) % 3]);
+                if edges.contains(&key) && !midpoints.contains_key(&key) {
+                    midpoints.insert(key, vertices.len());
+                    vertices.push(nalgebra::center(&vertices[key.0], &vertices[key.1]));
+                }
+            }
+        }
+
+        let mut new_elements = Vec::new();
+        let mut children: Vec<Vec<usize>> = Vec::with_capacity(elements.len());
+        for element in elements {
+            let first = new_elements.len();
+            bisect(&element.indices, &midpoints, &mut new_elements);
+            children.push((first..new_elements.len()).collect());
+        }
+
+        let mut refined = Mesh2d::new(vertices, new_elements, ElementType::P1);
+        for name in self.boundary_group_names() {
+            let mut group = Vec::new();
+            for edge in self.boundary_group(name).unwrap() {
+                let [a, b] = [edge.indices[0], edge.indices[1]];
+                match midpoints.get(&edge_key(a, b)) {
+                    Some(&m) => {
+                        group.push(Edge {
+                            indices: vec![a, m],
+                        });
+                        group.push(Edge {
+                            indices: vec![m, b],
+                        });
+                    }
+                    None => group.push(edge.clone()),
+                }
+            }
+            refined.add_boundary_group(name, group);
+        }
+        for name in self.region_names() {
+            let region = self.region(name).unwrap();
+            let elements = region.iter().flat_map(|&e| children[e].clone()).collect();
+            refined.add_region(name, elements);
+        }
+        Ok(refined)
+    }
+}
+// ANCHOR_END: refine_marked
+
+/// Bisects the triangle `[a, b, c]` on its refinement edge `a-b` if it is marked, then its
+/// children on their own refinement edges, pushing the resulting triangles.
+fn bisect(
+    indices: &[usize],
+    midpoints: &HashMap<(usize, usize), usize>,
+    elements: &mut Vec<Element>,
+) {
+    let [a, b, c] = [indices[0], indices[1], indices[2]];
+    match midpoints.get(&edge_key(a, b)) {
+        Some(&m) => {
+            bisect(&[c, a, m], midpoints, elements);
+            bisect(&[b, c, m], midpoints, elements);
+        }
+        None => elements.push(Element {
+            indices: indices.to_vec(),
+        }),
+    }
+}
+
+// ANCHOR: adaptive_config
+/// Stopping criteria and marking parameter of the adaptive loop.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveConfig {
+    /// Dörfler marking parameter, see `dorfler_marking`
+    pub theta: f64,
+    /// Stop when the global error estimate is below this value
+    pub tolerance: f64,
+    /// Maximum number of solves
+    pub max_steps: usize,
+    /// Stop once the mesh has at least this many vertices
+    pub max_vertices: usize,
+}
+
+impl AdaptiveConfig {
+    pub fn new(theta: f64) -> Self {
+        Self {
+            theta,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn with_max_vertices(mut self, max_vertices: usize) -> Self {
+        self.max_vertices = max_vertices;
+        self
+    }
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            theta: 0.5,
+            tolerance: 0.0,
+            max_steps: 20,
+            max_vertices: 100_000,
+        }
+    }
+}
+
+/// Size of the mesh and error estimate at one step of the adaptive loop.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveStep {
+    pub num_vertices: usize,
+    pub num_elements: usize,
+    pub estimate: f64,
+}
+
+/// Final mesh and solution of the adaptive loop, with the indicators of the last step.
+#[derive(Clone, Debug)]
+pub struct AdaptiveSolution {
+    pub mesh: Mesh2d,
+    pub u: DVector<f64>,
+    pub indicators: Vec<f64>,
+    pub history: Vec<AdaptiveStep>,
+}
+// ANCHOR_END: adaptive_config
+
+// ANCHOR: solve_adaptive
+/// Solves `-Δu = f` with `u = g` on the whole boundary, refining the mesh until one of the
+/// stopping criteria of `config` is met.
+///
+/// Each step solves on the current mesh, computes the Zienkiewicz-Zhu indicators, marks
+/// elements with Dörfler marking and refines them by newest vertex bisection. The initial
+/// mesh must be made of `P1` triangles.
+pub fn solve_adaptive<G, F>(
+    mesh: Mesh2d,
+    boundary_fn: G,
+    source_fn: F,
+    config: &AdaptiveConfig,
+) -> Result<AdaptiveSolution, PoissonError>
+where
+    G: Fn(f64, f64) -> f64,
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    let mut mesh = mesh;
+    let mut history = Vec::new();
+    loop {
+        let boundary_nodes = check_problem(&mesh, &mesh.boundary_nodes())?;
+        let (mut a, mut b) = assemble_system_sparse(&mesh, &source_fn);
+        apply_dirichlet_sparse(&mut a, &mut b, &boundary_nodes, &mesh, &boundary_fn);
+        let u = SparseCholesky::factor(&a, Reordering::ReverseCuthillMcKee)?.solve(&b)?;
+
+        let indicators = zz_indicators(&mesh, &u);
+        let estimate = indicators.iter().map(|eta| eta * eta).sum::<f64>().sqrt();
+        history.push(AdaptiveStep {
+            num_vertices: mesh.vertices().len(),
+            num_elements: mesh.elements().len(),
+            estimate,
+        });
+        if estimate <= config.tolerance
+            || history.len() >= config.max_steps
+            || mesh.vertices().len() >= config.max_vertices
+        {
+            return Ok(AdaptiveSolution {
+                mesh,
+                u,
+                indicators,
+                history,
+            });
+        }
+        let marked = dorfler_marking(&indicators, config.theta);
+        mesh = mesh.refine_marked(&marked)?;
+    }
+}
+// ANCHOR_END: solve_adaptive
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convergence::h1_seminorm_error;
+    use crate::generators::{l_shape, unit_square};
+    use std::f64::consts::PI;
+
+    /// Number of elements sharing each edge.
+    fn edge_counts(mesh: &Mesh2d) -> HashMap<(usize, usize), usize> {
+        let mut counts = HashMap::new();
+        for element in mesh.elements() {
+            for k in 0..3 {
+                let key = edge_key(element.indices[k], element.indices[(k + 1) % 3]);
+                *counts.entry(key).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    fn area(mesh: &Mesh2d) -> f64 {
+        mesh.quality().iter().map(|q| q.signed_area).sum()
+    }
+
+    /// `u = r^(2/3) sin(2θ/3)`, harmonic on the L-shaped domain.
+    fn corner_solution(x: f64, y: f64) -> f64 {
+        let theta = y.atan2(x).rem_euclid(2.0 * PI);
+        (x * x + y * y).powf(1.0 / 3.0) * (2.0 * theta / 3.0).sin()
+    }
+
+    fn corner_gradient(x: f64, y: f64) -> Vector2<f64> {
+        let theta = y.atan2(x).rem_euclid(2.0 * PI);
+        let scale = 2.0 / 3.0 * (x * x + y * y).powf(-1.0 / 6.0);
+        Vector2::new(-(theta / 3.0).sin(), (theta / 3.0).cos()) * scale
+    }
+
+    #[test]
+    fn test_dorfler_marking() {
+        let indicators = [1.0, 3.0, 0.5, 2.0, 1.0];
+        // Squared indicators: 9 + 4 = 13 >= 0.8 * 15.25, 9 < 0.8 * 15.25.
+        assert_eq!(dorfler_marking(&indicators, 0.8), vec![1, 3]);
+        assert_eq!(dorfler_marking(&indicators, 0.5), vec![1]);
+        assert_eq!(dorfler_marking(&indicators, 1.0).len(), 5);
+        assert!(dorfler_marking(&[0.0, 0.0], 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_refine_marked() {
+        let (mut mesh, _) = unit_square(4, ElementType::P1);
+        mesh.add_region("corner", vec![0, 1]);
+        let mut refined = mesh.clone();
+        for _ in 0..6 {
+            let num_elements = refined.elements().len();
+            // Refine towards the corner (0, 0).
+            let closest = (0..refined.elements().len())
+                .min_by(|&i, &j| {
+                    let distance = |e: usize| {
+                        refined.elements()[e]
+                            .indices
+                            .iter()
+                            .map(|&k| refined.vertices()[k].coords.norm())
+                            .sum::<f64>()
+                    };
+                    distance(i).total_cmp(&distance(j))
+                })
+                .unwrap();
+            refined = refined.refine_marked(&[closest]).unwrap();
+            refined.validate().unwrap();
+            assert!(refined.elements().len() > num_elements);
+
+            // No hanging node: the edges owned by one element all lie on the boundary.
+            for ((a, b), count) in edge_counts(&refined) {
+                assert!(count <= 2);
+                if count == 1 {
+                    let (p, q) = (refined.vertices()[a], refined.vertices()[b]);
+                    let on_side = |s: f64, t: f64| (s == 0.0 || s == 1.0) && s == t;
+                    assert!(on_side(p.x, q.x) || on_side(p.y, q.y));
+                }
+            }
+            assert!((area(&refined) - 1.0).abs() < 1e-14);
+        }
+
+        // The boundary groups still cover the sides, the region keeps its area.
+        let length = |name: &str| -> f64 {
+            refined
+                .boundary_group(name)
+                .unwrap()
+                .iter()
+                .map(|e| {
+                    (refined.vertices()[e.indices[1]] - refined.vertices()[e.indices[0]]).norm()
+                })
+                .sum()
+        };
+        for name in ["left", "right", "bottom", "top"] {
+            assert!((length(name) - 1.0).abs() < 1e-14);
+        }
+        let corner: f64 = refined
+            .region("corner")
+            .unwrap()
+            .iter()
+            .map(|&e| refined.element_quality(e).signed_area)
+            .sum();
+        assert!((corner - 1.0 / 16.0).abs() < 1e-14);
+
+        let (quads, _) = unit_square(2, ElementType::Q1);
+        assert!(matches!(
+            quads.refine_marked(&[0]),
+            Err(MeshError::UnsupportedElementType { element: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_refinement_keeps_angles() {
+        // Newest vertex bisection creates a finite number of similarity classes, so the
+        // minimum angle stops decreasing after the first refinements.
+        let (mut mesh, _) = unit_square(2, ElementType::P1);
+        for _ in 0..2 {
+            mesh = mesh.refine_marked(&[0]).unwrap();
+        }
+        let min_angle = mesh.quality_summary().min_angle;
+        for _ in 0..8 {
+            mesh = mesh.refine_marked(&[0]).unwrap();
+            assert!((mesh.quality_summary().min_angle - min_angle).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_l_shape() {
+        let (mesh, _) = l_shape(2, ElementType::P1);
+        let config = AdaptiveConfig::new(0.3)
+            .with_max_steps(50)
+            .with_max_vertices(1000);
+        let adaptive = solve_adaptive(mesh, corner_solution, |_, _| 0.0, &config).unwrap();
+
+        let history = &adaptive.history;
+        let last = history.last().unwrap();
+        assert!(last.num_vertices >= 1000);
+        assert!(last.estimate < 0.2 * history[0].estimate);
+
+        // The estimate decays like N^(-1/2), uniform refinement only reaches N^(-1/3).
+        let first = history.iter().find(|s| s.num_vertices >= 100).unwrap();
+        let rate = (first.estimate / last.estimate).ln()
+            / (last.num_vertices as f64 / first.num_vertices as f64).ln();
+        assert!(rate > 0.4, "observed rate {rate}");
+
+        // The estimator is equivalent to the true error.
+        let error = h1_seminorm_error(&adaptive.mesh, &adaptive.u, corner_gradient);
+        let effectivity = last.estimate / error;
+        assert!(
+            (0.5..2.0).contains(&effectivity),
+            "effectivity {effectivity}"
+        );
+
+        // A uniform mesh with more vertices is less accurate.
+        let (uniform, boundary) = l_shape(32, ElementType::P1);
+        assert!(uniform.vertices().len() > last.num_vertices);
+        let boundary = boundary.all();
+        let (mut a, mut b) = assemble_system_sparse(&uniform, &|_, _| 0.0);
+        apply_dirichlet_sparse(&mut a, &mut b, &boundary, &uniform, corner_solution);
+        let u = SparseCholesky::factor(&a, Reordering::ReverseCuthillMcKee)
+            .unwrap()
+            .solve(&b)
+            .unwrap();
+        assert!(error < h1_seminorm_error(&uniform, &u, corner_gradient));
+    }
+}
+// ANCHOR_END: tests
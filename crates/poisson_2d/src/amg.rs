@@ -0,0 +1,388 @@
+//! Module that builds a smoothed aggregation algebraic multigrid hierarchy from a matrix.
+//!
+//! Unlike geometric multigrid, no mesh hierarchy is needed: the unknowns are grouped into
+//! aggregates of strongly connected neighbours, a tentative prolongation interpolates the
+//! constant vector on each aggregate, and one damped Jacobi step smooths it (Vaněk, Mandel
+//! and Brezina, 1996). The resulting hierarchy is cycled by `Multigrid`, which makes it a
+//! preconditioner for the conjugate gradient on unstructured meshes.
+use crate::iterative::diagonal;
+use crate::multigrid::{Multigrid, MultigridConfig};
+use crate::solver::PoissonError;
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+
+// ANCHOR: amg_config
+/// Settings of the smoothed aggregation hierarchy.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AmgConfig {
+    /// Unknowns `i` and `j` are strongly connected if `|a_ij| ≥ θ √(a_ii a_jj)`
+    pub strength_threshold: f64,
+    /// Maximum number of levels, the finest one included
+    pub max_levels: usize,
+    /// Coarsening stops once a level has at most this many unknowns
+    pub coarse_size: usize,
+    /// Cycle and smoother applied to the hierarchy
+    pub multigrid: MultigridConfig,
+}
+
+impl Default for AmgConfig {
+    /// Strength threshold of `0.08`, at most 10 levels down to 50 unknowns, and V-cycles
+    /// with one Gauss-Seidel sweep before and after the coarse correction.
+    fn default() -> Self {
+        Self {
+            strength_threshold: 0.08,
+            max_levels: 10,
+            coarse_size: 50,
+            multigrid: MultigridConfig::default().with_smoothing(1, 1),
+        }
+    }
+}
+
+impl AmgConfig {
+    pub fn new(strength_threshold: f64) -> Self {
+        Self {
+            strength_threshold,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_max_levels(mut self, max_levels: usize) -> Self {
+        self.max_levels = max_levels;
+        self
+    }
+
+    pub fn with_coarse_size(mut self, coarse_size: usize) -> Self {
+        self.coarse_size = coarse_size;
+        self
+    }
+
+    pub fn with_multigrid(mut self, multigrid: MultigridConfig) -> Self {
+        self.multigrid = multigrid;
+        self
+    }
+}
+// ANCHOR_END: amg_config
+
+/// Strongly connected neighbours of each unknown, the unknown itself excluded.
+fn strong_connections(a: &CsrMatrix<f64>, diag: &[f64], threshold: f64) -> Vec<Vec<usize>> {
+    (0..a.nrows())
+        .map(|i| {
+            let row = a.row(i);
+            row.col_indices()
+                .iter()
+                .zip(row.values())
+                .filter(|&(&j, &a_ij)| {
+                    j != i && a_ij.abs() >= threshold * (diag[i] * diag[j]).abs().sqrt()
+                })
+                .map(|(&j, _)| j)
+                .collect()
+        })
+        .collect()
+}
+
+// ANCHOR: aggregate
+/// Groups the unknowns into aggregates and returns the aggregate of each unknown, with the
+/// number of aggregates.
+///
+/// The first pass builds aggregates from whole neighbourhoods of still free unknowns, the
+/// second one attaches the remaining unknowns to a neighbouring aggregate, and the third
+/// one groups what is left. Unknowns without strong connections, such as the rows of
+/// eliminated Dirichlet nodes, stay out of every aggregate: the smoother solves them.
+fn aggregate(strong: &[Vec<usize>]) -> (Vec<Option<usize>>, usize) {
+    let n = strong.len();
+    let mut aggregates: Vec<Option<usize>> = vec![None; n];
+    let mut count = 0;
+
+    for i in 0..n {
+        if strong[i].is_empty()
+            || aggregates[i].is_some()
+            || strong[i].iter().any(|&j| aggregates[j].is_some())
+        {
+            continue;
+        }
+        aggregates[i] = Some(count);
+        for &j in &strong[i] {
+            aggregates[j] = Some(count);
+        }
+        count += 1;
+    }
+
+    let first_pass = aggregates.clone();
+    for i in 0..n {
+        if aggregates[i].is_none() {
+            aggregates[i] = strong[i].iter().find_map(|&j| first_pass[j]);
+        }
+    }
+
+    for i in 0..n {
+        if strong[i].is_empty() || aggregates[i].is_some() {
+            continue;
+        }
+        aggregates[i] = Some(count);
+        for &j in &strong[i] {
+            if aggregates[j].is_none() {
+                aggregates[j] = Some(count);
+            }
+        }
+        count += 1;
+    }
+    (aggregates, count)
+}
+// ANCHOR_END: aggregate
+
+/// Smoothed prolongation `P = (I - ω D⁻¹ A) T`, where the tentative prolongation `T`
+/// interpolates the constant vector on each aggregate.
+///
+/// `ω = 4 / (3 ρ)`, where `ρ` is the Gershgorin bound of the spectral radius of `D⁻¹ A`.
+fn smoothed_prolongation(
+    a: &CsrMatrix<f64>,
+    diag: &[f64],
+    aggregates: &[Option<usize>],
+    count: usize,
+) -> CsrMatrix<f64> {
+    let mut sizes = vec![0usize; count];
+    for &k in aggregates.iter().flatten() {
+        sizes[k] += 1;
+    }
+    let mut tentative = CooMatrix::new(a.nrows(), count);
+    for (i, aggregate) in aggregates.iter().enumerate() {
+        if let Some(k) = *aggregate {
+            tentative.push(i, k, 1.0 / (sizes[k] as f64).sqrt());
+        }
+    }
+    let tentative = CsrMatrix::from(&tentative);
+
+    let rho = (0..a.nrows())
+        .map(|i| a.row(i).values().iter().map(|v| v.abs()).sum::<f64>() / diag[i].abs())
+        .fold(0.0, f64::max);
+    let omega = 4.0 / (3.0 * rho);
+    let mut smoothed = a * &tentative;
+    for (i, &d) in diag.iter().enumerate() {
+        for v in smoothed.row_mut(i).values_mut() {
+            *v *= omega / d;
+        }
+    }
+    &tentative - &smoothed
+}
+
+// ANCHOR: smoothed_aggregation
+/// Builds the smoothed aggregation hierarchy of a symmetric positive definite matrix.
+///
+/// Levels are added until the coarsest one has at most `coarse_size` unknowns, the hierarchy
+/// has `max_levels` levels or the aggregation stops reducing the size. Fails with
+/// `PreconditionerBreakdown` on a zero diagonal entry and with `SingularSystem` if the
+/// coarsest operator cannot be factored.
+pub fn smoothed_aggregation(
+    a: &CsrMatrix<f64>,
+    config: &AmgConfig,
+) -> Result<Multigrid, PoissonError> {
+    let mut operators = vec![a.clone()];
+    let mut prolongations = Vec::new();
+    while operators.len() < config.max_levels {
+        let a = operators.last().unwrap();
+        if a.nrows() <= config.coarse_size {
+            break;
+        }
+        let diag = diagonal(a)?;
+        let strong = strong_connections(a, &diag, config.strength_threshold);
+        let (aggregates, count) = aggregate(&strong);
+        if count == 0 || 2 * count > a.nrows() {
+            break;
+        }
+        let prolongation = smoothed_prolongation(a, &diag, &aggregates, count);
+        operators.push(&prolongation.transpose() * &(a * &prolongation));
+        prolongations.push(prolongation);
+    }
+    Multigrid::from_operators(operators, prolongations, config.multigrid)
+}
+// ANCHOR_END: smoothed_aggregation
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::ElementType;
+    use crate::generators::annulus;
+    use crate::gmsh::parse_msh;
+    use crate::iterative::{IterativeConfig, IterativeMethod, Preconditioner, solve_iterative};
+    use crate::mesh::Mesh2d;
+    use crate::solver::{apply_dirichlet_sparse, assemble_system_sparse};
+    use crate::{SolverType, solve_poisson_2d};
+    use nalgebra::{DVector, Point2};
+    use std::fmt::Write;
+
+    /// Linear congruential generator, to get reproducible pseudo-random numbers in [0, 1).
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    /// Unstructured triangulation of the annulus written in the MSH 2.2 format.
+    ///
+    /// The interior nodes of a polar grid are jittered, random elements are refined by
+    /// bisection and the nodes are renumbered in a scrambled order, like a mesh coming
+    /// out of Gmsh.
+    fn unstructured_msh(n: usize, seed: u64) -> String {
+        let mut rng = Lcg(seed);
+        let (mut mesh, _) = annulus(0.5, 1.0, n, 6 * n, ElementType::P1);
+        for _ in 0..3 {
+            let marked: Vec<usize> = (0..mesh.elements().len())
+                .filter(|_| rng.next() < 0.2)
+                .collect();
+            mesh = mesh.refine_marked(&marked).unwrap();
+        }
+        // Each interior vertex moves by at most a tenth of its shortest edge.
+        let mut shortest = vec![f64::INFINITY; mesh.vertices().len()];
+        for edge in mesh.edges() {
+            let [a, b] = [edge.indices[0], edge.indices[1]];
+            let length = (mesh.vertices()[a] - mesh.vertices()[b]).norm();
+            shortest[a] = shortest[a].min(length);
+            shortest[b] = shortest[b].min(length);
+        }
+        let boundary = mesh.boundary_nodes();
+        let vertices: Vec<Point2<f64>> = mesh
+            .vertices()
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                if boundary.binary_search(&i).is_ok() {
+                    *p
+                } else {
+                    let shift = Point2::new(rng.next(), rng.next()) - Point2::new(0.5, 0.5);
+                    p + shift * 0.2 * shortest[i]
+                }
+            })
+            .collect();
+
+        let num = vertices.len();
+        let stride = (1..num).rev().find(|s| gcd(*s, num) == 1).unwrap();
+        let tag = |i: usize| (i * stride) % num + 1;
+        let mut msh = String::from("$MeshFormat\n2.2 0 8\n$EndMeshFormat\n");
+        msh += "$PhysicalNames\n2\n1 1 \"wall\"\n2 2 \"domain\"\n$EndPhysicalNames\n";
+        writeln!(msh, "$Nodes\n{num}").unwrap();
+        for (i, p) in vertices.iter().enumerate() {
+            writeln!(msh, "{} {} {} 0", tag(i), p.x, p.y).unwrap();
+        }
+        msh += "$EndNodes\n";
+        let edges = mesh.boundary_edges();
+        writeln!(msh, "$Elements\n{}", edges.len() + mesh.elements().len()).unwrap();
+        let mut id = 1;
+        for edge in &edges {
+            let [a, b] = [tag(edge.indices[0]), tag(edge.indices[1])];
+            writeln!(msh, "{id} 1 2 1 1 {a} {b}").unwrap();
+            id += 1;
+        }
+        for element in mesh.elements() {
+            let [a, b, c] = [0, 1, 2].map(|k| tag(element.indices[k]));
+            writeln!(msh, "{id} 2 2 2 2 {a} {b} {c}").unwrap();
+            id += 1;
+        }
+        msh += "$EndElements\n";
+        msh
+    }
+
+    fn gcd(a: usize, b: usize) -> usize {
+        if b == 0 { a } else { gcd(b, a % b) }
+    }
+
+    fn unstructured_mesh(n: usize, seed: u64) -> Mesh2d {
+        let mesh = parse_msh(&unstructured_msh(n, seed)).unwrap();
+        mesh.validate().unwrap();
+        mesh
+    }
+
+    fn poisson_system(mesh: &Mesh2d) -> (CsrMatrix<f64>, DVector<f64>) {
+        let (mut a, mut b) = assemble_system_sparse(mesh, &|x, y| 1.0 + x * y);
+        let wall = mesh.boundary_group_nodes("wall").unwrap();
+        apply_dirichlet_sparse(&mut a, &mut b, &wall, mesh, |x, y| x - y);
+        (a, b)
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mesh = unstructured_mesh(4, 1);
+        let (a, _) = poisson_system(&mesh);
+        let diag = diagonal(&a).unwrap();
+        let strong = strong_connections(&a, &diag, 0.08);
+        let (aggregates, count) = aggregate(&strong);
+
+        // Only the eliminated Dirichlet rows are left out.
+        for (i, aggregate) in aggregates.iter().enumerate() {
+            assert_eq!(aggregate.is_none(), strong[i].is_empty());
+        }
+        let free = aggregates.iter().flatten().count();
+        assert!(count > 0 && 3 * count < free);
+
+        // Aggregate indices are contiguous.
+        let mut members = vec![Vec::new(); count];
+        for (i, aggregate) in aggregates.iter().enumerate() {
+            if let Some(k) = *aggregate {
+                members[k].push(i);
+            }
+        }
+        assert!(members.iter().all(|m| !m.is_empty()));
+    }
+
+    #[test]
+    fn test_amg_preconditioned_cg() {
+        let cg = IterativeConfig::new(IterativeMethod::ConjugateGradient);
+        let amg = cg
+            .clone()
+            .with_preconditioner(Preconditioner::SmoothedAggregation(AmgConfig::default()));
+        let jacobi = cg.with_preconditioner(Preconditioner::Jacobi);
+        let mut amg_iterations = Vec::new();
+        let mut jacobi_iterations = Vec::new();
+        for (n, seed) in [(4, 7), (8, 11), (16, 13)] {
+            let mesh = unstructured_mesh(n, seed);
+            let (a, b) = poisson_system(&mesh);
+            let hierarchy = smoothed_aggregation(&a, &AmgConfig::default()).unwrap();
+            assert!(hierarchy.num_levels() >= 2);
+
+            let (x, history) = solve_iterative(&a, &b, &amg).unwrap();
+            assert!((&b - &a * x).norm() <= 1e-10 * b.norm());
+            amg_iterations.push(history.iterations());
+            let (_, history) = solve_iterative(&a, &b, &jacobi).unwrap();
+            jacobi_iterations.push(history.iterations());
+        }
+        // Mildly growing with the mesh size, unlike the Jacobi preconditioner.
+        assert!(
+            amg_iterations.iter().all(|&n| n <= 40),
+            "iterations {amg_iterations:?}"
+        );
+        assert!(amg_iterations[2] <= 2 * amg_iterations[0]);
+        assert!(jacobi_iterations[2] > 3 * amg_iterations[2]);
+    }
+
+    #[test]
+    fn test_amg_solver_path() {
+        let mesh = unstructured_mesh(8, 3);
+        let wall = mesh.boundary_group_nodes("wall").unwrap();
+        let linear: fn(f64, f64) -> f64 = |x, y| 2.0 * x + y;
+        let zero: fn(f64, f64) -> f64 = |_, _| 0.0;
+        let config = IterativeConfig::new(IterativeMethod::ConjugateGradient)
+            .with_preconditioner(Preconditioner::SmoothedAggregation(AmgConfig::default()));
+        let u =
+            solve_poisson_2d(&mesh, &wall, &linear, &zero, SolverType::Iterative(config)).unwrap();
+        for (p, u_i) in mesh.vertices().iter().zip(u.iter()) {
+            assert!((u_i - linear(p.x, p.y)).abs() < 1e-8);
+        }
+
+        // The hierarchy also converges as a standalone solver.
+        let (a, b) = poisson_system(&mesh);
+        let config = AmgConfig::default()
+            .with_coarse_size(20)
+            .with_multigrid(MultigridConfig::default().with_max_cycles(200));
+        let (x, _) = smoothed_aggregation(&a, &config)
+            .unwrap()
+            .solve(&b)
+            .unwrap();
+        assert!((&b - &a * x).norm() <= 1e-10 * b.norm());
+    }
+}
+// ANCHOR_END: tests
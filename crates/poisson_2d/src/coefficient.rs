@@ -0,0 +1,160 @@
+//! Module that implements the diffusion coefficient of the operator `-div(κ ∇u)`.
+use crate::mesh::Mesh2d;
+use crate::solver::PoissonError;
+use nalgebra::Matrix2;
+use std::collections::BTreeMap;
+
+// ANCHOR: diffusion_enum
+/// Diffusion coefficient, either a scalar or a 2×2 tensor.
+pub enum Diffusion {
+    /// Constant isotropic coefficient
+    Constant(f64),
+    /// Isotropic coefficient κ(x, y)
+    Scalar(Box<dyn Fn(f64, f64) -> f64 + Send + Sync>),
+    /// Anisotropic coefficient K(x, y), expected to be symmetric positive definite
+    Tensor(Box<dyn Fn(f64, f64) -> Matrix2<f64> + Send + Sync>),
+    /// Piecewise-constant coefficient keyed by mesh region name
+    PerRegion(PerRegion),
+}
+
+/// Piecewise-constant coefficient keyed by mesh region name, converted into a `Diffusion`
+/// with `into`.
+///
+/// Elements outside of the listed regions use `default`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PerRegion {
+    pub values: BTreeMap<String, Matrix2<f64>>,
+    pub default: Matrix2<f64>,
+}
+// ANCHOR_END: diffusion_enum
+
+// ANCHOR: diffusion_impl
+impl Diffusion {
+    pub fn scalar<K>(kappa: K) -> Self
+    where
+        K: Fn(f64, f64) -> f64 + Send + Sync + 'static,
+    {
+        Diffusion::Scalar(Box::new(kappa))
+    }
+
+    pub fn tensor<K>(kappa: K) -> Self
+    where
+        K: Fn(f64, f64) -> Matrix2<f64> + Send + Sync + 'static,
+    {
+        Diffusion::Tensor(Box::new(kappa))
+    }
+
+    /// Computes the value of a `PerRegion` coefficient on every element of the mesh.
+    ///
+    /// Returns an empty vector for the other variants, which are evaluated pointwise.
+    /// Fails with `UnknownRegion` if a region is unknown to the mesh.
+    pub fn element_values(&self, mesh: &Mesh2d) -> Result<Vec<Matrix2<f64>>, PoissonError> {
+        match self {
+            Diffusion::PerRegion(PerRegion { values, default }) => {
+                let mut per_element = vec![*default; mesh.elements().len()];
+                for (name, value) in values {
+                    let elements = mesh
+                        .region(name)
+                        .ok_or_else(|| PoissonError::UnknownRegion(name.clone()))?;
+                    for &e in elements {
+                        per_element[e] = *value;
+                    }
+                }
+                Ok(per_element)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Evaluates the coefficient as a tensor in element `element` at point (x, y).
+    ///
+    /// `element_values` is the output of `Diffusion::element_values` for the same mesh.
+    pub fn eval(
+        &self,
+        element: usize,
+        x: f64,
+        y: f64,
+        element_values: &[Matrix2<f64>],
+    ) -> Matrix2<f64> {
+        match self {
+            Diffusion::Constant(kappa) => Matrix2::identity() * *kappa,
+            Diffusion::Scalar(kappa) => Matrix2::identity() * kappa(x, y),
+            Diffusion::Tensor(kappa) => kappa(x, y),
+            Diffusion::PerRegion(_) => element_values[element],
+        }
+    }
+}
+
+impl PerRegion {
+    /// Isotropic coefficient equal to `default` outside of any region.
+    pub fn new(default: f64) -> Self {
+        Self {
+            values: BTreeMap::new(),
+            default: Matrix2::identity() * default,
+        }
+    }
+
+    /// Sets an isotropic value on the region `name`.
+    pub fn with_region(self, name: &str, value: f64) -> Self {
+        self.with_region_tensor(name, Matrix2::identity() * value)
+    }
+
+    /// Sets a tensor value on the region `name`.
+    pub fn with_region_tensor(mut self, name: &str, value: Matrix2<f64>) -> Self {
+        self.values.insert(name.to_string(), value);
+        self
+    }
+}
+
+impl From<PerRegion> for Diffusion {
+    fn from(per_region: PerRegion) -> Self {
+        Diffusion::PerRegion(per_region)
+    }
+}
+// ANCHOR_END: diffusion_impl
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::ElementType;
+    use crate::generators::unit_square;
+
+    #[test]
+    fn test_eval() {
+        let identity = Matrix2::identity();
+        assert_eq!(
+            Diffusion::Constant(2.0).eval(0, 0.0, 0.0, &[]),
+            identity * 2.0
+        );
+        let scalar = Diffusion::scalar(|x, y| x + y);
+        assert_eq!(scalar.eval(0, 1.0, 2.0, &[]), identity * 3.0);
+        let tensor = Diffusion::tensor(|x, _| Matrix2::new(x, 0.5, 0.5, 1.0));
+        assert_eq!(tensor.eval(0, 4.0, 0.0, &[])[(0, 0)], 4.0);
+    }
+
+    #[test]
+    fn test_element_values() {
+        let (mut mesh, _) = unit_square(2, ElementType::Q1);
+        mesh.add_region("copper", vec![0, 3]);
+        let kappa: Diffusion = PerRegion::new(1.0).with_region("copper", 400.0).into();
+        let values = kappa.element_values(&mesh).unwrap();
+        assert_eq!(values.len(), 4);
+        assert_eq!(values[0][(1, 1)], 400.0);
+        assert_eq!(values[1][(0, 0)], 1.0);
+        assert_eq!(kappa.eval(3, 0.0, 0.0, &values)[(0, 0)], 400.0);
+        let constant = Diffusion::Constant(1.0).element_values(&mesh).unwrap();
+        assert!(constant.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_region() {
+        let (mesh, _) = unit_square(2, ElementType::Q1);
+        let kappa = Diffusion::from(PerRegion::new(1.0).with_region("air", 0.1));
+        assert!(matches!(
+            kappa.element_values(&mesh),
+            Err(PoissonError::UnknownRegion(name)) if name == "air"
+        ));
+    }
+}
+// ANCHOR_END: tests
@@ -0,0 +1,311 @@
+//! Module that measures the discretization error against an analytical solution.
+//!
+//! The L2 norm and the H1 seminorm of the error are integrated with quadrature rules more
+//! accurate than the assembly ones, so that the quadrature error does not pollute the
+//! observed convergence rates. A method-of-manufactured-solutions harness solves the
+//! Poisson problem on a sequence of refined meshes and reports these rates.
+use crate::direct::{Reordering, SparseCholesky};
+use crate::element::ElementType;
+use crate::mesh::Mesh2d;
+use crate::quadrature::QuadRule;
+use crate::solver::{PoissonError, apply_dirichlet_sparse, assemble_system_sparse, check_problem};
+use nalgebra::{DVector, Point2, Vector2};
+
+/// Quadrature rule used to integrate the error on an element type.
+fn error_rule(element_type: &ElementType) -> QuadRule {
+    let order = element_type.order();
+    let rule = if element_type.num_corners() == 3 {
+        QuadRule::triangle(2 * order + 4)
+    } else {
+        QuadRule::quadrilateral(order + 3)
+    };
+    rule.expect("error rules are always available")
+}
+
+/// Integrates `integrand(x, u_h, ∇u_h)` over the mesh, where `u_h` is the finite element
+/// field of the nodal values `u_h`.
+fn integrate_error<I>(mesh: &Mesh2d, u_h: &DVector<f64>, integrand: I) -> f64
+where
+    I: Fn(&Point2<f64>, f64, &Vector2<f64>) -> f64,
+{
+    assert_eq!(
+        u_h.len(),
+        mesh.vertices().len(),
+        "the field needs one value per mesh vertex"
+    );
+    let mut total = 0.0;
+    for (element, element_type) in mesh.elements().iter().zip(mesh.element_types()) {
+        let ref_element = element_type.reference_element();
+        let quad_rule = error_rule(element_type);
+        let nodes: Vec<Point2<f64>> = element
+            .indices
+            .iter()
+            .map(|&i| mesh.vertices()[i])
+            .collect();
+        for (p, w) in quad_rule.points.iter().zip(&quad_rule.weights) {
+            let jac = ref_element.jacobian(&nodes, p);
+            let jac_inv_t = jac
+                .try_inverse()
+                .expect("degenerate element, see Mesh2d::validate")
+                .transpose();
+            let mut x = Point2::origin();
+            let mut u = 0.0;
+            let mut grad = Vector2::zeros();
+            for (((val, grad_ref), vtx), &i) in ref_element
+                .shape_functions(p)
+                .iter()
+                .zip(ref_element.shape_gradients(p))
+                .zip(&nodes)
+                .zip(&element.indices)
+            {
+                x.coords += vtx.coords * *val;
+                u += val * u_h[i];
+                grad += jac_inv_t * grad_ref * u_h[i];
+            }
+            total += integrand(&x, u, &grad) * w * jac.determinant().abs();
+        }
+    }
+    total
+}
+
+// ANCHOR: error_norms
+/// L2 norm `‖u - u_h‖` of the error between the exact solution `u` and the finite element
+/// solution of nodal values `u_h`.
+///
+/// Panics if `u_h` does not have one value per mesh vertex.
+pub fn l2_error<U>(mesh: &Mesh2d, u_h: &DVector<f64>, exact: U) -> f64
+where
+    U: Fn(f64, f64) -> f64,
+{
+    integrate_error(mesh, u_h, |x, u, _| (u - exact(x.x, x.y)).powi(2)).sqrt()
+}
+
+/// H1 seminorm `‖∇u - ∇u_h‖` of the error, given the gradient of the exact solution.
+///
+/// Panics if `u_h` does not have one value per mesh vertex.
+pub fn h1_seminorm_error<G>(mesh: &Mesh2d, u_h: &DVector<f64>, exact_gradient: G) -> f64
+where
+    G: Fn(f64, f64) -> Vector2<f64>,
+{
+    integrate_error(mesh, u_h, |x, _, grad| {
+        (grad - exact_gradient(x.x, x.y)).norm_squared()
+    })
+    .sqrt()
+}
+// ANCHOR_END: error_norms
+
+/// Largest distance between two nodes of an element, over all the elements of the mesh.
+pub fn mesh_size(mesh: &Mesh2d) -> f64 {
+    let mut h: f64 = 0.0;
+    for element in mesh.elements() {
+        for (k, &i) in element.indices.iter().enumerate() {
+            for &j in &element.indices[k + 1..] {
+                h = h.max((mesh.vertices()[i] - mesh.vertices()[j]).norm());
+            }
+        }
+    }
+    h
+}
+
+// ANCHOR: convergence_study
+/// Errors measured on one mesh of a convergence study.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ConvergenceLevel {
+    /// Mesh size, see `mesh_size`
+    pub h: f64,
+    pub num_vertices: usize,
+    pub l2_error: f64,
+    pub h1_error: f64,
+}
+
+/// Errors on a sequence of refined meshes, from the coarsest to the finest.
+#[derive(Clone, Debug, Default)]
+pub struct ConvergenceStudy {
+    pub levels: Vec<ConvergenceLevel>,
+}
+
+impl ConvergenceStudy {
+    /// Observed orders `log(e_{k-1} / e_k) / log(h_{k-1} / h_k)` of the L2 error between
+    /// consecutive levels.
+    pub fn l2_rates(&self) -> Vec<f64> {
+        self.rates(|level| level.l2_error)
+    }
+
+    /// Observed orders of the H1 seminorm error between consecutive levels.
+    pub fn h1_rates(&self) -> Vec<f64> {
+        self.rates(|level| level.h1_error)
+    }
+
+    fn rates<E>(&self, error: E) -> Vec<f64>
+    where
+        E: Fn(&ConvergenceLevel) -> f64,
+    {
+        self.levels
+            .windows(2)
+            .map(|pair| (error(&pair[0]) / error(&pair[1])).ln() / (pair[0].h / pair[1].h).ln())
+            .collect()
+    }
+}
+
+/// Manufactured solution `u` of `-Δu = f`, with its gradient and the matching source.
+pub struct ManufacturedSolution<U, G, F> {
+    pub exact: U,
+    pub gradient: G,
+    pub source: F,
+}
+
+impl<U, G, F> ManufacturedSolution<U, G, F>
+where
+    U: Fn(f64, f64) -> f64,
+    G: Fn(f64, f64) -> Vector2<f64>,
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    pub fn new(exact: U, gradient: G, source: F) -> Self {
+        Self {
+            exact,
+            gradient,
+            source,
+        }
+    }
+
+    /// Solves the problem on one mesh, with `u` imposed on the boundary nodes.
+    pub fn solve(
+        &self,
+        mesh: &Mesh2d,
+        boundary_nodes: &[usize],
+    ) -> Result<DVector<f64>, PoissonError> {
+        let boundary_nodes = check_problem(mesh, boundary_nodes)?;
+        let (mut a, mut b) = assemble_system_sparse(mesh, &self.source);
+        apply_dirichlet_sparse(&mut a, &mut b, &boundary_nodes, mesh, &self.exact);
+        SparseCholesky::factor(&a, Reordering::ReverseCuthillMcKee)?.solve(&b)
+    }
+
+    /// Solves the problem on the meshes `mesh_fn(n)` for each resolution `n` and measures
+    /// the errors.
+    ///
+    /// `mesh_fn` returns the mesh and its Dirichlet boundary nodes, e.g. from one of the
+    /// generators.
+    pub fn study<M>(
+        &self,
+        resolutions: &[usize],
+        mut mesh_fn: M,
+    ) -> Result<ConvergenceStudy, PoissonError>
+    where
+        M: FnMut(usize) -> (Mesh2d, Vec<usize>),
+    {
+        let mut study = ConvergenceStudy::default();
+        for &n in resolutions {
+            let (mesh, boundary_nodes) = mesh_fn(n);
+            let u_h = self.solve(&mesh, &boundary_nodes)?;
+            study.levels.push(ConvergenceLevel {
+                h: mesh_size(&mesh),
+                num_vertices: mesh.vertices().len(),
+                l2_error: l2_error(&mesh, &u_h, &self.exact),
+                h1_error: h1_seminorm_error(&mesh, &u_h, &self.gradient),
+            });
+        }
+        Ok(study)
+    }
+}
+// ANCHOR_END: convergence_study
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::{annulus, unit_square};
+    use std::f64::consts::PI;
+
+    type Solution = ManufacturedSolution<
+        fn(f64, f64) -> f64,
+        fn(f64, f64) -> Vector2<f64>,
+        fn(f64, f64) -> f64,
+    >;
+
+    /// `u = sin(πx) sin(πy)` on the unit square.
+    fn sine_solution() -> Solution {
+        ManufacturedSolution::new(
+            |x, y| (PI * x).sin() * (PI * y).sin(),
+            |x, y| {
+                Vector2::new(
+                    PI * (PI * x).cos() * (PI * y).sin(),
+                    PI * (PI * x).sin() * (PI * y).cos(),
+                )
+            },
+            |x, y| 2.0 * PI * PI * (PI * x).sin() * (PI * y).sin(),
+        )
+    }
+
+    fn unit_square_study(element_type: ElementType, resolutions: &[usize]) -> ConvergenceStudy {
+        sine_solution()
+            .study(resolutions, |n| {
+                let (mesh, boundary) = unit_square(n, element_type.clone());
+                (mesh, boundary.all())
+            })
+            .unwrap()
+    }
+
+    fn assert_rates(rates: &[f64], expected: f64) {
+        for &rate in rates {
+            assert!(
+                (rate - expected).abs() < 0.15,
+                "observed rate {rate}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_norms() {
+        // The interpolant of a linear field is exact, both errors vanish.
+        let (mesh, _) = unit_square(3, ElementType::Q1);
+        let u_h = DVector::from_iterator(
+            mesh.vertices().len(),
+            mesh.vertices().iter().map(|p| 1.0 + 2.0 * p.x - p.y),
+        );
+        assert!(l2_error(&mesh, &u_h, |x, y| 1.0 + 2.0 * x - y) < 1e-13);
+        assert!(h1_seminorm_error(&mesh, &u_h, |_, _| Vector2::new(2.0, -1.0)) < 1e-13);
+
+        // ‖1‖ = 1 and ‖∇x‖ = 1 on the unit square.
+        let zero = DVector::zeros(mesh.vertices().len());
+        assert!((l2_error(&mesh, &zero, |_, _| 1.0) - 1.0).abs() < 1e-13);
+        assert!((h1_seminorm_error(&mesh, &zero, |_, _| Vector2::x()) - 1.0).abs() < 1e-13);
+    }
+
+    #[test]
+    fn test_linear_rates() {
+        for element_type in [ElementType::P1, ElementType::Q1] {
+            let study = unit_square_study(element_type, &[4, 8, 16, 32]);
+            assert_rates(&study.l2_rates(), 2.0);
+            assert_rates(&study.h1_rates(), 1.0);
+        }
+    }
+
+    #[test]
+    fn test_quadratic_rates() {
+        for element_type in [ElementType::P2, ElementType::Q2, ElementType::Q8] {
+            let study = unit_square_study(element_type, &[2, 4, 8, 16]);
+            assert_rates(&study.l2_rates()[1..], 3.0);
+            assert_rates(&study.h1_rates()[1..], 2.0);
+        }
+    }
+
+    #[test]
+    fn test_curved_domain_rates() {
+        // u = r² - 1/4 vanishes on the inner circle, the distorted Q1 cells keep the rates.
+        let solution = ManufacturedSolution::new(
+            |x: f64, y: f64| x * x + y * y - 0.25,
+            |x, y| Vector2::new(2.0 * x, 2.0 * y),
+            |_, _| -4.0,
+        );
+        let study = solution
+            .study(&[4, 8, 16], |n| {
+                let (mesh, boundary) = annulus(0.5, 1.0, n, 4 * n, ElementType::Q1);
+                (mesh, boundary.all())
+            })
+            .unwrap();
+        assert_rates(&study.l2_rates(), 2.0);
+        assert_rates(&study.h1_rates(), 1.0);
+        assert_eq!(study.levels[2].num_vertices, 17 * 64);
+    }
+}
+// ANCHOR_END: tests
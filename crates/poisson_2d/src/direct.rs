@@ -0,0 +1,335 @@
+//! Module that implements a sparse direct solver for symmetric positive definite systems.
+//!
+//! The unknowns are first renumbered to limit the fill-in of the Cholesky factor, then the
+//! permuted matrix is factored once with `nalgebra_sparse`. The factorization can be reused
+//! to solve for as many right-hand sides as needed.
+use crate::solver::PoissonError;
+use nalgebra::{DMatrix, DVector};
+use nalgebra_sparse::factorization::CscCholesky;
+use nalgebra_sparse::{CooMatrix, CscMatrix, CsrMatrix};
+use std::collections::VecDeque;
+
+// ANCHOR: reordering
+/// Renumbering of the unknowns applied before the factorization.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reordering {
+    /// Keep the mesh numbering
+    Natural,
+    /// Reverse Cuthill-McKee, which reduces the bandwidth and the profile of the matrix
+    ReverseCuthillMcKee,
+}
+// ANCHOR_END: reordering
+
+/// Neighbours of each row in the sparsity pattern, diagonal excluded.
+fn adjacency(a: &CsrMatrix<f64>) -> Vec<Vec<usize>> {
+    (0..a.nrows())
+        .map(|i| {
+            a.row(i)
+                .col_indices()
+                .iter()
+                .copied()
+                .filter(|&j| j != i)
+                .collect()
+        })
+        .collect()
+}
+
+/// Breadth-first level structure rooted at `root`, restricted to unvisited nodes.
+///
+/// `marks` is reused across calls: a node is reached by the current traversal when its
+/// mark equals `generation`, which must be new for every call.
+fn level_structure(
+    adj: &[Vec<usize>],
+    root: usize,
+    visited: &[bool],
+    marks: &mut [usize],
+    generation: usize,
+) -> Vec<Vec<usize>> {
+    marks[root] = generation;
+    let mut levels = vec![vec![root]];
+    loop {
+        let mut next = Vec::new();
+        for &i in levels.last().unwrap() {
+            for &j in &adj[i] {
+                if !visited[j] && marks[j] != generation {
+                    marks[j] = generation;
+                    next.push(j);
+                }
+            }
+        }
+        if next.is_empty() {
+            return levels;
+        }
+        levels.push(next);
+    }
+}
+
+// ANCHOR: reverse_cuthill_mckee
+/// Reverse Cuthill-McKee ordering of the sparsity pattern of `a`.
+///
+/// Returns the permutation `perm`, where `perm[k]` is the original index of the `k`-th
+/// unknown. Each connected component starts from a pseudo-peripheral node found with the
+/// George-Liu heuristic.
+pub fn reverse_cuthill_mckee(a: &CsrMatrix<f64>) -> Vec<usize> {
+    let n = a.nrows();
+    let adj = adjacency(a);
+    let degree = |i: usize| adj[i].len();
+    let mut visited = vec![false; n];
+    let mut perm = Vec::with_capacity(n);
+
+    // Isolated rows, e.g. those eliminated by Dirichlet conditions, are numbered directly.
+    for (i, visited_i) in visited.iter_mut().enumerate() {
+        if degree(i) == 0 {
+            *visited_i = true;
+            perm.push(i);
+        }
+    }
+
+    // Remaining nodes by increasing degree, the cursor skipping the numbered ones.
+    let mut by_degree: Vec<usize> = (0..n).filter(|&i| !visited[i]).collect();
+    by_degree.sort_by_key(|&i| degree(i));
+    let mut cursor = 0;
+    let mut marks = vec![0; n];
+    let mut generation = 0;
+
+    while perm.len() < n {
+        // Start from the unvisited node of lowest degree, then move to a node of the last
+        // level as long as this increases the eccentricity.
+        while visited[by_degree[cursor]] {
+            cursor += 1;
+        }
+        let mut root = by_degree[cursor];
+        generation += 1;
+        let mut levels = level_structure(&adj, root, &visited, &mut marks, generation);
+        loop {
+            let candidate = *levels
+                .last()
+                .unwrap()
+                .iter()
+                .min_by_key(|&&i| degree(i))
+                .unwrap();
+            generation += 1;
+            let candidate_levels =
+                level_structure(&adj, candidate, &visited, &mut marks, generation);
+            if candidate_levels.len() <= levels.len() {
+                break;
+            }
+            root = candidate;
+            levels = candidate_levels;
+        }
+
+        // Cuthill-McKee traversal, visiting neighbours by increasing degree.
+        let mut queue = VecDeque::from([root]);
+        visited[root] = true;
+        while let Some(i) = queue.pop_front() {
+            perm.push(i);
+            let mut neighbours: Vec<usize> =
+                adj[i].iter().copied().filter(|&j| !visited[j]).collect();
+            neighbours.sort_by_key(|&j| degree(j));
+            for j in neighbours {
+                visited[j] = true;
+                queue.push_back(j);
+            }
+        }
+    }
+    perm.reverse();
+    perm
+}
+// ANCHOR_END: reverse_cuthill_mckee
+
+/// Symmetric permutation `P A Pᵀ`, the `k`-th row of the result being row `perm[k]` of `a`.
+fn permute(a: &CsrMatrix<f64>, perm: &[usize]) -> CscMatrix<f64> {
+    let mut inverse = vec![0; perm.len()];
+    for (k, &i) in perm.iter().enumerate() {
+        inverse[i] = k;
+    }
+    let mut coo = CooMatrix::new(a.nrows(), a.ncols());
+    for (i, j, &v) in a.triplet_iter() {
+        coo.push(inverse[i], inverse[j], v);
+    }
+    CscMatrix::from(&coo)
+}
+
+// ANCHOR: sparse_cholesky
+/// Cholesky factorization `P A Pᵀ = L Lᵀ` of a sparse symmetric positive definite matrix.
+pub struct SparseCholesky {
+    perm: Vec<usize>,
+    factor: CscCholesky<f64>,
+}
+
+impl SparseCholesky {
+    /// Factors the matrix after renumbering its unknowns.
+    ///
+    /// Fails with `SingularSystem` if the matrix is not positive definite, including pivots
+    /// that vanish up to rounding errors.
+    pub fn factor(a: &CsrMatrix<f64>, reordering: Reordering) -> Result<Self, PoissonError> {
+        let n = a.nrows();
+        if a.ncols() != n {
+            return Err(PoissonError::DimensionMismatch {
+                expected: n,
+                found: a.ncols(),
+            });
+        }
+        let perm = match reordering {
+            Reordering::Natural => (0..n).collect(),
+            Reordering::ReverseCuthillMcKee => reverse_cuthill_mckee(a),
+        };
+        let factor =
+            CscCholesky::factor(&permute(a, &perm)).map_err(|_| PoissonError::SingularSystem)?;
+
+        let scale = a
+            .diagonal_as_csr()
+            .values()
+            .iter()
+            .fold(0.0, |m: f64, d| m.max(d.abs()));
+        let threshold = f64::EPSILON * n as f64 * scale;
+        let l = factor.l();
+        if (0..n).any(|k| l.col(k).values()[0].powi(2) <= threshold) {
+            return Err(PoissonError::SingularSystem);
+        }
+        Ok(Self { perm, factor })
+    }
+
+    /// Permutation applied to the unknowns, `perm[k]` being the original index of the
+    /// `k`-th unknown of the factored matrix.
+    pub fn permutation(&self) -> &[usize] {
+        &self.perm
+    }
+
+    /// Number of non-zero entries of the factor `L`, diagonal included.
+    pub fn factor_nnz(&self) -> usize {
+        self.factor.l().nnz()
+    }
+
+    /// Solves `A x = b` with the stored factorization.
+    pub fn solve(&self, b: &DVector<f64>) -> Result<DVector<f64>, PoissonError> {
+        let x = self.solve_many(&DMatrix::from_column_slice(b.len(), 1, b.as_slice()))?;
+        Ok(x.column(0).into_owned())
+    }
+
+    /// Solves `A X = B` for all the columns of `B` at once.
+    pub fn solve_many(&self, b: &DMatrix<f64>) -> Result<DMatrix<f64>, PoissonError> {
+        let n = self.perm.len();
+        if b.nrows() != n {
+            return Err(PoissonError::DimensionMismatch {
+                expected: n,
+                found: b.nrows(),
+            });
+        }
+        let mut permuted = b.select_rows(&self.perm);
+        self.factor.solve_mut(&mut permuted);
+        let mut x = DMatrix::zeros(n, b.ncols());
+        for (k, &i) in self.perm.iter().enumerate() {
+            x.set_row(i, &permuted.row(k));
+        }
+        Ok(x)
+    }
+}
+// ANCHOR_END: sparse_cholesky
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::ElementType;
+    use crate::generators::unit_square;
+    use crate::solver::{apply_dirichlet_sparse, assemble_system_sparse};
+
+    /// Largest distance `|i - j|` between the indices of a non-zero entry.
+    fn bandwidth(a: &CsrMatrix<f64>) -> usize {
+        a.triplet_iter()
+            .map(|(i, j, _)| i.abs_diff(j))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Poisson system on the unit square with its unknowns numbered in a scrambled order.
+    fn scrambled_system(n: usize) -> (CsrMatrix<f64>, DVector<f64>) {
+        let (mesh, boundary) = unit_square(n, ElementType::P1);
+        let (mut a, mut b) = assemble_system_sparse(&mesh, &|x, y| 1.0 + x * y);
+        apply_dirichlet_sparse(&mut a, &mut b, &boundary.all(), &mesh, |x, _| x);
+        let num = a.nrows();
+        let scramble: Vec<usize> = (0..num).map(|k| (k * 7919) % num).collect();
+        let a = CsrMatrix::from(&permute(&a, &scramble));
+        let b = b.select_rows(&scramble);
+        (a, b)
+    }
+
+    #[test]
+    fn test_reverse_cuthill_mckee() {
+        let (a, _) = scrambled_system(10);
+        let perm = reverse_cuthill_mckee(&a);
+
+        let mut sorted = perm.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..a.nrows()).collect::<Vec<_>>());
+
+        let reordered = CsrMatrix::from(&permute(&a, &perm));
+        assert!(bandwidth(&reordered) * 4 < bandwidth(&a));
+    }
+
+    #[test]
+    fn test_reverse_cuthill_mckee_isolated_rows() {
+        // The Dirichlet conditions drop the eliminated entries, which isolates the boundary rows.
+        let (a, _) = scrambled_system(10);
+        let n = a.nrows();
+        let isolated: Vec<usize> = (0..n).filter(|&i| a.row(i).nnz() == 1).collect();
+        assert_eq!(isolated.len(), 40);
+
+        let perm = reverse_cuthill_mckee(&a);
+        let mut sorted = perm.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..n).collect::<Vec<_>>());
+
+        // The isolated rows are numbered last, after the interior unknowns.
+        let mut last = perm[n - isolated.len()..].to_vec();
+        last.sort_unstable();
+        assert_eq!(last, isolated);
+
+        let reordered = CsrMatrix::from(&permute(&a, &perm));
+        assert!(bandwidth(&reordered) * 4 < bandwidth(&a));
+    }
+
+    #[test]
+    fn test_sparse_cholesky() {
+        let (a, b) = scrambled_system(10);
+        let dense = DMatrix::from(&a);
+        let expected = dense.clone().cholesky().unwrap().solve(&b);
+
+        let natural = SparseCholesky::factor(&a, Reordering::Natural).unwrap();
+        let rcm = SparseCholesky::factor(&a, Reordering::ReverseCuthillMcKee).unwrap();
+        assert!(rcm.factor_nnz() < natural.factor_nnz());
+
+        for chol in [natural, rcm] {
+            let x = chol.solve(&b).unwrap();
+            assert!((x - &expected).norm() < 1e-10 * expected.norm());
+
+            // The factorization is reused for several right-hand sides.
+            let rhs = DMatrix::from_fn(a.nrows(), 3, |i, j| ((i + 1) * (j + 2)) as f64);
+            let x = chol.solve_many(&rhs).unwrap();
+            assert!((&dense * x - &rhs).norm() < 1e-10 * rhs.norm());
+        }
+    }
+
+    #[test]
+    fn test_sparse_cholesky_errors() {
+        // Without boundary conditions the stiffness matrix is singular.
+        let (mesh, _) = unit_square(4, ElementType::Q1);
+        let (a, b) = assemble_system_sparse(&mesh, &|_, _| 1.0);
+        assert!(matches!(
+            SparseCholesky::factor(&a, Reordering::ReverseCuthillMcKee),
+            Err(PoissonError::SingularSystem)
+        ));
+
+        let identity = CsrMatrix::identity(3);
+        let chol = SparseCholesky::factor(&identity, Reordering::Natural).unwrap();
+        assert!(matches!(
+            chol.solve(&b),
+            Err(PoissonError::DimensionMismatch {
+                expected: 3,
+                found: 25
+            })
+        ));
+    }
+}
+// ANCHOR_END: tests
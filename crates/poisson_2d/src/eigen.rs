@@ -0,0 +1,393 @@
+//! Module that computes the lowest eigenpairs of the Laplace operator on `Mesh2d`.
+//!
+//! The discrete problem is the generalized symmetric eigenproblem `K x = λ M x`, where `K`
+//! is the stiffness matrix and `M` the mass matrix restricted to the free nodes. Small
+//! problems are solved densely with nalgebra, large ones with a shift-invert block Lanczos
+//! process built on the sparse Cholesky factorization of `K`.
+use crate::direct::{Reordering, SparseCholesky};
+use crate::mesh::Mesh2d;
+use crate::solver::{PoissonError, assemble_mass_sparse, assemble_system_sparse, check_problem};
+use nalgebra::{DMatrix, DVector, SymmetricEigen};
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+
+// ANCHOR: eigen_types
+/// Method used to solve the generalized eigenproblem.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EigenMethod {
+    /// Dense Cholesky reduction to a standard eigenproblem, for small meshes
+    Dense,
+    /// Shift-invert block Lanczos, stopping when the relative residuals of the requested
+    /// eigenpairs are below `tolerance` and failing after `max_iterations` blocks
+    Lanczos {
+        tolerance: f64,
+        max_iterations: usize,
+    },
+}
+
+/// Eigenvalues in increasing order, with the matching eigenvectors as columns.
+///
+/// The eigenvectors are normalized so that `xᵀ M x = 1`.
+#[derive(Clone, Debug)]
+pub struct EigenPairs {
+    pub values: Vec<f64>,
+    pub vectors: DMatrix<f64>,
+}
+// ANCHOR_END: eigen_types
+
+/// Sub-matrix of the rows and columns listed in `free`.
+fn restrict(a: &CsrMatrix<f64>, free: &[usize]) -> CsrMatrix<f64> {
+    let mut new_index = vec![None; a.nrows()];
+    for (k, &i) in free.iter().enumerate() {
+        new_index[i] = Some(k);
+    }
+    let mut coo = CooMatrix::new(free.len(), free.len());
+    for (i, j, &v) in a.triplet_iter() {
+        if let (Some(i), Some(j)) = (new_index[i], new_index[j]) {
+            coo.push(i, j, v);
+        }
+    }
+    CsrMatrix::from(&coo)
+}
+
+fn check_count(count: usize, available: usize) -> Result<(), PoissonError> {
+    if count > available {
+        return Err(PoissonError::TooManyEigenpairs {
+            requested: count,
+            available,
+        });
+    }
+    Ok(())
+}
+
+// ANCHOR: generalized_eigen_dense
+/// Lowest `count` eigenpairs of `K x = λ M x` for dense symmetric `K` and symmetric
+/// positive definite `M`.
+///
+/// With `M = L Lᵀ`, the problem becomes `L⁻¹ K L⁻ᵀ y = λ y` with `x = L⁻ᵀ y`.
+pub fn generalized_eigen_dense(
+    k: &DMatrix<f64>,
+    m: &DMatrix<f64>,
+    count: usize,
+) -> Result<EigenPairs, PoissonError> {
+    check_count(count, k.nrows())?;
+    let l = m
+        .clone()
+        .cholesky()
+        .ok_or(PoissonError::SingularSystem)?
+        .unpack();
+    let l_inv = l
+        .clone()
+        .try_inverse()
+        .ok_or(PoissonError::SingularSystem)?;
+    let mut c = &l_inv * k * l_inv.transpose();
+    // Remove the rounding asymmetry before the symmetric eigen solver.
+    c = (&c + c.transpose()) * 0.5;
+    let eigen = SymmetricEigen::new(c);
+
+    let mut order: Vec<usize> = (0..eigen.eigenvalues.len()).collect();
+    order.sort_by(|&a, &b| eigen.eigenvalues[a].total_cmp(&eigen.eigenvalues[b]));
+    order.truncate(count);
+    let values = order.iter().map(|&i| eigen.eigenvalues[i]).collect();
+    let y = eigen.eigenvectors.select_columns(&order);
+    let vectors = l_inv.transpose() * y;
+    Ok(EigenPairs { values, vectors })
+}
+// ANCHOR_END: generalized_eigen_dense
+
+/// Deterministic pseudo-random starting block, generic enough not to be orthogonal to
+/// any eigenvector.
+fn starting_block(n: usize, size: usize) -> DMatrix<f64> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    DMatrix::from_fn(n, size, |_, _| {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+    })
+}
+
+/// Orthonormalizes the columns of `w` in the `M` inner product against the columns of
+/// `basis` and each other, dropping the columns that are (numerically) in the span of the
+/// previous ones.
+fn m_orthonormalize(
+    w: &DMatrix<f64>,
+    basis: &[DVector<f64>],
+    m_basis: &[DVector<f64>],
+    m: &CsrMatrix<f64>,
+) -> (Vec<DVector<f64>>, Vec<DVector<f64>>) {
+    let mut new: Vec<DVector<f64>> = Vec::new();
+    let mut m_new: Vec<DVector<f64>> = Vec::new();
+    for column in w.column_iter() {
+        let mut v = column.into_owned();
+        let initial_norm = v.dot(&(m * &v)).sqrt();
+        // Two passes of Gram-Schmidt give orthogonality to working precision.
+        for _ in 0..2 {
+            for (q, mq) in basis.iter().zip(m_basis).chain(new.iter().zip(&m_new)) {
+                let coef = v.dot(mq);
+                v.axpy(-coef, q, 1.0);
+            }
+        }
+        let mv = m * &v;
+        let norm = v.dot(&mv).sqrt();
+        if norm > 1e-10 * initial_norm {
+            new.push(v / norm);
+            m_new.push(mv / norm);
+        }
+    }
+    (new, m_new)
+}
+
+// ANCHOR: generalized_eigen_lanczos
+/// Lowest `count` eigenpairs of `K x = λ M x` for sparse symmetric positive definite `K`
+/// and `M`.
+///
+/// Block Lanczos process on the operator `K⁻¹ M`, whose largest eigenvalues are the
+/// inverses of the smallest `λ`. The Krylov basis is kept `M`-orthonormal with full
+/// reorthogonalization, and the Ritz pairs come from the projection `Vᵀ K V`. Blocks are
+/// wider than `count`, so that multiple eigenvalues are found as well.
+///
+/// The basis grows by one block of `count + 2` vectors per iteration. Fails with
+/// `NotConverged` after `max_iterations` iterations, or earlier if the Krylov space is
+/// exhausted before the residuals reach `tolerance`.
+pub fn generalized_eigen_lanczos(
+    k: &CsrMatrix<f64>,
+    m: &CsrMatrix<f64>,
+    count: usize,
+    tolerance: f64,
+    max_iterations: usize,
+) -> Result<EigenPairs, PoissonError> {
+    let n = k.nrows();
+    check_count(count, n)?;
+    if count == 0 {
+        return Ok(EigenPairs {
+            values: Vec::new(),
+            vectors: DMatrix::zeros(n, 0),
+        });
+    }
+    let chol = SparseCholesky::factor(k, Reordering::ReverseCuthillMcKee)?;
+    let block_size = (count + 2).min(n);
+
+    let (mut basis, mut m_basis) = m_orthonormalize(&starting_block(n, block_size), &[], &[], m);
+    let mut block: Vec<DVector<f64>> = basis.clone();
+    let mut k_basis: Vec<DVector<f64>> = basis.iter().map(|v| k * v).collect();
+    let mut iterations = 0;
+    loop {
+        iterations += 1;
+
+        // Rayleigh-Ritz on the current basis, which is M-orthonormal.
+        let size = basis.len();
+        let projected = DMatrix::from_fn(size, size, |i, j| basis[i].dot(&k_basis[j]));
+        let eigen = SymmetricEigen::new((&projected + projected.transpose()) * 0.5);
+        let mut order: Vec<usize> = (0..size).collect();
+        order.sort_by(|&a, &b| eigen.eigenvalues[a].total_cmp(&eigen.eigenvalues[b]));
+
+        let mut max_residual = f64::INFINITY;
+        if size >= count {
+            let mut values = Vec::with_capacity(count);
+            let mut vectors = DMatrix::zeros(n, count);
+            max_residual = 0.0;
+            for (c, &i) in order.iter().take(count).enumerate() {
+                let lambda = eigen.eigenvalues[i];
+                let s = eigen.eigenvectors.column(i);
+                let mut x = DVector::zeros(n);
+                let mut kx = DVector::zeros(n);
+                let mut mx = DVector::zeros(n);
+                for (j, s_j) in s.iter().enumerate() {
+                    x.axpy(*s_j, &basis[j], 1.0);
+                    kx.axpy(*s_j, &k_basis[j], 1.0);
+                    mx.axpy(*s_j, &m_basis[j], 1.0);
+                }
+                let residual = (&kx - &mx * lambda).norm() / (lambda.abs() * mx.norm());
+                max_residual = max_residual.max(residual);
+                values.push(lambda);
+                vectors.set_column(c, &x);
+            }
+            if max_residual <= tolerance || size == n {
+                return Ok(EigenPairs { values, vectors });
+            }
+        }
+        // An empty block means the basis cannot grow any more.
+        if block.is_empty() || iterations >= max_iterations {
+            return Err(PoissonError::NotConverged {
+                iterations,
+                residual: max_residual,
+            });
+        }
+
+        // Next block of the Krylov sequence, w = K⁻¹ M q for the last block.
+        let mut rhs = DMatrix::zeros(n, block.len());
+        for (j, q) in block.iter().enumerate() {
+            rhs.set_column(j, &(m * q));
+        }
+        let w = chol.solve_many(&rhs)?;
+        let (new, m_new) = m_orthonormalize(&w, &basis, &m_basis, m);
+        k_basis.extend(new.iter().map(|v| k * v));
+        basis.extend(new.iter().cloned());
+        m_basis.extend(m_new);
+        block = new;
+    }
+}
+// ANCHOR_END: generalized_eigen_lanczos
+
+// ANCHOR: laplace_eigenpairs
+/// Lowest `count` eigenpairs of `-Δu = λu` with homogeneous Dirichlet conditions on the
+/// boundary nodes.
+///
+/// The eigenvectors have one entry per mesh vertex, zero on the boundary nodes.
+pub fn laplace_eigenpairs(
+    mesh: &Mesh2d,
+    boundary_nodes: &[usize],
+    count: usize,
+    method: EigenMethod,
+) -> Result<EigenPairs, PoissonError> {
+    let boundary_nodes = check_problem(mesh, boundary_nodes)?;
+    let num_vertices = mesh.vertices().len();
+    let free: Vec<usize> = (0..num_vertices)
+        .filter(|i| boundary_nodes.binary_search(i).is_err())
+        .collect();
+
+    let (k, _) = assemble_system_sparse(mesh, &|_, _| 0.0);
+    let m = assemble_mass_sparse(mesh);
+    let k = restrict(&k, &free);
+    let m = restrict(&m, &free);
+
+    let reduced = match method {
+        EigenMethod::Dense => {
+            generalized_eigen_dense(&DMatrix::from(&k), &DMatrix::from(&m), count)?
+        }
+        EigenMethod::Lanczos {
+            tolerance,
+            max_iterations,
+        } => generalized_eigen_lanczos(&k, &m, count, tolerance, max_iterations)?,
+    };
+
+    let mut vectors = DMatrix::zeros(num_vertices, count);
+    for (row, &i) in free.iter().enumerate() {
+        vectors.set_row(i, &reduced.vectors.row(row));
+    }
+    Ok(EigenPairs {
+        values: reduced.values,
+        vectors,
+    })
+}
+// ANCHOR_END: laplace_eigenpairs
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::ElementType;
+    use crate::generators::unit_square;
+    use crate::solver::assemble_mass_dense;
+    use std::f64::consts::PI;
+
+    /// Eigenvalues `π² (i² + j²)` of the unit square, in increasing order.
+    fn unit_square_eigenvalues(count: usize) -> Vec<f64> {
+        let mut values: Vec<f64> = (1..10)
+            .flat_map(|i| (1..10).map(move |j| PI * PI * (i * i + j * j) as f64))
+            .collect();
+        values.sort_by(f64::total_cmp);
+        values.truncate(count);
+        values
+    }
+
+    #[test]
+    fn test_unit_square_eigenvalues() {
+        let exact = unit_square_eigenvalues(6);
+        for element_type in [ElementType::P1, ElementType::Q1] {
+            let (mesh, boundary) = unit_square(16, element_type);
+            let dense = laplace_eigenpairs(&mesh, &boundary.all(), 6, EigenMethod::Dense).unwrap();
+            let lanczos = laplace_eigenpairs(
+                &mesh,
+                &boundary.all(),
+                6,
+                EigenMethod::Lanczos {
+                    tolerance: 1e-9,
+                    max_iterations: 100,
+                },
+            )
+            .unwrap();
+            for ((l_dense, l_lanczos), l_exact) in
+                dense.values.iter().zip(&lanczos.values).zip(&exact)
+            {
+                assert!((l_dense - l_lanczos).abs() < 1e-8 * l_exact);
+                // Conforming elements approximate the eigenvalues from above.
+                assert!(*l_dense > *l_exact && (l_dense - l_exact) / l_exact < 0.05);
+            }
+
+            // The eigenvectors are M-orthonormal, vanish on the boundary and satisfy the
+            // discrete eigenproblem.
+            let m = assemble_mass_dense(&mesh);
+            let gram = lanczos.vectors.transpose() * &m * &lanczos.vectors;
+            assert!((gram - DMatrix::identity(6, 6)).norm() < 1e-8);
+            for &i in &boundary.all() {
+                assert!(lanczos.vectors.row(i).iter().all(|&v| v == 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_eigenvalue_convergence() {
+        // The eigenvalue error of linear elements decreases as h².
+        let exact = unit_square_eigenvalues(3);
+        let errors: Vec<f64> = [8, 16, 32]
+            .iter()
+            .map(|&n| {
+                let (mesh, boundary) = unit_square(n, ElementType::Q1);
+                let pairs = laplace_eigenpairs(
+                    &mesh,
+                    &boundary.all(),
+                    3,
+                    EigenMethod::Lanczos {
+                        tolerance: 1e-10,
+                        max_iterations: 100,
+                    },
+                )
+                .unwrap();
+                (pairs.values[2] - exact[2]) / exact[2]
+            })
+            .collect();
+        for pair in errors.windows(2) {
+            let rate = (pair[0] / pair[1]).log2();
+            assert!((rate - 2.0).abs() < 0.2, "rate {rate} (errors {errors:?})");
+        }
+    }
+
+    #[test]
+    fn test_too_many_eigenpairs() {
+        let (mesh, boundary) = unit_square(2, ElementType::Q1);
+        // A 2 x 2 grid has a single interior node.
+        assert!(matches!(
+            laplace_eigenpairs(&mesh, &boundary.all(), 2, EigenMethod::Dense),
+            Err(PoissonError::TooManyEigenpairs {
+                requested: 2,
+                available: 1
+            })
+        ));
+        let pairs = laplace_eigenpairs(
+            &mesh,
+            &boundary.all(),
+            1,
+            EigenMethod::Lanczos {
+                tolerance: 1e-10,
+                max_iterations: 100,
+            },
+        )
+        .unwrap();
+        assert_eq!(pairs.values.len(), 1);
+    }
+
+    #[test]
+    fn test_lanczos_not_converged() {
+        let (mesh, boundary) = unit_square(16, ElementType::Q1);
+        let method = EigenMethod::Lanczos {
+            tolerance: 1e-12,
+            max_iterations: 1,
+        };
+        assert!(matches!(
+            laplace_eigenpairs(&mesh, &boundary.all(), 4, method),
+            Err(PoissonError::NotConverged { iterations: 1, .. })
+        ));
+    }
+}
+// ANCHOR_END: tests
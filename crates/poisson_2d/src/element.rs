@@ -1,13 +1,23 @@
-//! Module that implements two classical finite element types: tri3 and quad4.
+//! Module that implements the classical Lagrange finite element types: linear and
+//! quadratic triangles, bilinear, biquadratic and serendipity quadrangles.
+//!
+//! Quadratic elements follow the Gmsh node ordering: corner nodes first, then the
+//! midside nodes of the edges 0-1, 1-2, ... and finally the centre node (Q2 only).
 use nalgebra::{Matrix2, Point2, Vector2};
 
 // ANCHOR: elements
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum ElementType {
     /// 3-node triangle
     P1,
     /// 4-node quadrangle
     Q1,
+    /// 6-node quadratic triangle
+    P2,
+    /// 9-node biquadratic quadrangle
+    Q2,
+    /// 8-node serendipity quadrangle
+    Q8,
 }
 
 /// An element stores a vector containing its global indices.
@@ -17,6 +27,59 @@ pub struct Element {
 }
 // ANCHOR_END: elements
 
+// ANCHOR: element_type_impl
+impl ElementType {
+    /// Number of corner nodes, which come first in the element connectivity.
+    pub fn num_corners(&self) -> usize {
+        match self {
+            ElementType::P1 | ElementType::P2 => 3,
+            ElementType::Q1 | ElementType::Q2 | ElementType::Q8 => 4,
+        }
+    }
+
+    /// Polynomial order of the element.
+    pub fn order(&self) -> usize {
+        match self {
+            ElementType::P1 | ElementType::Q1 => 1,
+            ElementType::P2 | ElementType::Q2 | ElementType::Q8 => 2,
+        }
+    }
+
+    /// Local node indices of the element edges, ordered counter-clockwise.
+    ///
+    /// Each edge lists its two end nodes followed by its midside node, if any.
+    pub fn local_edges(&self) -> Vec<Vec<usize>> {
+        match self {
+            ElementType::P1 => vec![vec![0, 1], vec![1, 2], vec![2, 0]],
+            ElementType::Q1 => vec![vec![0, 1], vec![1, 2], vec![2, 3], vec![3, 0]],
+            ElementType::P2 => vec![vec![0, 1, 3], vec![1, 2, 4], vec![2, 0, 5]],
+            ElementType::Q2 | ElementType::Q8 => {
+                vec![vec![0, 1, 4], vec![1, 2, 5], vec![2, 3, 6], vec![3, 0, 7]]
+            }
+        }
+    }
+
+    /// Reference element used to integrate over the element.
+    pub fn reference_element(&self) -> ReferenceElement {
+        match self {
+            ElementType::P1 => ReferenceElement::Tri3,
+            ElementType::Q1 => ReferenceElement::Quad4,
+            ElementType::P2 => ReferenceElement::Tri6,
+            ElementType::Q2 => ReferenceElement::Quad9,
+            ElementType::Q8 => ReferenceElement::Quad8,
+        }
+    }
+
+    /// Reference element used to integrate along the element edges.
+    pub fn reference_edge(&self) -> ReferenceEdge {
+        match self {
+            ElementType::P1 | ElementType::Q1 => ReferenceEdge::Line2,
+            ElementType::P2 | ElementType::Q2 | ElementType::Q8 => ReferenceEdge::Line3,
+        }
+    }
+}
+// ANCHOR_END: element_type_impl
+
 // ANCHOR: reference_elements
 #[derive(Debug, Clone)]
 pub enum ReferenceElement {
@@ -24,6 +87,12 @@ pub enum ReferenceElement {
     Tri3,
     /// 4-node reference quadrangle
     Quad4,
+    /// 6-node reference triangle
+    Tri6,
+    /// 9-node reference quadrangle
+    Quad9,
+    /// 8-node serendipity reference quadrangle
+    Quad8,
 }
 
 impl ReferenceElement {
@@ -31,8 +100,69 @@ impl ReferenceElement {
         match self {
             ReferenceElement::Tri3 => 3,
             ReferenceElement::Quad4 => 4,
+            ReferenceElement::Tri6 => 6,
+            ReferenceElement::Quad9 => 9,
+            ReferenceElement::Quad8 => 8,
         }
     }
+
+    /// Local coordinates of the element nodes.
+    pub fn nodes(&self) -> Vec<Point2<f64>> {
+        let tri = [
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (0.0, 1.0),
+            (0.5, 0.0),
+            (0.5, 0.5),
+            (0.0, 0.5),
+        ];
+        let quad = QUAD9_NODES.map(|(i, j)| (i as f64, j as f64));
+        let coordinates: &[(f64, f64)] = match self {
+            ReferenceElement::Tri3 => &tri[..3],
+            ReferenceElement::Tri6 => &tri,
+            ReferenceElement::Quad4 => &quad[..4],
+            ReferenceElement::Quad8 => &quad[..8],
+            ReferenceElement::Quad9 => &quad,
+        };
+        coordinates
+            .iter()
+            .map(|&(x, y)| Point2::new(x, y))
+            .collect()
+    }
+}
+
+/// Local coordinates of the Quad9 nodes, each being -1, 0 or 1.
+const QUAD9_NODES: [(i32, i32); 9] = [
+    (-1, -1),
+    (1, -1),
+    (1, 1),
+    (-1, 1),
+    (0, -1),
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (0, 0),
+];
+
+/// 1D quadratic Lagrange polynomial on the nodes {-1, 0, 1} equal to one at `node`.
+fn lagrange_1d(node: i32, s: f64) -> f64 {
+    match node {
+        -1 => 0.5 * s * (s - 1.0),
+        0 => 1.0 - s * s,
+        _ => 0.5 * s * (s + 1.0),
+    }
+}
+
+fn lagrange_1d_derivative(node: i32, s: f64) -> f64 {
+    match node {
+        -1 => s - 0.5,
+        0 => -2.0 * s,
+        _ => s + 0.5,
+    }
+}
+
+fn lagrange_1d_second_derivative(node: i32) -> f64 {
+    if node == 0 { -2.0 } else { 1.0 }
 }
 // ANCHOR_END: reference_elements
 
@@ -54,6 +184,46 @@ impl ReferenceElement {
                 let n4 = 0.25 * (1.0 - xi) * (1.0 + eta);
                 vec![n1, n2, n3, n4]
             }
+            ReferenceElement::Tri6 => {
+                let l1 = local_coordinates.x;
+                let l2 = local_coordinates.y;
+                let l0 = 1.0 - l1 - l2;
+                vec![
+                    l0 * (2.0 * l0 - 1.0),
+                    l1 * (2.0 * l1 - 1.0),
+                    l2 * (2.0 * l2 - 1.0),
+                    4.0 * l0 * l1,
+                    4.0 * l1 * l2,
+                    4.0 * l2 * l0,
+                ]
+            }
+            ReferenceElement::Quad9 => {
+                let xi = local_coordinates.x;
+                let eta = local_coordinates.y;
+                QUAD9_NODES
+                    .iter()
+                    .map(|&(i, j)| lagrange_1d(i, xi) * lagrange_1d(j, eta))
+                    .collect()
+            }
+            ReferenceElement::Quad8 => {
+                let xi = local_coordinates.x;
+                let eta = local_coordinates.y;
+                QUAD9_NODES[..8]
+                    .iter()
+                    .map(|&(i, j)| {
+                        let (xi_i, eta_i) = (i as f64, j as f64);
+                        if i == 0 {
+                            0.5 * (1.0 - xi * xi) * (1.0 + eta * eta_i)
+                        } else if j == 0 {
+                            0.5 * (1.0 + xi * xi_i) * (1.0 - eta * eta)
+                        } else {
+                            0.25 * (1.0 + xi * xi_i)
+                                * (1.0 + eta * eta_i)
+                                * (xi * xi_i + eta * eta_i - 1.0)
+                        }
+                    })
+                    .collect()
+            }
         }
     }
 
@@ -61,7 +231,7 @@ impl ReferenceElement {
         match self {
             ReferenceElement::Tri3 => {
                 vec![
-                    Vector2::new(-1.0, 1.0),
+                    Vector2::new(-1.0, -1.0),
                     Vector2::new(1.0, 0.0),
                     Vector2::new(0.0, 1.0),
                 ]
@@ -84,9 +254,153 @@ impl ReferenceElement {
                     Vector2::new(dn4_dxi, dn4_deta),
                 ]
             }
+            ReferenceElement::Tri6 => {
+                let l1 = local_coordinates.x;
+                let l2 = local_coordinates.y;
+                let l0 = 1.0 - l1 - l2;
+                let dl0 = Vector2::new(-1.0, -1.0);
+                let dl1 = Vector2::new(1.0, 0.0);
+                let dl2 = Vector2::new(0.0, 1.0);
+                vec![
+                    dl0 * (4.0 * l0 - 1.0),
+                    dl1 * (4.0 * l1 - 1.0),
+                    dl2 * (4.0 * l2 - 1.0),
+                    (dl0 * l1 + dl1 * l0) * 4.0,
+                    (dl1 * l2 + dl2 * l1) * 4.0,
+                    (dl2 * l0 + dl0 * l2) * 4.0,
+                ]
+            }
+            ReferenceElement::Quad9 => {
+                let xi = local_coordinates.x;
+                let eta = local_coordinates.y;
+                QUAD9_NODES
+                    .iter()
+                    .map(|&(i, j)| {
+                        Vector2::new(
+                            lagrange_1d_derivative(i, xi) * lagrange_1d(j, eta),
+                            lagrange_1d(i, xi) * lagrange_1d_derivative(j, eta),
+                        )
+                    })
+                    .collect()
+            }
+            ReferenceElement::Quad8 => {
+                let xi = local_coordinates.x;
+                let eta = local_coordinates.y;
+                QUAD9_NODES[..8]
+                    .iter()
+                    .map(|&(i, j)| {
+                        let (xi_i, eta_i) = (i as f64, j as f64);
+                        if i == 0 {
+                            Vector2::new(-xi * (1.0 + eta * eta_i), 0.5 * eta_i * (1.0 - xi * xi))
+                        } else if j == 0 {
+                            Vector2::new(0.5 * xi_i * (1.0 - eta * eta), -eta * (1.0 + xi * xi_i))
+                        } else {
+                            Vector2::new(
+                                0.25 * xi_i * (1.0 + eta * eta_i) * (2.0 * xi * xi_i + eta * eta_i),
+                                0.25 * eta_i * (1.0 + xi * xi_i) * (xi * xi_i + 2.0 * eta * eta_i),
+                            )
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Second derivatives of the shape functions with respect to the local coordinates.
+    pub fn shape_hessians(&self, local_coordinates: &Point2<f64>) -> Vec<Matrix2<f64>> {
+        let xi = local_coordinates.x;
+        let eta = local_coordinates.y;
+        match self {
+            ReferenceElement::Tri3 => vec![Matrix2::zeros(); 3],
+            ReferenceElement::Quad4 => [0.25, -0.25, 0.25, -0.25]
+                .iter()
+                .map(|&d| Matrix2::new(0.0, d, d, 0.0))
+                .collect(),
+            ReferenceElement::Tri6 => {
+                let dl = [
+                    Vector2::new(-1.0, -1.0),
+                    Vector2::new(1.0, 0.0),
+                    Vector2::new(0.0, 1.0),
+                ];
+                // Hessian of the product of two barycentric coordinates.
+                let product =
+                    |a: usize, b: usize| dl[a] * dl[b].transpose() + dl[b] * dl[a].transpose();
+                vec![
+                    product(0, 0) * 2.0,
+                    product(1, 1) * 2.0,
+                    product(2, 2) * 2.0,
+                    product(0, 1) * 4.0,
+                    product(1, 2) * 4.0,
+                    product(2, 0) * 4.0,
+                ]
+            }
+            ReferenceElement::Quad9 => QUAD9_NODES
+                .iter()
+                .map(|&(i, j)| {
+                    let d_xi_d_eta = lagrange_1d_derivative(i, xi) * lagrange_1d_derivative(j, eta);
+                    Matrix2::new(
+                        lagrange_1d_second_derivative(i) * lagrange_1d(j, eta),
+                        d_xi_d_eta,
+                        d_xi_d_eta,
+                        lagrange_1d(i, xi) * lagrange_1d_second_derivative(j),
+                    )
+                })
+                .collect(),
+            ReferenceElement::Quad8 => QUAD9_NODES[..8]
+                .iter()
+                .map(|&(i, j)| {
+                    let (xi_i, eta_i) = (i as f64, j as f64);
+                    if i == 0 {
+                        let d_xi_d_eta = -xi * eta_i;
+                        Matrix2::new(-(1.0 + eta * eta_i), d_xi_d_eta, d_xi_d_eta, 0.0)
+                    } else if j == 0 {
+                        let d_xi_d_eta = -eta * xi_i;
+                        Matrix2::new(0.0, d_xi_d_eta, d_xi_d_eta, -(1.0 + xi * xi_i))
+                    } else {
+                        let d_xi_d_eta =
+                            0.25 * xi_i * eta_i * (2.0 * xi * xi_i + 2.0 * eta * eta_i + 1.0);
+                        Matrix2::new(
+                            0.5 * (1.0 + eta * eta_i),
+                            d_xi_d_eta,
+                            d_xi_d_eta,
+                            0.5 * (1.0 + xi * xi_i),
+                        )
+                    }
+                })
+                .collect(),
         }
     }
 
+    /// Second derivatives of the shape functions with respect to the physical coordinates,
+    /// `jac_inv_t` being the inverse transpose of the Jacobian at the same point.
+    ///
+    /// With `J` the Jacobian and `g` the physical gradient, `∇²φ = J⁻ᵀ (∇²_ξ φ - Σ_k g_k
+    /// ∇²_ξ x_k) J⁻¹`, where the second term accounts for the curvature of the mapping.
+    pub fn physical_hessians(
+        &self,
+        vertices_coordinates: &[Point2<f64>],
+        local_coordinates: &Point2<f64>,
+        jac_inv_t: &Matrix2<f64>,
+    ) -> Vec<Matrix2<f64>> {
+        let hessians = self.shape_hessians(local_coordinates);
+        let mut hessian_x = Matrix2::zeros();
+        let mut hessian_y = Matrix2::zeros();
+        for (hessian, vertex) in hessians.iter().zip(vertices_coordinates) {
+            hessian_x += hessian * vertex.x;
+            hessian_y += hessian * vertex.y;
+        }
+        hessians
+            .iter()
+            .zip(self.shape_gradients(local_coordinates))
+            .map(|(hessian, grad_ref)| {
+                let grad = jac_inv_t * grad_ref;
+                jac_inv_t
+                    * (hessian - hessian_x * grad.x - hessian_y * grad.y)
+                    * jac_inv_t.transpose()
+            })
+            .collect()
+    }
+
     pub fn jacobian(
         &self,
         vertices_coordinates: &[Point2<f64>],
@@ -103,7 +417,8 @@ impl ReferenceElement {
                 let dy_deta = v2.y - v0.y;
                 Matrix2::new(dx_dxi, dx_deta, dy_dxi, dy_deta)
             }
-            ReferenceElement::Quad4 => {
+            // Isoparametric mapping x = Σ N_i(ξ, η) x_i.
+            _ => {
                 let grads = self.shape_gradients(local_coordinates);
                 let mut jac = Matrix2::zeros();
                 for (grad, vertex) in grads.iter().zip(vertices_coordinates.iter()) {
@@ -119,6 +434,55 @@ impl ReferenceElement {
 }
 // ANCHOR_END: reference_elements_impl
 
+// ANCHOR: reference_edges
+/// Reference elements used to integrate along element edges.
+#[derive(Debug, Clone)]
+pub enum ReferenceEdge {
+    /// 2-node reference segment [-1, 1]
+    Line2,
+    /// 3-node reference segment [-1, 1], the midside node coming last
+    Line3,
+}
+
+impl ReferenceEdge {
+    pub fn num_nodes(&self) -> usize {
+        match self {
+            ReferenceEdge::Line2 => 2,
+            ReferenceEdge::Line3 => 3,
+        }
+    }
+
+    pub fn shape_functions(&self, s: f64) -> Vec<f64> {
+        match self {
+            ReferenceEdge::Line2 => vec![0.5 * (1.0 - s), 0.5 * (1.0 + s)],
+            ReferenceEdge::Line3 => vec![lagrange_1d(-1, s), lagrange_1d(1, s), lagrange_1d(0, s)],
+        }
+    }
+
+    pub fn shape_derivatives(&self, s: f64) -> Vec<f64> {
+        match self {
+            ReferenceEdge::Line2 => vec![-0.5, 0.5],
+            ReferenceEdge::Line3 => vec![
+                lagrange_1d_derivative(-1, s),
+                lagrange_1d_derivative(1, s),
+                lagrange_1d_derivative(0, s),
+            ],
+        }
+    }
+
+    /// Tangent vector dx/ds of the edge mapping at the local coordinate `s`.
+    ///
+    /// Its norm is the length scaling factor between the reference and physical edges.
+    pub fn tangent(&self, vertices_coordinates: &[Point2<f64>], s: f64) -> Vector2<f64> {
+        self.shape_derivatives(s)
+            .iter()
+            .zip(vertices_coordinates)
+            .map(|(dn, vertex)| vertex.coords * *dn)
+            .sum()
+    }
+}
+// ANCHOR_END: reference_edges
+
 // ANCHOR: tests
 #[cfg(test)]
 mod tests {
@@ -139,5 +503,132 @@ mod tests {
         assert_eq!(tri_shape_funcs.len(), 3);
         assert_eq!(quad_shape_funcs.len(), 4);
     }
+
+    #[test]
+    fn test_reference_edge() {
+        let line2 = ReferenceEdge::Line2;
+        assert_eq!(line2.num_nodes(), 2);
+        assert_eq!(line2.shape_functions(-1.0), vec![1.0, 0.0]);
+        assert_eq!(line2.shape_functions(0.0), vec![0.5, 0.5]);
+
+        let nodes = [Point2::new(1.0, 1.0), Point2::new(1.0, 3.0)];
+        let tangent = line2.tangent(&nodes, 0.3);
+        assert_eq!(tangent, Vector2::new(0.0, 1.0));
+
+        let line3 = ReferenceEdge::Line3;
+        assert_eq!(line3.shape_functions(1.0), vec![0.0, 1.0, 0.0]);
+        assert_eq!(line3.shape_functions(0.0), vec![0.0, 0.0, 1.0]);
+        // A curved edge along the parabola y = x², parametrized by x = s.
+        let nodes = [
+            Point2::new(-1.0, 1.0),
+            Point2::new(1.0, 1.0),
+            Point2::new(0.0, 0.0),
+        ];
+        let tangent = line3.tangent(&nodes, 0.5);
+        assert!((tangent - Vector2::new(1.0, 1.0)).norm() < 1e-14);
+    }
+
+    #[test]
+    fn test_quadratic_reference_elements() {
+        let elements = [
+            ReferenceElement::Tri3,
+            ReferenceElement::Quad4,
+            ReferenceElement::Tri6,
+            ReferenceElement::Quad9,
+            ReferenceElement::Quad8,
+        ];
+        for element in elements {
+            let nodes = element.nodes();
+            assert_eq!(nodes.len(), element.num_nodes());
+
+            // Kronecker property at the nodes.
+            for (i, node) in nodes.iter().enumerate() {
+                for (j, n) in element.shape_functions(node).iter().enumerate() {
+                    let expected = if i == j { 1.0 } else { 0.0 };
+                    assert!((n - expected).abs() < 1e-14, "{element:?}");
+                }
+            }
+
+            // Partition of unity and gradients checked against finite differences.
+            let p = Point2::new(0.2, 0.3);
+            let eps = 1e-6;
+            let n = element.shape_functions(&p);
+            assert!((n.iter().sum::<f64>() - 1.0).abs() < 1e-14);
+            let grads = element.shape_gradients(&p);
+            let nx = element.shape_functions(&Point2::new(p.x + eps, p.y));
+            let ny = element.shape_functions(&Point2::new(p.x, p.y + eps));
+            for i in 0..n.len() {
+                let fd = Vector2::new((nx[i] - n[i]) / eps, (ny[i] - n[i]) / eps);
+                assert!((fd - grads[i]).norm() < 1e-5, "{element:?} node {i}");
+            }
+            let hessians = element.shape_hessians(&p);
+            let gx = element.shape_gradients(&Point2::new(p.x + eps, p.y));
+            let gy = element.shape_gradients(&Point2::new(p.x, p.y + eps));
+            for i in 0..n.len() {
+                let fd =
+                    Matrix2::from_columns(&[(gx[i] - grads[i]) / eps, (gy[i] - grads[i]) / eps]);
+                assert!((fd - hessians[i]).norm() < 1e-5, "{element:?} node {i}");
+            }
+
+            // The isoparametric map of the reference nodes is the identity.
+            let jac = element.jacobian(&nodes, &p);
+            assert!((jac - Matrix2::identity()).norm() < 1e-14);
+        }
+    }
+
+    #[test]
+    fn test_element_type_edges() {
+        for element_type in [ElementType::P2, ElementType::Q2, ElementType::Q8] {
+            let reference = element_type.reference_element();
+            let nodes = reference.nodes();
+            for edge in element_type.local_edges() {
+                // The midside node lies halfway between the end nodes.
+                let mid = nodes[edge[0]].coords.lerp(&nodes[edge[1]].coords, 0.5);
+                assert_eq!(nodes[edge[2]].coords, mid);
+            }
+            assert_eq!(element_type.order(), 2);
+        }
+    }
+
+    #[test]
+    fn test_physical_hessians() {
+        let quad4 = ReferenceElement::Quad4;
+        let hessians_at = |nodes: &[Point2<f64>], p: &Point2<f64>| {
+            let jac_inv_t = quad4.jacobian(nodes, p).try_inverse().unwrap().transpose();
+            quad4.physical_hessians(nodes, p, &jac_inv_t)
+        };
+        let p = Point2::new(0.3, -0.6);
+
+        // On the rectangle [0, 2] x [0, 1], the nodal values of xy give ∂²u/∂x∂y = 1.
+        let rectangle = [
+            Point2::new(0.0, 0.0),
+            Point2::new(2.0, 0.0),
+            Point2::new(2.0, 1.0),
+            Point2::new(0.0, 1.0),
+        ];
+        let hessian: Matrix2<f64> = hessians_at(&rectangle, &p)
+            .iter()
+            .zip(&rectangle)
+            .map(|(h, v)| h * (v.x * v.y))
+            .sum();
+        assert!((hessian - Matrix2::new(0.0, 1.0, 1.0, 0.0)).norm() < 1e-14);
+
+        // The coordinates are linear, even on a distorted quadrangle.
+        let distorted = [
+            Point2::new(0.0, 0.0),
+            Point2::new(2.0, 0.2),
+            Point2::new(1.7, 1.5),
+            Point2::new(-0.2, 0.9),
+        ];
+        let hessians = hessians_at(&distorted, &p);
+        for coordinate in [|v: &Point2<f64>| v.x, |v: &Point2<f64>| v.y] {
+            let hessian: Matrix2<f64> = hessians
+                .iter()
+                .zip(&distorted)
+                .map(|(h, v)| h * coordinate(v))
+                .sum();
+            assert!(hessian.norm() < 1e-13);
+        }
+    }
 }
 // ANCHOR_END: tests
@@ -0,0 +1,243 @@
+//! Module that implements the bilinear forms assembled by the solver.
+//!
+//! A bilinear form adds the contribution of one quadrature point to the element matrix
+//! (and possibly to the element load vector). The assembly routines in `solver.rs` are
+//! generic over the `BilinearForm` trait, so new operators only need to implement it.
+use crate::coefficient::Diffusion;
+use crate::mesh::Mesh2d;
+use crate::solver::PoissonError;
+use nalgebra::{Matrix2, Vector2};
+
+// ANCHOR: quad_point
+/// Quantities evaluated at a quadrature point of an element.
+pub struct QuadPoint<'a> {
+    /// Index of the element in the mesh
+    pub element: usize,
+    /// Physical coordinates of the quadrature point
+    pub x: f64,
+    pub y: f64,
+    /// Quadrature weight times the absolute value of the Jacobian determinant
+    pub weight: f64,
+    /// Shape function values
+    pub shape_values: &'a [f64],
+    /// Shape function gradients in physical coordinates
+    pub shape_gradients: &'a [Vector2<f64>],
+    /// Shape function second derivatives in physical coordinates
+    pub shape_hessians: &'a [Matrix2<f64>],
+    /// Element diameter (largest distance between two nodes)
+    pub h: f64,
+}
+// ANCHOR_END: quad_point
+
+// ANCHOR: bilinear_form
+/// Forms and their mesh data are shared between the threads of the parallel assembly,
+/// hence `Sync`.
+pub trait BilinearForm: Sync {
+    /// Data computed once per assembly from the mesh, e.g. region-wise coefficients.
+    type MeshData: Sync;
+
+    /// Fails with `UnknownRegion` if the form refers to a region the mesh does not have.
+    fn mesh_data(&self, mesh: &Mesh2d) -> Result<Self::MeshData, PoissonError>;
+
+    /// Adds the contribution of the quadrature point to the element matrix `ke`, where
+    /// `ke[i][j]` couples test function `i` with trial function `j`.
+    fn add_element_matrix(&self, data: &Self::MeshData, qp: &QuadPoint, ke: &mut [Vec<f64>]);
+
+    /// Adds the contribution of the quadrature point to the element load vector, `f_val`
+    /// being the source term at the point. Defaults to the Galerkin load `f φ_i`.
+    fn add_element_load(&self, _data: &Self::MeshData, qp: &QuadPoint, f_val: f64, fe: &mut [f64]) {
+        for (fe_i, phi_i) in fe.iter_mut().zip(qp.shape_values) {
+            *fe_i += phi_i * f_val * qp.weight;
+        }
+    }
+}
+// ANCHOR_END: bilinear_form
+
+// ANCHOR: diffusion_form
+/// The diffusion form `∫ ∇v · κ ∇u`.
+impl BilinearForm for Diffusion {
+    type MeshData = Vec<Matrix2<f64>>;
+
+    fn mesh_data(&self, mesh: &Mesh2d) -> Result<Self::MeshData, PoissonError> {
+        self.element_values(mesh)
+    }
+
+    fn add_element_matrix(&self, data: &Self::MeshData, qp: &QuadPoint, ke: &mut [Vec<f64>]) {
+        let kappa = self.eval(qp.element, qp.x, qp.y, data);
+        for (ke_i, grad_i) in ke.iter_mut().zip(qp.shape_gradients) {
+            for (ke_ij, grad_j) in ke_i.iter_mut().zip(qp.shape_gradients) {
+                *ke_ij += grad_i.dot(&(kappa * grad_j)) * qp.weight;
+            }
+        }
+    }
+}
+// ANCHOR_END: diffusion_form
+
+// ANCHOR: cdr_struct
+/// Stabilization applied to the convective term.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stabilization {
+    /// Plain Galerkin discretization
+    None,
+    /// Streamline-upwind Petrov-Galerkin
+    Supg,
+}
+
+/// The convection-diffusion-reaction operator `-div(κ ∇u) + b·∇u + c u`.
+pub struct ConvectionDiffusionReaction {
+    pub diffusion: Diffusion,
+    pub velocity: Box<dyn Fn(f64, f64) -> Vector2<f64> + Send + Sync>,
+    pub reaction: Box<dyn Fn(f64, f64) -> f64 + Send + Sync>,
+    pub stabilization: Stabilization,
+}
+// ANCHOR_END: cdr_struct
+
+// ANCHOR: cdr_impl
+impl ConvectionDiffusionReaction {
+    /// Pure diffusion operator, to be completed with `with_velocity` and `with_reaction`.
+    pub fn new(diffusion: Diffusion) -> Self {
+        Self {
+            diffusion,
+            velocity: Box::new(|_, _| Vector2::zeros()),
+            reaction: Box::new(|_, _| 0.0),
+            stabilization: Stabilization::None,
+        }
+    }
+
+    pub fn with_velocity<B>(mut self, velocity: B) -> Self
+    where
+        B: Fn(f64, f64) -> Vector2<f64> + Send + Sync + 'static,
+    {
+        self.velocity = Box::new(velocity);
+        self
+    }
+
+    pub fn with_reaction<C>(mut self, reaction: C) -> Self
+    where
+        C: Fn(f64, f64) -> f64 + Send + Sync + 'static,
+    {
+        self.reaction = Box::new(reaction);
+        self
+    }
+
+    pub fn with_stabilization(mut self, stabilization: Stabilization) -> Self {
+        self.stabilization = stabilization;
+        self
+    }
+
+    /// SUPG parameter `τ = h / (2|b|) (coth(Pe) - 1/Pe)` with the element Péclet number
+    /// `Pe = |b| h / (2κ)`, κ being the mean eigenvalue of the diffusion tensor.
+    fn supg_tau(&self, kappa: &Matrix2<f64>, b: &Vector2<f64>, h: f64) -> f64 {
+        let b_norm = b.norm();
+        if self.stabilization == Stabilization::None || b_norm == 0.0 {
+            return 0.0;
+        }
+        let kappa = 0.5 * kappa.trace();
+        if kappa <= 0.0 {
+            return h / (2.0 * b_norm);
+        }
+        let peclet = b_norm * h / (2.0 * kappa);
+        // coth(Pe) - 1/Pe ~ Pe/3 for small Pe, which avoids the cancellation.
+        let xi = if peclet < 1e-3 {
+            peclet / 3.0
+        } else {
+            1.0 / peclet.tanh() - 1.0 / peclet
+        };
+        h / (2.0 * b_norm) * xi
+    }
+}
+
+impl BilinearForm for ConvectionDiffusionReaction {
+    type MeshData = Vec<Matrix2<f64>>;
+
+    fn mesh_data(&self, mesh: &Mesh2d) -> Result<Self::MeshData, PoissonError> {
+        self.diffusion.element_values(mesh)
+    }
+
+    fn add_element_matrix(&self, data: &Self::MeshData, qp: &QuadPoint, ke: &mut [Vec<f64>]) {
+        self.diffusion.add_element_matrix(data, qp, ke);
+
+        let kappa = self.diffusion.eval(qp.element, qp.x, qp.y, data);
+        let b = (self.velocity)(qp.x, qp.y);
+        let c = (self.reaction)(qp.x, qp.y);
+        let tau = self.supg_tau(&kappa, &b, qp.h);
+        let phi = qp.shape_values;
+        let b_grad: Vec<f64> = qp.shape_gradients.iter().map(|g| b.dot(g)).collect();
+        // Second-order part -div(κ ∇φ_j) of the residual, the gradient of κ being neglected,
+        // which is exact for piecewise-constant coefficients.
+        let diffusion: Vec<f64> = qp
+            .shape_hessians
+            .iter()
+            .map(|hessian| -kappa.component_mul(hessian).sum())
+            .collect();
+
+        for i in 0..ke.len() {
+            for j in 0..ke.len() {
+                let galerkin = phi[i] * b_grad[j] + c * phi[i] * phi[j];
+                let supg = tau * b_grad[i] * (b_grad[j] + diffusion[j] + c * phi[j]);
+                ke[i][j] += (galerkin + supg) * qp.weight;
+            }
+        }
+    }
+
+    fn add_element_load(&self, data: &Self::MeshData, qp: &QuadPoint, f_val: f64, fe: &mut [f64]) {
+        let kappa = self.diffusion.eval(qp.element, qp.x, qp.y, data);
+        let b = (self.velocity)(qp.x, qp.y);
+        let tau = self.supg_tau(&kappa, &b, qp.h);
+        for ((fe_i, phi_i), grad_i) in fe.iter_mut().zip(qp.shape_values).zip(qp.shape_gradients) {
+            *fe_i += (phi_i + tau * b.dot(grad_i)) * f_val * qp.weight;
+        }
+    }
+}
+// ANCHOR_END: cdr_impl
+
+// ANCHOR: mass_form
+/// The mass form `∫ v u`, used by time-dependent and eigenvalue problems.
+pub struct Mass;
+
+impl BilinearForm for Mass {
+    type MeshData = ();
+
+    fn mesh_data(&self, _mesh: &Mesh2d) -> Result<Self::MeshData, PoissonError> {
+        Ok(())
+    }
+
+    fn add_element_matrix(&self, _data: &Self::MeshData, qp: &QuadPoint, ke: &mut [Vec<f64>]) {
+        let phi = qp.shape_values;
+        for (ke_i, phi_i) in ke.iter_mut().zip(phi) {
+            for (ke_ij, phi_j) in ke_i.iter_mut().zip(phi) {
+                *ke_ij += phi_i * phi_j * qp.weight;
+            }
+        }
+    }
+}
+// ANCHOR_END: mass_form
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supg_tau() {
+        let kappa = Matrix2::identity() * 1e-2;
+        let b = Vector2::new(3.0, 4.0);
+        let form = ConvectionDiffusionReaction::new(Diffusion::Constant(1e-2))
+            .with_stabilization(Stabilization::Supg);
+
+        // Advection-dominated limit: τ -> h / (2|b|).
+        let tau = form.supg_tau(&kappa, &b, 0.1);
+        assert!((tau - 0.1 / 10.0 * (1.0 / 25.0f64.tanh() - 1.0 / 25.0)).abs() < 1e-14);
+        assert!(tau < 0.01 && tau > 0.009);
+
+        // Diffusion-dominated limit: τ -> h² / (12κ).
+        let tau = form.supg_tau(&kappa, &b, 1e-6);
+        assert!((tau - 1e-12 / 12e-2).abs() < 1e-20);
+
+        let no_velocity = form.supg_tau(&kappa, &Vector2::zeros(), 0.1);
+        assert_eq!(no_velocity, 0.0);
+        let galerkin = ConvectionDiffusionReaction::new(Diffusion::Constant(1e-2));
+        assert_eq!(galerkin.supg_tau(&kappa, &b, 0.1), 0.0);
+    }
+}
+// ANCHOR_END: tests
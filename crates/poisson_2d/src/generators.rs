@@ -0,0 +1,577 @@
+//! Module that implements structured mesh generators for simple 2D domains.
+//!
+//! Every generator returns the mesh together with its boundary nodes, tagged by side,
+//! so that the result can be passed directly to the solver. The same sides are also
+//! registered as named boundary groups on the mesh (`"left"`, `"inner"`, ...).
+//!
+//! Quadratic meshes are obtained by adding midside (and centre) nodes to the linear
+//! mesh, placed on the exact geometry so that curved boundaries are resolved.
+use crate::element::{Element, ElementType};
+use crate::mesh::{Edge, Mesh2d};
+use nalgebra::{Point2, Vector2};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::f64::consts::PI;
+
+// ANCHOR: boundary_nodes
+/// Sides of a generated domain on which boundary nodes are collected.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum BoundarySide {
+    Left,
+    Right,
+    Bottom,
+    Top,
+    /// Inner boundary (re-entrant edges of the L-shape, inner circle of the annulus)
+    Inner,
+    /// Outer boundary (outer circle of the annulus)
+    Outer,
+}
+
+impl BoundarySide {
+    /// Name of the boundary group created on the generated mesh for this side.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BoundarySide::Left => "left",
+            BoundarySide::Right => "right",
+            BoundarySide::Bottom => "bottom",
+            BoundarySide::Top => "top",
+            BoundarySide::Inner => "inner",
+            BoundarySide::Outer => "outer",
+        }
+    }
+}
+
+/// Boundary nodes of a generated mesh, grouped by side.
+///
+/// Corner nodes belong to every side they touch.
+#[derive(Clone, Debug, Default)]
+pub struct BoundaryNodes {
+    sides: BTreeMap<BoundarySide, Vec<usize>>,
+}
+
+impl BoundaryNodes {
+    fn insert(&mut self, side: BoundarySide, node: usize) {
+        let nodes = self.sides.entry(side).or_default();
+        if !nodes.contains(&node) {
+            nodes.push(node);
+        }
+    }
+
+    /// Returns the nodes lying on the given side (empty if the domain has no such side).
+    pub fn side(&self, side: BoundarySide) -> &[usize] {
+        self.sides.get(&side).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    /// Returns the sides present in the domain together with their nodes.
+    pub fn sides(&self) -> impl Iterator<Item = (BoundarySide, &[usize])> {
+        self.sides
+            .iter()
+            .map(|(side, nodes)| (*side, nodes.as_slice()))
+    }
+
+    /// Returns all boundary nodes, sorted and without duplicates.
+    pub fn all(&self) -> Vec<usize> {
+        let mut all: Vec<usize> = self.sides.values().flatten().copied().collect();
+        all.sort_unstable();
+        all.dedup();
+        all
+    }
+}
+// ANCHOR_END: boundary_nodes
+
+/// Builds the mesh and registers one boundary group per side, named after `BoundarySide::name`.
+///
+/// `elements` only hold the corner nodes. For quadratic element types, the missing nodes
+/// are placed by `midpoint` from the corners of the edge or cell they belong to.
+fn build_mesh<M>(
+    vertices: Vec<Point2<f64>>,
+    elements: Vec<Element>,
+    element_type: ElementType,
+    boundary: BoundaryNodes,
+    midpoint: M,
+) -> (Mesh2d, BoundaryNodes)
+where
+    M: Fn(&[Point2<f64>]) -> Point2<f64>,
+{
+    let (vertices, elements, boundary) = if element_type.order() == 2 {
+        add_quadratic_nodes(vertices, elements, &element_type, boundary, midpoint)
+    } else {
+        (vertices, elements, boundary)
+    };
+    let mut mesh = Mesh2d::new(vertices, elements, element_type);
+    let boundary_edges = mesh.boundary_edges();
+    for (side, nodes) in boundary.sides() {
+        let nodes: HashSet<usize> = nodes.iter().copied().collect();
+        let edges: Vec<Edge> = boundary_edges
+            .iter()
+            .filter(|edge| edge.indices.iter().all(|i| nodes.contains(i)))
+            .cloned()
+            .collect();
+        mesh.add_boundary_group(side.name(), edges);
+    }
+    (mesh, boundary)
+}
+
+/// Completes linear elements with the midside nodes of their edges, and the centre node
+/// of Q2 elements.
+///
+/// Midside nodes of boundary edges are added to the sides of both end nodes.
+fn add_quadratic_nodes<M>(
+    mut vertices: Vec<Point2<f64>>,
+    elements: Vec<Element>,
+    element_type: &ElementType,
+    mut boundary: BoundaryNodes,
+    midpoint: M,
+) -> (Vec<Point2<f64>>, Vec<Element>, BoundaryNodes)
+where
+    M: Fn(&[Point2<f64>]) -> Point2<f64>,
+{
+    let corners = element_type.num_corners();
+    let mut midside: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+    let mut quadratic = Vec::with_capacity(elements.len());
+    for element in elements {
+        let mut indices = element.indices.clone();
+        for k in 0..corners {
+            let (a, b) = (element.indices[k], element.indices[(k + 1) % corners]);
+            let (id, count) = midside.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                vertices.push(midpoint(&[vertices[a], vertices[b]]));
+                (vertices.len() - 1, 0)
+            });
+            *count += 1;
+            indices.push(*id);
+        }
+        if *element_type == ElementType::Q2 {
+            let cell: Vec<Point2<f64>> = element.indices.iter().map(|&i| vertices[i]).collect();
+            vertices.push(midpoint(&cell));
+            indices.push(vertices.len() - 1);
+        }
+        quadratic.push(Element { indices });
+    }
+
+    // Edges shared by a single element lie on the boundary.
+    let sides: Vec<(BoundarySide, HashSet<usize>)> = boundary
+        .sides()
+        .map(|(side, nodes)| (side, nodes.iter().copied().collect()))
+        .collect();
+    let mut boundary_midside: Vec<_> = midside
+        .into_iter()
+        .filter(|(_, (_, count))| *count == 1)
+        .collect();
+    boundary_midside.sort_unstable();
+    for ((a, b), (id, _)) in boundary_midside {
+        for (side, nodes) in &sides {
+            if nodes.contains(&a) && nodes.contains(&b) {
+                boundary.insert(*side, id);
+            }
+        }
+    }
+    (vertices, quadratic, boundary)
+}
+
+/// Midpoint used on straight-sided domains: the barycenter of the points.
+fn barycenter(points: &[Point2<f64>]) -> Point2<f64> {
+    Point2::from(points.iter().map(|p| p.coords).sum::<Vector2<f64>>() / points.len() as f64)
+}
+
+/// Splits the structured cell `[n0, n1, n2, n3]` (counter-clockwise) into elements.
+///
+/// Quadrangles keep the cell as a single element, triangles cut it along the `n0`-`n2`
+/// diagonal. Only the corner nodes are set.
+fn push_cell(elements: &mut Vec<Element>, cell: [usize; 4], element_type: &ElementType) {
+    let [n0, n1, n2, n3] = cell;
+    match element_type {
+        ElementType::P1 | ElementType::P2 => {
+            elements.push(Element {
+                indices: vec![n0, n1, n2],
+            });
+            elements.push(Element {
+                indices: vec![n0, n2, n3],
+            });
+        }
+        ElementType::Q1 | ElementType::Q2 | ElementType::Q8 => elements.push(Element {
+            indices: vec![n0, n1, n2, n3],
+        }),
+    }
+}
+
+// ANCHOR: rectangle
+/// Generates a uniform `nx` × `ny` grid of the rectangle spanned by `lower` and `upper`.
+///
+/// Each grid cell becomes one quadrangle or two triangles. The returned boundary nodes
+/// are tagged `Left`, `Right`, `Bottom` and `Top`.
+pub fn rectangle(
+    lower: Point2<f64>,
+    upper: Point2<f64>,
+    nx: usize,
+    ny: usize,
+    element_type: ElementType,
+) -> (Mesh2d, BoundaryNodes) {
+    assert!(
+        nx > 0 && ny > 0,
+        "the grid needs at least one cell per direction"
+    );
+    let hx = (upper.x - lower.x) / nx as f64;
+    let hy = (upper.y - lower.y) / ny as f64;
+    let node = |i: usize, j: usize| j * (nx + 1) + i;
+
+    let mut vertices = Vec::with_capacity((nx + 1) * (ny + 1));
+    let mut boundary = BoundaryNodes::default();
+    for j in 0..=ny {
+        for i in 0..=nx {
+            vertices.push(Point2::new(
+                lower.x + i as f64 * hx,
+                lower.y + j as f64 * hy,
+            ));
+            if i == 0 {
+                boundary.insert(BoundarySide::Left, node(i, j));
+            }
+            if i == nx {
+                boundary.insert(BoundarySide::Right, node(i, j));
+            }
+            if j == 0 {
+                boundary.insert(BoundarySide::Bottom, node(i, j));
+            }
+            if j == ny {
+                boundary.insert(BoundarySide::Top, node(i, j));
+            }
+        }
+    }
+
+    let mut elements = Vec::new();
+    for j in 0..ny {
+        for i in 0..nx {
+            let cell = [
+                node(i, j),
+                node(i + 1, j),
+                node(i + 1, j + 1),
+                node(i, j + 1),
+            ];
+            push_cell(&mut elements, cell, &element_type);
+        }
+    }
+
+    build_mesh(vertices, elements, element_type, boundary, barycenter)
+}
+
+/// Generates a uniform `n` × `n` grid of the unit square `[0, 1] x [0, 1]`.
+pub fn unit_square(n: usize, element_type: ElementType) -> (Mesh2d, BoundaryNodes) {
+    rectangle(
+        Point2::new(0.0, 0.0),
+        Point2::new(1.0, 1.0),
+        n,
+        n,
+        element_type,
+    )
+}
+// ANCHOR_END: rectangle
+
+// ANCHOR: l_shape
+/// Generates the L-shaped domain `(-1, 1)^2 \ [0, 1) x (-1, 0]`.
+///
+/// Each unit length is split into `n` cells. The outer sides are tagged `Left`, `Right`,
+/// `Bottom` and `Top`, the two re-entrant sides meeting at the origin are tagged `Inner`.
+pub fn l_shape(n: usize, element_type: ElementType) -> (Mesh2d, BoundaryNodes) {
+    assert!(n > 0, "the grid needs at least one cell per unit length");
+    let m = 2 * n;
+    let h = 1.0 / n as f64;
+    // A grid point is kept unless it lies strictly inside the removed quadrant.
+    let removed = |i: usize, j: usize| i > n && j < n;
+
+    let mut vertices = Vec::new();
+    let mut boundary = BoundaryNodes::default();
+    let mut ids = vec![None; (m + 1) * (m + 1)];
+    for j in 0..=m {
+        for i in 0..=m {
+            if removed(i, j) {
+                continue;
+            }
+            let id = vertices.len();
+            ids[j * (m + 1) + i] = Some(id);
+            vertices.push(Point2::new(-1.0 + i as f64 * h, -1.0 + j as f64 * h));
+            if i == 0 {
+                boundary.insert(BoundarySide::Left, id);
+            }
+            if i == m {
+                boundary.insert(BoundarySide::Right, id);
+            }
+            if j == 0 {
+                boundary.insert(BoundarySide::Bottom, id);
+            }
+            if j == m {
+                boundary.insert(BoundarySide::Top, id);
+            }
+            if (i == n && j <= n) || (j == n && i >= n) {
+                boundary.insert(BoundarySide::Inner, id);
+            }
+        }
+    }
+
+    let node = |i: usize, j: usize| ids[j * (m + 1) + i].expect("node of a kept cell");
+    let mut elements = Vec::new();
+    for j in 0..m {
+        for i in 0..m {
+            // Skip the cells of the removed lower-right quadrant.
+            if i >= n && j < n {
+                continue;
+            }
+            let cell = [
+                node(i, j),
+                node(i + 1, j),
+                node(i + 1, j + 1),
+                node(i, j + 1),
+            ];
+            push_cell(&mut elements, cell, &element_type);
+        }
+    }
+
+    build_mesh(vertices, elements, element_type, boundary, barycenter)
+}
+// ANCHOR_END: l_shape
+
+// ANCHOR: annulus
+/// Generates the annulus `inner_radius < r < outer_radius` centered at the origin.
+///
+/// A `nr` × `ntheta` grid in polar coordinates is mapped onto the physical domain, the
+/// angular direction being periodic. The inner and outer circles are tagged `Inner` and
+/// `Outer`.
+pub fn annulus(
+    inner_radius: f64,
+    outer_radius: f64,
+    nr: usize,
+    ntheta: usize,
+    element_type: ElementType,
+) -> (Mesh2d, BoundaryNodes) {
+    assert!(
+        0.0 < inner_radius && inner_radius < outer_radius,
+        "radii must satisfy 0 < inner_radius < outer_radius"
+    );
+    assert!(
+        nr > 0 && ntheta > 2,
+        "the grid needs at least one radial and three angular cells"
+    );
+    let dr = (outer_radius - inner_radius) / nr as f64;
+    let dtheta = 2.0 * PI / ntheta as f64;
+    let node = |i: usize, j: usize| (j % ntheta) * (nr + 1) + i;
+
+    let mut vertices = Vec::with_capacity((nr + 1) * ntheta);
+    let mut boundary = BoundaryNodes::default();
+    for j in 0..ntheta {
+        let theta = j as f64 * dtheta;
+        for i in 0..=nr {
+            let r = inner_radius + i as f64 * dr;
+            vertices.push(Point2::new(r * theta.cos(), r * theta.sin()));
+            if i == 0 {
+                boundary.insert(BoundarySide::Inner, node(i, j));
+            }
+            if i == nr {
+                boundary.insert(BoundarySide::Outer, node(i, j));
+            }
+        }
+    }
+
+    // The polar map preserves orientation, so cells counter-clockwise in (r, theta)
+    // remain counter-clockwise in (x, y).
+    let mut elements = Vec::new();
+    for j in 0..ntheta {
+        for i in 0..nr {
+            let cell = [
+                node(i, j),
+                node(i + 1, j),
+                node(i + 1, j + 1),
+                node(i, j + 1),
+            ];
+            push_cell(&mut elements, cell, &element_type);
+        }
+    }
+
+    // Quadratic nodes are placed at the mean radius and angle of the corners, hence on
+    // the circles for the boundary edges.
+    let polar_midpoint = |points: &[Point2<f64>]| {
+        let r = points.iter().map(|p| p.coords.norm()).sum::<f64>() / points.len() as f64;
+        let direction: Vector2<f64> = points.iter().map(|p| p.coords.normalize()).sum();
+        Point2::from(direction.normalize() * r)
+    };
+    build_mesh(vertices, elements, element_type, boundary, polar_midpoint)
+}
+// ANCHOR_END: annulus
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quadrature::QuadRule;
+    use crate::{SolverType, solve_poisson_2d};
+
+    /// Signed area of an element computed with the shoelace formula.
+    fn signed_area(mesh: &Mesh2d, element: &Element) -> f64 {
+        let pts: Vec<Point2<f64>> = element
+            .indices
+            .iter()
+            .map(|&i| mesh.vertices()[i])
+            .collect();
+        let mut area = 0.0;
+        for k in 0..pts.len() {
+            let (p, q) = (pts[k], pts[(k + 1) % pts.len()]);
+            area += p.x * q.y - q.x * p.y;
+        }
+        0.5 * area
+    }
+
+    fn total_area(mesh: &Mesh2d) -> f64 {
+        mesh.elements().iter().map(|e| signed_area(mesh, e)).sum()
+    }
+
+    /// Area of the mesh computed with the isoparametric mapping of the elements.
+    fn mapped_area(mesh: &Mesh2d) -> f64 {
+        let mut area = 0.0;
+        for (element, element_type) in mesh.elements().iter().zip(mesh.element_types()) {
+            let ref_element = element_type.reference_element();
+            let rule = QuadRule::for_element(element_type);
+            let nodes: Vec<Point2<f64>> = element
+                .indices
+                .iter()
+                .map(|&i| mesh.vertices()[i])
+                .collect();
+            for (p, w) in rule.points.iter().zip(&rule.weights) {
+                let det = ref_element.jacobian(&nodes, p).determinant();
+                assert!(det > 0.0);
+                area += w * det;
+            }
+        }
+        area
+    }
+
+    #[test]
+    fn test_rectangle() {
+        let lower = Point2::new(0.0, -1.0);
+        let upper = Point2::new(2.0, 1.0);
+        let (quads, boundary) = rectangle(lower, upper, 4, 3, ElementType::Q1);
+        assert_eq!(quads.vertices().len(), 20);
+        assert_eq!(quads.elements().len(), 12);
+        assert_eq!(boundary.side(BoundarySide::Left).len(), 4);
+        assert_eq!(boundary.side(BoundarySide::Bottom).len(), 5);
+        assert_eq!(boundary.all().len(), 14);
+        assert!(boundary.side(BoundarySide::Inner).is_empty());
+
+        let (tris, _) = rectangle(lower, upper, 4, 3, ElementType::P1);
+        assert_eq!(tris.elements().len(), 24);
+
+        for mesh in [&quads, &tris] {
+            assert!(mesh.elements().iter().all(|e| signed_area(mesh, e) > 0.0));
+            assert!((total_area(mesh) - 4.0).abs() < 1e-12);
+        }
+        for &i in boundary.side(BoundarySide::Right) {
+            assert_eq!(quads.vertices()[i].x, 2.0);
+        }
+    }
+
+    #[test]
+    fn test_l_shape() {
+        let (mesh, boundary) = l_shape(2, ElementType::Q1);
+        assert_eq!(mesh.elements().len(), 12);
+        assert_eq!(mesh.boundary_nodes(), boundary.all());
+        assert_eq!(mesh.boundary_group("inner").map(|g| g.len()), Some(4));
+        assert_eq!(mesh.boundary_group_nodes("inner").unwrap().len(), 5);
+        assert_eq!(mesh.vertices().len(), 21);
+        assert!((total_area(&mesh) - 3.0).abs() < 1e-12);
+
+        let inner = boundary.side(BoundarySide::Inner);
+        assert_eq!(inner.len(), 5);
+        for &i in inner {
+            let v = mesh.vertices()[i];
+            assert!((v.x == 0.0 && v.y <= 0.0) || (v.y == 0.0 && v.x >= 0.0));
+        }
+        assert_eq!(boundary.all().len(), 16);
+
+        let (tris, _) = l_shape(2, ElementType::P1);
+        assert!(tris.elements().iter().all(|e| signed_area(&tris, e) > 0.0));
+    }
+
+    #[test]
+    fn test_annulus() {
+        let (mesh, boundary) = annulus(0.5, 1.0, 4, 64, ElementType::Q1);
+        assert_eq!(mesh.vertices().len(), 5 * 64);
+        assert_eq!(mesh.elements().len(), 4 * 64);
+        assert_eq!(boundary.side(BoundarySide::Inner).len(), 64);
+        assert_eq!(boundary.side(BoundarySide::Outer).len(), 64);
+        assert_eq!(mesh.boundary_group("outer").map(|g| g.len()), Some(64));
+        assert_eq!(mesh.boundary_edges().len(), 128);
+        for &i in boundary.side(BoundarySide::Outer) {
+            assert!((mesh.vertices()[i].coords.norm() - 1.0).abs() < 1e-12);
+        }
+        assert!(mesh.elements().iter().all(|e| signed_area(&mesh, e) > 0.0));
+
+        // The polygonal approximation converges to the exact area.
+        let exact = PI * (1.0 - 0.25);
+        assert!((total_area(&mesh) - exact).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_quadratic_meshes() {
+        let lower = Point2::new(0.0, 0.0);
+        let upper = Point2::new(2.0, 1.0);
+        for (element_type, vertices, nodes_per_element) in [
+            (ElementType::P2, 15, 6),
+            (ElementType::Q2, 15, 9),
+            (ElementType::Q8, 13, 8),
+        ] {
+            let (mesh, boundary) = rectangle(lower, upper, 2, 1, element_type);
+            assert_eq!(mesh.vertices().len(), vertices);
+            assert!(
+                mesh.elements()
+                    .iter()
+                    .all(|e| e.indices.len() == nodes_per_element)
+            );
+            assert_eq!(boundary.side(BoundarySide::Bottom).len(), 5);
+            assert_eq!(boundary.side(BoundarySide::Left).len(), 3);
+            assert_eq!(mesh.boundary_nodes(), boundary.all());
+            assert_eq!(mesh.boundary_group("top").map(|g| g.len()), Some(2));
+            assert!((mapped_area(&mesh) - 2.0).abs() < 1e-12);
+        }
+
+        let (mesh, boundary) = l_shape(2, ElementType::Q8);
+        assert_eq!(boundary.side(BoundarySide::Inner).len(), 9);
+        assert_eq!(mesh.boundary_nodes(), boundary.all());
+    }
+
+    #[test]
+    fn test_quadratic_annulus() {
+        // Midside nodes lie on the circles, which makes the area much more accurate.
+        let exact = PI * (1.0 - 0.25);
+        let (linear, _) = annulus(0.5, 1.0, 2, 16, ElementType::Q1);
+        let (quadratic, boundary) = annulus(0.5, 1.0, 2, 16, ElementType::Q2);
+        assert_eq!(boundary.side(BoundarySide::Outer).len(), 32);
+        for &i in boundary.side(BoundarySide::Outer) {
+            assert!((quadratic.vertices()[i].coords.norm() - 1.0).abs() < 1e-12);
+        }
+        let linear_error = (mapped_area(&linear) - exact).abs();
+        let quadratic_error = (mapped_area(&quadratic) - exact).abs();
+        assert!(quadratic_error < 1e-3);
+        assert!(quadratic_error < linear_error / 50.0);
+    }
+
+    #[test]
+    fn test_linear_solution_is_reproduced() {
+        let exact: fn(f64, f64) -> f64 = |x, y| 1.0 + 2.0 * x - 3.0 * y;
+        let source: fn(f64, f64) -> f64 = |_, _| 0.0;
+        for element_type in [ElementType::P1, ElementType::Q1] {
+            let (mesh, boundary) = l_shape(3, element_type);
+            let u = solve_poisson_2d(&mesh, &boundary.all(), &exact, &source, SolverType::Dense)
+                .unwrap();
+            for (v, u_h) in mesh.vertices().iter().zip(u.iter()) {
+                assert!((u_h - exact(v.x, v.y)).abs() < 1e-10);
+            }
+        }
+
+        // Quadratic elements also reproduce harmonic quadratic polynomials.
+        let exact: fn(f64, f64) -> f64 = |x, y| 1.0 + x * x - y * y + 3.0 * x * y;
+        for element_type in [ElementType::P2, ElementType::Q2, ElementType::Q8] {
+            let (mesh, boundary) = l_shape(2, element_type);
+            let u = solve_poisson_2d(&mesh, &boundary.all(), &exact, &source, SolverType::Dense)
+                .unwrap();
+            for (v, u_h) in mesh.vertices().iter().zip(u.iter()) {
+                assert!((u_h - exact(v.x, v.y)).abs() < 1e-10);
+            }
+        }
+    }
+}
+// ANCHOR_END: tests
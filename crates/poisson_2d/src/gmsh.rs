@@ -0,0 +1,725 @@
+//! Module that reads meshes written by Gmsh in the MSH 2.2 and 4.1 ASCII formats.
+//!
+//! Surface elements (3 and 6-node triangles, 4, 8 and 9-node quadrangles) become the
+//! elements of the mesh.
+//! Physical groups are carried over by name: physical curves become boundary groups and
+//! physical surfaces become regions. Groups without a `$PhysicalNames` entry are named
+//! after their numeric tag.
+use crate::element::{Element, ElementType};
+use crate::mesh::{Edge, Mesh2d};
+use nalgebra::Point2;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+
+// ANCHOR: error_enum
+#[derive(Debug, thiserror::Error)]
+pub enum GmshError {
+    /// The file could not be read
+    #[error("cannot read mesh file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The `$MeshFormat` header announces a version or encoding we cannot read
+    #[error("unsupported MSH format: {0} (expected ASCII 2.2 or 4.1)")]
+    UnsupportedFormat(String),
+    /// A required section is missing
+    #[error("missing section ${0}")]
+    MissingSection(&'static str),
+    /// The content of a section is malformed
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+    /// The file contains an element type that has no counterpart in `ElementType`
+    #[error("line {line}: unsupported Gmsh element type {type_id} ({description})")]
+    UnsupportedElementType {
+        line: usize,
+        type_id: usize,
+        description: &'static str,
+    },
+    /// The file mixes linear and quadratic surface elements
+    #[error("meshes mixing linear and quadratic elements are not supported")]
+    MixedElementTypes,
+    /// The file does not contain any triangle or quadrangle
+    #[error("the mesh does not contain any surface element")]
+    NoSurfaceElements,
+    /// An element references a node that is not listed in `$Nodes`
+    #[error("element references unknown node {0}")]
+    UnknownNode(usize),
+    /// A physical curve contains a line that is not on the boundary of the mesh
+    #[error("physical curve \"{0}\" contains an edge that is not on the boundary")]
+    InteriorPhysicalCurve(String),
+}
+// ANCHOR_END: error_enum
+
+/// Gmsh element as read from the file, before conversion.
+struct RawElement {
+    line: usize,
+    type_id: usize,
+    physical_tags: Vec<usize>,
+    nodes: Vec<usize>,
+}
+
+/// Content of a MSH file, independent of the format version.
+#[derive(Default)]
+struct RawMesh {
+    physical_names: HashMap<(usize, usize), String>,
+    nodes: Vec<(usize, Point2<f64>)>,
+    elements: Vec<RawElement>,
+}
+
+/// Lines of a `$Section ... $EndSection` block, with their line numbers.
+struct Section<'a> {
+    header_line: usize,
+    lines: Vec<(usize, &'a str)>,
+}
+
+// ANCHOR: read_msh
+/// Reads a Gmsh MSH file (version 2.2 or 4.1, ASCII).
+pub fn read_msh<P: AsRef<Path>>(path: P) -> Result<Mesh2d, GmshError> {
+    let content = std::fs::read_to_string(path)?;
+    parse_msh(&content)
+}
+
+/// Parses the content of a Gmsh MSH file (version 2.2 or 4.1, ASCII).
+pub fn parse_msh(content: &str) -> Result<Mesh2d, GmshError> {
+    let sections = split_sections(content)?;
+    let format = sections
+        .get("MeshFormat")
+        .ok_or(GmshError::MissingSection("MeshFormat"))?;
+    let (line, header) = format.lines.first().copied().ok_or(GmshError::Parse {
+        line: format.header_line,
+        message: "empty $MeshFormat section".to_string(),
+    })?;
+    let fields: Vec<&str> = header.split_whitespace().collect();
+    if fields.len() < 2 || fields[1] != "0" {
+        return Err(GmshError::UnsupportedFormat(header.to_string()));
+    }
+
+    let mut raw = match fields[0] {
+        "2.2" => parse_v2(&sections)?,
+        "4.1" => parse_v4(&sections)?,
+        version => {
+            return Err(GmshError::UnsupportedFormat(format!(
+                "version {version} at line {line}"
+            )));
+        }
+    };
+    if let Some(names) = sections.get("PhysicalNames") {
+        raw.physical_names = parse_physical_names(names)?;
+    }
+    build_mesh(raw)
+}
+// ANCHOR_END: read_msh
+
+fn split_sections(content: &str) -> Result<HashMap<&str, Section<'_>>, GmshError> {
+    let mut sections = HashMap::new();
+    let mut current: Option<(&str, Section)> = None;
+    for (i, raw_line) in content.lines().enumerate() {
+        let line_no = i + 1;
+        let line = raw_line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match current.take() {
+            None => {
+                if let Some(name) = line.strip_prefix('$') {
+                    let section = Section {
+                        header_line: line_no,
+                        lines: Vec::new(),
+                    };
+                    current = Some((name, section));
+                }
+                // Anything outside of a section is ignored, as Gmsh does.
+            }
+            Some((name, mut section)) => {
+                if line.strip_prefix("$End") == Some(name) {
+                    sections.insert(name, section);
+                } else {
+                    section.lines.push((line_no, line));
+                    current = Some((name, section));
+                }
+            }
+        }
+    }
+    match current {
+        Some((_, section)) => Err(GmshError::Parse {
+            line: section.header_line,
+            message: "section is never closed".to_string(),
+        }),
+        None => Ok(sections),
+    }
+}
+
+/// Parses the whitespace-separated field `index` of `line`.
+fn field<T: FromStr>(line: (usize, &str), index: usize) -> Result<T, GmshError> {
+    let (line_no, text) = line;
+    text.split_whitespace()
+        .nth(index)
+        .and_then(|token| token.parse().ok())
+        .ok_or_else(|| GmshError::Parse {
+            line: line_no,
+            message: format!("cannot read field {} of \"{text}\"", index + 1),
+        })
+}
+
+/// Parses every field of `line`.
+fn fields<T: FromStr>(line: (usize, &str)) -> Result<Vec<T>, GmshError> {
+    let (line_no, text) = line;
+    text.split_whitespace()
+        .map(|token| {
+            token.parse().map_err(|_| GmshError::Parse {
+                line: line_no,
+                message: format!("cannot parse \"{token}\""),
+            })
+        })
+        .collect()
+}
+
+/// Returns the next line of a section, or an error if the section ended too early.
+fn next_line<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    section: &Section,
+) -> Result<(usize, &'a str), GmshError> {
+    lines.next().ok_or(GmshError::Parse {
+        line: section.header_line,
+        message: "section ends prematurely".to_string(),
+    })
+}
+
+fn required<'a, 'b>(
+    sections: &'b HashMap<&str, Section<'a>>,
+    name: &'static str,
+) -> Result<&'b Section<'a>, GmshError> {
+    sections.get(name).ok_or(GmshError::MissingSection(name))
+}
+
+fn parse_physical_names(section: &Section) -> Result<HashMap<(usize, usize), String>, GmshError> {
+    let mut lines = section.lines.iter().copied();
+    let count: usize = field(next_line(&mut lines, section)?, 0)?;
+    let mut names = HashMap::with_capacity(count);
+    for _ in 0..count {
+        let line = next_line(&mut lines, section)?;
+        let dim: usize = field(line, 0)?;
+        let tag: usize = field(line, 1)?;
+        let name = line
+            .1
+            .split_once('"')
+            .and_then(|(_, rest)| rest.rsplit_once('"'))
+            .map(|(name, _)| name.to_string())
+            .ok_or_else(|| GmshError::Parse {
+                line: line.0,
+                message: "physical name must be quoted".to_string(),
+            })?;
+        names.insert((dim, tag), name);
+    }
+    Ok(names)
+}
+
+// ANCHOR: parse_v2
+fn parse_v2(sections: &HashMap<&str, Section>) -> Result<RawMesh, GmshError> {
+    let mut raw = RawMesh::default();
+
+    let section = required(sections, "Nodes")?;
+    let mut lines = section.lines.iter().copied();
+    let count: usize = field(next_line(&mut lines, section)?, 0)?;
+    for _ in 0..count {
+        let line = next_line(&mut lines, section)?;
+        let point = Point2::new(field(line, 1)?, field(line, 2)?);
+        raw.nodes.push((field(line, 0)?, point));
+    }
+
+    // Each element reads: id type num_tags tags... nodes..., the first tag being the
+    // physical group (0 if none).
+    let section = required(sections, "Elements")?;
+    let mut lines = section.lines.iter().copied();
+    let count: usize = field(next_line(&mut lines, section)?, 0)?;
+    for _ in 0..count {
+        let line = next_line(&mut lines, section)?;
+        let values: Vec<usize> = fields(line)?;
+        let num_tags = *values.get(2).ok_or(GmshError::Parse {
+            line: line.0,
+            message: "incomplete element".to_string(),
+        })?;
+        let physical = values
+            .get(3)
+            .copied()
+            .filter(|&tag| tag > 0 && num_tags > 0);
+        raw.elements.push(RawElement {
+            line: line.0,
+            type_id: values[1],
+            physical_tags: physical.into_iter().collect(),
+            nodes: values.get(3 + num_tags..).unwrap_or(&[]).to_vec(),
+        });
+    }
+    Ok(raw)
+}
+// ANCHOR_END: parse_v2
+
+// ANCHOR: parse_v4
+fn parse_v4(sections: &HashMap<&str, Section>) -> Result<RawMesh, GmshError> {
+    let mut raw = RawMesh::default();
+
+    // In MSH 4, physical groups are attached to geometric entities, not to elements.
+    let mut entity_tags: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    if let Some(section) = sections.get("Entities") {
+        let mut lines = section.lines.iter().copied();
+        let counts: Vec<usize> = fields(next_line(&mut lines, section)?)?;
+        for (dim, &count) in counts.iter().enumerate() {
+            // Points store their coordinates, higher dimensions a bounding box.
+            let offset = if dim == 0 { 4 } else { 7 };
+            for _ in 0..count {
+                let line = next_line(&mut lines, section)?;
+                let tag: usize = field(line, 0)?;
+                let num_physical: usize = field(line, offset)?;
+                let mut physical = Vec::with_capacity(num_physical);
+                for k in 0..num_physical {
+                    let tag: i64 = field(line, offset + 1 + k)?;
+                    physical.push(tag.unsigned_abs() as usize);
+                }
+                entity_tags.insert((dim, tag), physical);
+            }
+        }
+    }
+
+    let section = required(sections, "Nodes")?;
+    let mut lines = section.lines.iter().copied();
+    let num_blocks: usize = field(next_line(&mut lines, section)?, 0)?;
+    for _ in 0..num_blocks {
+        let header = next_line(&mut lines, section)?;
+        let count: usize = field(header, 3)?;
+        let mut tags = Vec::with_capacity(count);
+        for _ in 0..count {
+            tags.push(field(next_line(&mut lines, section)?, 0)?);
+        }
+        for tag in tags {
+            let line = next_line(&mut lines, section)?;
+            raw.nodes
+                .push((tag, Point2::new(field(line, 0)?, field(line, 1)?)));
+        }
+    }
+
+    let section = required(sections, "Elements")?;
+    let mut lines = section.lines.iter().copied();
+    let num_blocks: usize = field(next_line(&mut lines, section)?, 0)?;
+    for _ in 0..num_blocks {
+        let header = next_line(&mut lines, section)?;
+        let dim: usize = field(header, 0)?;
+        let entity: usize = field(header, 1)?;
+        let type_id: usize = field(header, 2)?;
+        let count: usize = field(header, 3)?;
+        let physical_tags = entity_tags.get(&(dim, entity)).cloned().unwrap_or_default();
+        for _ in 0..count {
+            let line = next_line(&mut lines, section)?;
+            let values: Vec<usize> = fields(line)?;
+            raw.elements.push(RawElement {
+                line: line.0,
+                type_id,
+                physical_tags: physical_tags.clone(),
+                nodes: values[1..].to_vec(),
+            });
+        }
+    }
+    Ok(raw)
+}
+// ANCHOR_END: parse_v4
+
+/// Dimension and `ElementType` of the Gmsh element types we can read.
+///
+/// Points are accepted but skipped, lines feed the boundary groups.
+fn element_kind(element: &RawElement) -> Result<(usize, Option<ElementType>), GmshError> {
+    let (dim, element_type, num_nodes) = match element.type_id {
+        15 => (0, None, 1),
+        1 => (1, None, 2),
+        8 => (1, None, 3),
+        2 => (2, Some(ElementType::P1), 3),
+        3 => (2, Some(ElementType::Q1), 4),
+        9 => (2, Some(ElementType::P2), 6),
+        10 => (2, Some(ElementType::Q2), 9),
+        16 => (2, Some(ElementType::Q8), 8),
+        type_id => {
+            let description = match type_id {
+                4 => "4-node tetrahedron",
+                5 => "8-node hexahedron",
+                6 => "6-node prism",
+                7 => "5-node pyramid",
+                11 => "10-node tetrahedron",
+                12 => "27-node hexahedron",
+                21 => "10-node triangle",
+                _ => "unknown element type",
+            };
+            return Err(GmshError::UnsupportedElementType {
+                line: element.line,
+                type_id,
+                description,
+            });
+        }
+    };
+    if element.nodes.len() != num_nodes {
+        return Err(GmshError::Parse {
+            line: element.line,
+            message: format!(
+                "element type {} expects {num_nodes} nodes, got {}",
+                element.type_id,
+                element.nodes.len()
+            ),
+        });
+    }
+    Ok((dim, element_type))
+}
+
+/// Signed area of the polygon formed by the given nodes.
+fn signed_area(vertices: &[Point2<f64>], indices: &[usize]) -> f64 {
+    let n = indices.len();
+    (0..n)
+        .map(|k| {
+            let p = vertices[indices[k]];
+            let q = vertices[indices[(k + 1) % n]];
+            p.x * q.y - q.x * p.y
+        })
+        .sum::<f64>()
+        * 0.5
+}
+
+// ANCHOR: build_mesh
+fn build_mesh(raw: RawMesh) -> Result<Mesh2d, GmshError> {
+    let group_name = |dim: usize, tag: usize| {
+        raw.physical_names
+            .get(&(dim, tag))
+            .cloned()
+            .unwrap_or_else(|| tag.to_string())
+    };
+
+    let mut node_index: HashMap<usize, usize> = HashMap::with_capacity(raw.nodes.len());
+    let mut vertices = Vec::with_capacity(raw.nodes.len());
+    for (tag, point) in &raw.nodes {
+        node_index.insert(*tag, vertices.len());
+        vertices.push(*point);
+    }
+    let to_indices = |nodes: &[usize]| -> Result<Vec<usize>, GmshError> {
+        nodes
+            .iter()
+            .map(|tag| {
+                node_index
+                    .get(tag)
+                    .copied()
+                    .ok_or(GmshError::UnknownNode(*tag))
+            })
+            .collect()
+    };
+
+    let mut elements = Vec::new();
+    let mut element_types: Vec<ElementType> = Vec::new();
+    let mut regions: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut curves: BTreeMap<String, Vec<Vec<usize>>> = BTreeMap::new();
+    for raw_element in &raw.elements {
+        let (dim, kind) = element_kind(raw_element)?;
+        let mut indices = to_indices(&raw_element.nodes)?;
+        match (dim, kind) {
+            (1, _) => {
+                for &tag in &raw_element.physical_tags {
+                    curves
+                        .entry(group_name(1, tag))
+                        .or_default()
+                        .push(indices.clone());
+                }
+            }
+            (2, Some(kind)) => {
+                // Triangles and quadrangles can be mixed, as long as their edges match.
+                if element_types
+                    .first()
+                    .is_some_and(|t| t.order() != kind.order())
+                {
+                    return Err(GmshError::MixedElementTypes);
+                }
+                let corners = kind.num_corners();
+                // Gmsh may orient surfaces clockwise, the solver expects counter-clockwise.
+                // Reversing the corners also reverses the order of the edges, hence of
+                // their midside nodes, while the centre node stays last.
+                if signed_area(&vertices, &indices[..corners]) < 0.0 {
+                    indices[1..corners].reverse();
+                    let midside = indices.len().min(2 * corners);
+                    indices[corners..midside].reverse();
+                }
+                for &tag in &raw_element.physical_tags {
+                    regions
+                        .entry(group_name(2, tag))
+                        .or_default()
+                        .push(elements.len());
+                }
+                elements.push(Element { indices });
+                element_types.push(kind);
+            }
+            _ => {}
+        }
+    }
+
+    if elements.is_empty() {
+        return Err(GmshError::NoSurfaceElements);
+    }
+    let mut mesh = Mesh2d::new_mixed(vertices, elements, element_types);
+    for (name, elements) in regions {
+        mesh.add_region(&name, elements);
+    }
+
+    // Physical curves are matched against the boundary edges of the mesh, which carry
+    // the counter-clockwise orientation.
+    let boundary: HashMap<(usize, usize), Edge> = mesh
+        .boundary_edges()
+        .into_iter()
+        .map(|edge| (edge.key(), edge))
+        .collect();
+    for (name, lines) in curves {
+        let mut edges = Vec::with_capacity(lines.len());
+        for nodes in lines {
+            let key = (nodes[0].min(nodes[1]), nodes[0].max(nodes[1]));
+            match boundary.get(&key) {
+                Some(edge) => edges.push(edge.clone()),
+                None => return Err(GmshError::InteriorPhysicalCurve(name)),
+            }
+        }
+        mesh.add_boundary_group(&name, edges);
+    }
+    Ok(mesh)
+}
+// ANCHOR_END: build_mesh
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit square split into two triangles, the first one oriented clockwise.
+    const SQUARE_V2: &str = r#"$MeshFormat
+2.2 0 8
+$EndMeshFormat
+$PhysicalNames
+3
+1 1 "wall"
+1 2 "inlet"
+2 3 "domain"
+$EndPhysicalNames
+$Nodes
+4
+1 0 0 0
+2 1 0 0
+3 1 1 0
+4 0 1 0
+$EndNodes
+$Elements
+7
+1 15 2 0 1 1
+2 1 2 1 1 1 2
+3 1 2 1 2 2 3
+4 1 2 1 3 3 4
+5 1 2 2 4 4 1
+6 2 2 3 1 1 3 2
+7 2 2 3 1 1 3 4
+$EndElements
+"#;
+
+    /// Unit square made of two quadrangles, [0, 2] x [0, 1].
+    const QUADS_V4: &str = r#"$MeshFormat
+4.1 0 8
+$EndMeshFormat
+$PhysicalNames
+2
+1 10 "left"
+2 20 "plate"
+$EndPhysicalNames
+$Entities
+0 2 1 0
+1 0 0 0 0 1 0 1 10 2 1 -2
+2 0 0 0 2 0 0 0 2 1 -2
+1 0 0 0 2 1 0 1 20 2 1 2
+$EndEntities
+$Nodes
+2 6 1 6
+1 1 0 2
+1
+4
+0 0 0
+0 1 0
+2 1 0 4
+2
+3
+5
+6
+1 0 0
+2 0 0
+1 1 0
+2 1 0
+$EndNodes
+$Elements
+2 3 1 3
+1 1 1 1
+1 1 4
+2 1 3 2
+2 1 2 5 4
+3 2 3 6 5
+$EndElements
+"#;
+
+    #[test]
+    fn test_parse_v2() {
+        let mesh = parse_msh(SQUARE_V2).unwrap();
+        assert_eq!(mesh.element_type(), Some(&ElementType::P1));
+        assert_eq!(mesh.vertices().len(), 4);
+        assert_eq!(mesh.elements().len(), 2);
+        assert_eq!(mesh.region("domain"), Some(&[0, 1][..]));
+        assert_eq!(mesh.boundary_group("wall").map(|g| g.len()), Some(3));
+        assert_eq!(mesh.boundary_group_nodes("inlet"), Some(vec![0, 3]));
+
+        // The clockwise triangle has been reoriented.
+        for element in mesh.elements() {
+            assert!(signed_area(mesh.vertices(), &element.indices) > 0.0);
+        }
+        // Boundary groups follow the counter-clockwise orientation of the mesh.
+        let inlet = mesh.boundary_group("inlet").unwrap();
+        assert_eq!(inlet[0].indices, vec![3, 0]);
+    }
+
+    #[test]
+    fn test_parse_v4() {
+        let mesh = parse_msh(QUADS_V4).unwrap();
+        assert_eq!(mesh.element_type(), Some(&ElementType::Q1));
+        assert_eq!(mesh.vertices().len(), 6);
+        assert_eq!(mesh.vertices()[4], Point2::new(1.0, 1.0));
+        assert_eq!(mesh.elements().len(), 2);
+        assert_eq!(mesh.elements()[1].indices, vec![2, 3, 5, 4]);
+        assert_eq!(mesh.region("plate"), Some(&[0, 1][..]));
+        assert_eq!(mesh.boundary_group_nodes("left"), Some(vec![0, 1]));
+        assert_eq!(mesh.boundary_nodes().len(), 6);
+    }
+
+    /// Quadratic triangle oriented clockwise, with a 3-node line on the bottom side.
+    const TRI6_V2: &str = r#"$MeshFormat
+2.2 0 8
+$EndMeshFormat
+$PhysicalNames
+1
+1 2 "bottom"
+$EndPhysicalNames
+$Nodes
+6
+1 0 0 0
+2 1 0 0
+3 0 1 0
+4 0.5 0 0
+5 0.5 0.5 0
+6 0 0.5 0
+$EndNodes
+$Elements
+2
+1 8 2 2 1 1 2 4
+2 9 2 1 1 1 3 2 6 5 4
+$EndElements
+"#;
+
+    #[test]
+    fn test_parse_quadratic() {
+        let mesh = parse_msh(TRI6_V2).unwrap();
+        assert_eq!(mesh.element_type(), Some(&ElementType::P2));
+        // Corners and midside nodes have been reoriented consistently.
+        assert_eq!(mesh.elements()[0].indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(mesh.boundary_group_nodes("bottom"), Some(vec![0, 1, 3]));
+        assert_eq!(
+            mesh.boundary_group("bottom").unwrap()[0].indices,
+            vec![0, 1, 3]
+        );
+    }
+
+    /// A quadrangle and two triangles covering [0, 2] x [0, 1].
+    const MIXED_V2: &str = r#"$MeshFormat
+2.2 0 8
+$EndMeshFormat
+$Nodes
+6
+1 0 0 0
+2 1 0 0
+3 2 0 0
+4 0 1 0
+5 1 1 0
+6 2 1 0
+$EndNodes
+$Elements
+3
+1 3 2 1 1 1 2 5 4
+2 2 2 1 1 2 3 6
+3 2 2 1 1 2 6 5
+$EndElements
+"#;
+
+    #[test]
+    fn test_parse_mixed() {
+        let mesh = parse_msh(MIXED_V2).unwrap();
+        assert_eq!(mesh.element_type(), None);
+        assert_eq!(
+            mesh.element_types(),
+            &[ElementType::Q1, ElementType::P1, ElementType::P1]
+        );
+        assert_eq!(mesh.boundary_edges().len(), 6);
+
+        // Linear and quadratic elements do not share their edges.
+        let content = TRI6_V2
+            .replace("$Elements\n2", "$Elements\n3")
+            .replace("$EndElements", "3 2 2 1 1 1 2 3\n$EndElements");
+        assert!(matches!(
+            parse_msh(&content),
+            Err(GmshError::MixedElementTypes)
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_element_type() {
+        let content = SQUARE_V2.replace("6 2 2 3 1 1 3 2", "6 4 2 3 1 1 3 2 4");
+        match parse_msh(&content) {
+            Err(GmshError::UnsupportedElementType { type_id, line, .. }) => {
+                assert_eq!(type_id, 4);
+                assert_eq!(line, 24);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_malformed_files() {
+        let binary = SQUARE_V2.replace("2.2 0 8", "2.2 1 8");
+        assert!(matches!(
+            parse_msh(&binary),
+            Err(GmshError::UnsupportedFormat(_))
+        ));
+
+        let version = SQUARE_V2.replace("2.2 0 8", "3.0 0 8");
+        assert!(matches!(
+            parse_msh(&version),
+            Err(GmshError::UnsupportedFormat(_))
+        ));
+
+        let truncated = SQUARE_V2.replace("$EndElements\n", "");
+        assert!(matches!(
+            parse_msh(&truncated),
+            Err(GmshError::Parse { .. })
+        ));
+
+        let unknown_node = SQUARE_V2.replace("7 2 2 3 1 1 3 4", "7 2 2 3 1 1 3 9");
+        assert!(matches!(
+            parse_msh(&unknown_node),
+            Err(GmshError::UnknownNode(9))
+        ));
+
+        let no_nodes = SQUARE_V2
+            .replace("$Nodes", "$Points")
+            .replace("$EndNodes", "$EndPoints");
+        assert!(matches!(
+            parse_msh(&no_nodes),
+            Err(GmshError::MissingSection("Nodes"))
+        ));
+    }
+
+    #[test]
+    fn test_read_msh_missing_file() {
+        assert!(matches!(
+            read_msh("does/not/exist.msh"),
+            Err(GmshError::Io(_))
+        ));
+    }
+}
+// ANCHOR_END: tests
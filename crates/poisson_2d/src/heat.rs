@@ -0,0 +1,370 @@
+//! Module that implements the transient heat equation `∂u/∂t - Δu = f(x, y, t)`.
+//!
+//! The semi-discrete system `M u' + K u = b(t)` is integrated with backward Euler,
+//! Crank-Nicolson or BDF2. The time step is constant, so the matrix of each scheme is
+//! assembled and factored, or preconditioned for iterative solvers, once for the whole
+//! simulation.
+use crate::SolverType;
+use crate::direct::SparseCholesky;
+use crate::iterative::{IterativeConfig, PreconditionerOp, solve_iterative_with};
+use crate::mesh::Mesh2d;
+use crate::solver::{
+    PoissonError, apply_dirichlet_sparse, assemble_load_vector, assemble_mass_sparse,
+    assemble_system_sparse, check_problem, sparse_solver,
+};
+use nalgebra::{Cholesky, DMatrix, DVector, Dyn};
+use nalgebra_sparse::CsrMatrix;
+
+// ANCHOR: time_stepping
+/// Time integration scheme.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeScheme {
+    /// First-order implicit Euler
+    BackwardEuler,
+    /// Second-order trapezoidal rule
+    CrankNicolson,
+    /// Second-order backward differentiation formula, started with one backward Euler step
+    Bdf2,
+}
+
+/// Time discretization of a transient simulation.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeStepping {
+    pub scheme: TimeScheme,
+    /// Time step, expected to be positive
+    pub dt: f64,
+    pub num_steps: usize,
+    /// Time of the initial condition
+    pub start_time: f64,
+}
+
+impl TimeStepping {
+    pub fn new(scheme: TimeScheme, dt: f64, num_steps: usize) -> Self {
+        Self {
+            scheme,
+            dt,
+            num_steps,
+            start_time: 0.0,
+        }
+    }
+
+    pub fn with_start_time(mut self, start_time: f64) -> Self {
+        self.start_time = start_time;
+        self
+    }
+}
+// ANCHOR_END: time_stepping
+
+/// Linear solver of one time step, built once from the constrained matrix.
+enum StepSolver {
+    Dense(Cholesky<f64, Dyn>),
+    Sparse(CsrMatrix<f64>),
+    Iterative {
+        a: CsrMatrix<f64>,
+        config: IterativeConfig,
+        precond: PreconditionerOp,
+    },
+    SparseCholesky(SparseCholesky),
+}
+
+impl StepSolver {
+    fn new(a: CsrMatrix<f64>, solver_type: &SolverType) -> Result<Self, PoissonError> {
+        Ok(match solver_type {
+            SolverType::Dense => Self::Dense(
+                DMatrix::from(&a)
+                    .cholesky()
+                    .ok_or(PoissonError::SingularSystem)?,
+            ),
+            SolverType::Sparse => Self::Sparse(a),
+            SolverType::Iterative(config) => Self::Iterative {
+                precond: PreconditionerOp::new(&a, config.preconditioner)?,
+                a,
+                config: config.clone(),
+            },
+            SolverType::SparseCholesky(reordering) => {
+                Self::SparseCholesky(SparseCholesky::factor(&a, *reordering)?)
+            }
+        })
+    }
+
+    /// Solves the step, `previous` being the solution of the previous step.
+    fn solve(
+        &self,
+        b: &DVector<f64>,
+        previous: &DVector<f64>,
+    ) -> Result<DVector<f64>, PoissonError> {
+        match self {
+            Self::Dense(chol) => Ok(chol.solve(b)),
+            Self::Sparse(a) => sparse_solver(a, b),
+            Self::Iterative { a, config, precond } => {
+                // The previous solution is a good starting point for the iterations.
+                let config = config.clone().with_initial_guess(previous.clone());
+                solve_iterative_with(a, b, &config, precond).map(|(x, _)| x)
+            }
+            Self::SparseCholesky(chol) => chol.solve(b),
+        }
+    }
+}
+
+/// System `A u = rhs` solved at every step, with Dirichlet conditions changing in time.
+struct ConstrainedSystem<'a> {
+    /// Matrix before elimination of the boundary nodes, used to lift the boundary values
+    a: CsrMatrix<f64>,
+    solver: StepSolver,
+    boundary_nodes: &'a [usize],
+}
+
+impl<'a> ConstrainedSystem<'a> {
+    fn new(
+        a: CsrMatrix<f64>,
+        boundary_nodes: &'a [usize],
+        mesh: &Mesh2d,
+        solver_type: &SolverType,
+    ) -> Result<Self, PoissonError> {
+        let mut constrained = a.clone();
+        let mut dummy = DVector::zeros(a.nrows());
+        apply_dirichlet_sparse(
+            &mut constrained,
+            &mut dummy,
+            boundary_nodes,
+            mesh,
+            |_, _| 0.0,
+        );
+        Ok(Self {
+            a,
+            solver: StepSolver::new(constrained, solver_type)?,
+            boundary_nodes,
+        })
+    }
+
+    /// Solves the step with the boundary values `g`, which are zero on the free nodes.
+    fn solve(
+        &self,
+        rhs: DVector<f64>,
+        g: &DVector<f64>,
+        previous: &DVector<f64>,
+    ) -> Result<DVector<f64>, PoissonError> {
+        let mut b = rhs - &self.a * g;
+        for &j in self.boundary_nodes {
+            b[j] = g[j];
+        }
+        self.solver.solve(&b, previous)
+    }
+}
+
+// ANCHOR: solve_heat
+/// Solves `∂u/∂t - Δu = f` with `u = g` on the boundary nodes and `u = u0` at the start.
+///
+/// Arguments:
+/// - `boundary_fn`: Dirichlet data `g(x, y, t)`.
+/// - `source_fn`: Source term `f(x, y, t)`.
+/// - `initial_fn`: Initial condition `u0(x, y)`, overwritten by `g` on the boundary.
+/// - `solver_type`: Linear solver used at each step.
+/// - `on_step`: Called with the step index, the time and the solution, first with the
+///   initial condition (step 0) and then after each time step.
+///
+/// Returns:
+/// - The solution at the final time.
+#[allow(clippy::too_many_arguments)]
+pub fn solve_heat<G, F, U, C>(
+    mesh: &Mesh2d,
+    boundary_nodes: &[usize],
+    boundary_fn: &G,
+    source_fn: &F,
+    initial_fn: &U,
+    stepping: &TimeStepping,
+    solver_type: SolverType,
+    mut on_step: C,
+) -> Result<DVector<f64>, PoissonError>
+where
+    G: Fn(f64, f64, f64) -> f64,
+    F: Fn(f64, f64, f64) -> f64 + Sync,
+    U: Fn(f64, f64) -> f64,
+    C: FnMut(usize, f64, &DVector<f64>),
+{
+    let boundary_nodes = check_problem(mesh, boundary_nodes)?;
+    let dt = stepping.dt;
+
+    let (k, _) = assemble_system_sparse(mesh, &|_, _| 0.0);
+    let m = assemble_mass_sparse(mesh);
+    let load = |t: f64| assemble_load_vector(mesh, &|x, y| source_fn(x, y, t));
+    let boundary_values = |t: f64| {
+        let mut g = DVector::zeros(mesh.vertices().len());
+        for &j in &boundary_nodes {
+            let v = &mesh.vertices()[j];
+            g[j] = boundary_fn(v.x, v.y, t);
+        }
+        g
+    };
+
+    let mut t = stepping.start_time;
+    let mut u = DVector::from_iterator(
+        mesh.vertices().len(),
+        mesh.vertices().iter().map(|v| initial_fn(v.x, v.y)),
+    );
+    for &j in &boundary_nodes {
+        let v = &mesh.vertices()[j];
+        u[j] = boundary_fn(v.x, v.y, t);
+    }
+    on_step(0, t, &u);
+
+    let euler =
+        |solver_type| ConstrainedSystem::new(&m + &k * dt, &boundary_nodes, mesh, solver_type);
+    match stepping.scheme {
+        TimeScheme::BackwardEuler => {
+            // (M + dt K) u_{n+1} = M u_n + dt b_{n+1}
+            let system = euler(&solver_type)?;
+            for step in 1..=stepping.num_steps {
+                t = stepping.start_time + step as f64 * dt;
+                let rhs = &m * &u + load(t) * dt;
+                u = system.solve(rhs, &boundary_values(t), &u)?;
+                on_step(step, t, &u);
+            }
+        }
+        TimeScheme::CrankNicolson => {
+            // (M + dt/2 K) u_{n+1} = (M - dt/2 K) u_n + dt/2 (b_n + b_{n+1})
+            let system =
+                ConstrainedSystem::new(&m + &k * (0.5 * dt), &boundary_nodes, mesh, &solver_type)?;
+            let explicit = &m - &k * (0.5 * dt);
+            let mut b_prev = load(t);
+            for step in 1..=stepping.num_steps {
+                t = stepping.start_time + step as f64 * dt;
+                let b_next = load(t);
+                let rhs = &explicit * &u + (&b_prev + &b_next) * (0.5 * dt);
+                u = system.solve(rhs, &boundary_values(t), &u)?;
+                b_prev = b_next;
+                on_step(step, t, &u);
+            }
+        }
+        TimeScheme::Bdf2 => {
+            // (3/2 M + dt K) u_{n+1} = 2 M u_n - 1/2 M u_{n-1} + dt b_{n+1}
+            let system =
+                ConstrainedSystem::new(&m * 1.5 + &k * dt, &boundary_nodes, mesh, &solver_type)?;
+            let mut u_prev = u.clone();
+            for step in 1..=stepping.num_steps {
+                t = stepping.start_time + step as f64 * dt;
+                let g = boundary_values(t);
+                let u_next = if step == 1 {
+                    let rhs = &m * &u + load(t) * dt;
+                    euler(&solver_type)?.solve(rhs, &g, &u)?
+                } else {
+                    let rhs = &m * (&u * 2.0 - &u_prev * 0.5) + load(t) * dt;
+                    system.solve(rhs, &g, &u)?
+                };
+                u_prev = std::mem::replace(&mut u, u_next);
+                on_step(step, t, &u);
+            }
+        }
+    }
+    Ok(u)
+}
+// ANCHOR_END: solve_heat
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direct::Reordering;
+    use crate::element::ElementType;
+    use crate::generators::unit_square;
+    use crate::iterative::{IterativeMethod, Preconditioner};
+
+    fn solver_types() -> Vec<SolverType> {
+        vec![
+            SolverType::Dense,
+            SolverType::Sparse,
+            SolverType::Iterative(
+                IterativeConfig::new(IterativeMethod::ConjugateGradient)
+                    .with_preconditioner(Preconditioner::IncompleteCholesky),
+            ),
+            SolverType::SparseCholesky(Reordering::ReverseCuthillMcKee),
+        ]
+    }
+
+    #[test]
+    fn test_linear_in_time_solution() {
+        // u = (1 + x + 2y)(1 + t) is harmonic in space and linear in time, so that every
+        // scheme reproduces it exactly.
+        let exact = |x: f64, y: f64, t: f64| (1.0 + x + 2.0 * y) * (1.0 + t);
+        let source = |x: f64, y: f64, _: f64| 1.0 + x + 2.0 * y;
+        let initial = |x: f64, y: f64| exact(x, y, 0.5);
+        let (mesh, boundary) = unit_square(4, ElementType::Q1);
+        for scheme in [
+            TimeScheme::BackwardEuler,
+            TimeScheme::CrankNicolson,
+            TimeScheme::Bdf2,
+        ] {
+            for solver_type in solver_types() {
+                let stepping = TimeStepping::new(scheme, 0.1, 5).with_start_time(0.5);
+                let mut times = Vec::new();
+                let u = solve_heat(
+                    &mesh,
+                    &boundary.all(),
+                    &exact,
+                    &source,
+                    &initial,
+                    &stepping,
+                    solver_type,
+                    |step, t, u: &DVector<f64>| {
+                        for (v, u_h) in mesh.vertices().iter().zip(u.iter()) {
+                            assert!((u_h - exact(v.x, v.y, t)).abs() < 1e-8, "step {step}");
+                        }
+                        times.push(t);
+                    },
+                )
+                .unwrap();
+                assert_eq!(times.len(), 6);
+                assert!((times[5] - 1.0).abs() < 1e-12);
+                assert_eq!(u.len(), mesh.vertices().len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_time_convergence_orders() {
+        // u = (1 + x + y) e^{-t} is harmonic in space, so the error only comes from the
+        // time discretization.
+        let exact = |x: f64, y: f64, t: f64| (1.0 + x + y) * (-t).exp();
+        let source = |x: f64, y: f64, t: f64| -exact(x, y, t);
+        let initial = |x: f64, y: f64| exact(x, y, 0.0);
+        let (mesh, boundary) = unit_square(3, ElementType::P1);
+
+        let cases = [
+            (TimeScheme::BackwardEuler, 1.0),
+            (TimeScheme::CrankNicolson, 2.0),
+            (TimeScheme::Bdf2, 2.0),
+        ];
+        for (scheme, expected_rate) in cases {
+            let errors: Vec<f64> = [10, 20, 40]
+                .iter()
+                .map(|&num_steps| {
+                    let stepping = TimeStepping::new(scheme, 1.0 / num_steps as f64, num_steps);
+                    let u = solve_heat(
+                        &mesh,
+                        &boundary.all(),
+                        &exact,
+                        &source,
+                        &initial,
+                        &stepping,
+                        SolverType::Dense,
+                        |_, _, _| {},
+                    )
+                    .unwrap();
+                    mesh.vertices()
+                        .iter()
+                        .zip(u.iter())
+                        .map(|(v, u_h)| (u_h - exact(v.x, v.y, 1.0)).abs())
+                        .fold(0.0, f64::max)
+                })
+                .collect();
+            for pair in errors.windows(2) {
+                let rate = (pair[0] / pair[1]).log2();
+                assert!(
+                    (rate - expected_rate).abs() < 0.2,
+                    "{scheme:?}: rate {rate} (errors {errors:?})"
+                );
+            }
+        }
+    }
+}
+// ANCHOR_END: tests
@@ -0,0 +1,700 @@
+//! Module that implements preconditioned Krylov solvers for sparse FEM systems.
+//!
+//! The conjugate gradient is meant for the symmetric positive definite systems of the
+//! Poisson problem, GMRES and BiCGStab for non-symmetric operators such as the
+//! convection-diffusion-reaction form. All methods stop when `‖b - Ax‖ ≤ tolerance ‖b‖`
+//! and record the residual norm at every iteration.
+use crate::amg::{AmgConfig, smoothed_aggregation};
+use crate::multigrid::Multigrid;
+use crate::solver::PoissonError;
+use nalgebra::DVector;
+use nalgebra_sparse::CsrMatrix;
+
+// ANCHOR: config
+/// Krylov method used by `solve_iterative`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum IterativeMethod {
+    /// Conjugate gradient, for symmetric positive definite matrices
+    ConjugateGradient,
+    /// GMRES restarted every `restart` iterations
+    Gmres { restart: usize },
+    /// Stabilized bi-conjugate gradient
+    BiCgStab,
+}
+
+/// Preconditioner applied by the Krylov methods.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Preconditioner {
+    None,
+    /// Diagonal scaling
+    Jacobi,
+    /// Symmetric successive over-relaxation with relaxation factor `0 < omega < 2`
+    Ssor {
+        omega: f64,
+    },
+    /// Incomplete Cholesky factorization without fill-in, built from the lower triangle
+    IncompleteCholesky,
+    /// One V- or W-cycle of smoothed aggregation algebraic multigrid
+    SmoothedAggregation(AmgConfig),
+}
+
+/// Settings of an iterative solve.
+#[derive(Clone, Debug)]
+pub struct IterativeConfig {
+    pub method: IterativeMethod,
+    pub preconditioner: Preconditioner,
+    /// Relative tolerance on the residual norm
+    pub tolerance: f64,
+    pub max_iterations: usize,
+    /// Starting point of the iterations, zero if `None`
+    pub initial_guess: Option<DVector<f64>>,
+}
+
+impl Default for IterativeConfig {
+    /// Jacobi-preconditioned CG with a relative tolerance of `1e-10` and at most 1000
+    /// iterations.
+    fn default() -> Self {
+        Self {
+            method: IterativeMethod::ConjugateGradient,
+            preconditioner: Preconditioner::Jacobi,
+            tolerance: 1e-10,
+            max_iterations: 1000,
+            initial_guess: None,
+        }
+    }
+}
+
+impl IterativeConfig {
+    pub fn new(method: IterativeMethod) -> Self {
+        Self {
+            method,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_preconditioner(mut self, preconditioner: Preconditioner) -> Self {
+        self.preconditioner = preconditioner;
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    pub fn with_initial_guess(mut self, initial_guess: DVector<f64>) -> Self {
+        self.initial_guess = Some(initial_guess);
+        self
+    }
+}
+// ANCHOR_END: config
+
+// ANCHOR: history
+/// Residual norms `‖b - A x_k‖` of a solve, starting with the initial residual.
+#[derive(Clone, Debug, Default)]
+pub struct ConvergenceHistory {
+    pub residuals: Vec<f64>,
+    /// Norm of the right-hand side, to compute relative residuals
+    pub rhs_norm: f64,
+}
+
+impl ConvergenceHistory {
+    /// Number of iterations performed.
+    pub fn iterations(&self) -> usize {
+        self.residuals.len().saturating_sub(1)
+    }
+
+    /// Last residual norm divided by the norm of the right-hand side.
+    pub fn relative_residual(&self) -> f64 {
+        match self.residuals.last() {
+            Some(r) if self.rhs_norm > 0.0 => r / self.rhs_norm,
+            _ => 0.0,
+        }
+    }
+}
+// ANCHOR_END: history
+
+// ANCHOR: apply_preconditioner
+/// Preconditioner applying `z = M⁻¹ r`, for preconditioners that are not built from the
+/// matrix alone, such as geometric multigrid.
+///
+/// `M` must be symmetric positive definite to be used with the conjugate gradient.
+pub trait ApplyPreconditioner {
+    fn apply(&self, r: &DVector<f64>) -> DVector<f64>;
+}
+// ANCHOR_END: apply_preconditioner
+
+/// Preconditioner built from the system matrix, applying `z = M⁻¹ r`.
+pub(crate) enum PreconditionerOp {
+    Identity,
+    Jacobi(DVector<f64>),
+    Ssor {
+        a: CsrMatrix<f64>,
+        diag: Vec<f64>,
+        omega: f64,
+    },
+    /// Rows of the lower triangular factor, the diagonal entry last
+    IncompleteCholesky(CsrMatrix<f64>),
+    Multigrid(Box<Multigrid>),
+}
+
+/// Diagonal of the matrix, failing on zero entries.
+pub(crate) fn diagonal(a: &CsrMatrix<f64>) -> Result<Vec<f64>, PoissonError> {
+    let mut diag = vec![0.0; a.nrows()];
+    for (i, d) in diag.iter_mut().enumerate() {
+        let row = a.row(i);
+        if let Ok(pos) = row.col_indices().binary_search(&i) {
+            *d = row.values()[pos];
+        }
+        if *d == 0.0 {
+            return Err(PoissonError::PreconditionerBreakdown { row: i });
+        }
+    }
+    Ok(diag)
+}
+
+// ANCHOR: incomplete_cholesky
+/// IC(0) factorization `A ≈ L Lᵀ`, where `L` has the sparsity pattern of the lower
+/// triangle of `A`.
+fn incomplete_cholesky(a: &CsrMatrix<f64>) -> Result<CsrMatrix<f64>, PoissonError> {
+    let n = a.nrows();
+    let mut row_offsets = Vec::with_capacity(n + 1);
+    let mut col_indices = Vec::new();
+    let mut values: Vec<f64> = Vec::new();
+    row_offsets.push(0);
+    for i in 0..n {
+        let row = a.row(i);
+        let start = col_indices.len();
+        for (&k, &a_ik) in row.col_indices().iter().zip(row.values()) {
+            if k == i {
+                let pivot = a_ik - values[start..].iter().map(|l| l * l).sum::<f64>();
+                if pivot <= 0.0 {
+                    return Err(PoissonError::PreconditionerBreakdown { row: i });
+                }
+                col_indices.push(i);
+                values.push(pivot.sqrt());
+                break;
+            }
+            // l_ik = (a_ik - Σ_{j<k} l_ij l_kj) / l_kk, the sum running over the common
+            // pattern of rows i and k of L.
+            let mut sum = 0.0;
+            let (mut p, mut q) = (start, row_offsets[k]);
+            let end_k = row_offsets[k + 1] - 1;
+            while p < col_indices.len() && q < end_k {
+                match col_indices[p].cmp(&col_indices[q]) {
+                    std::cmp::Ordering::Less => p += 1,
+                    std::cmp::Ordering::Greater => q += 1,
+                    std::cmp::Ordering::Equal => {
+                        sum += values[p] * values[q];
+                        p += 1;
+                        q += 1;
+                    }
+                }
+            }
+            col_indices.push(k);
+            values.push((a_ik - sum) / values[end_k]);
+        }
+        if col_indices.last() != Some(&i) {
+            return Err(PoissonError::PreconditionerBreakdown { row: i });
+        }
+        row_offsets.push(col_indices.len());
+    }
+    Ok(
+        CsrMatrix::try_from_csr_data(n, n, row_offsets, col_indices, values)
+            .expect("the factor inherits the valid pattern of the matrix"),
+    )
+}
+// ANCHOR_END: incomplete_cholesky
+
+impl PreconditionerOp {
+    pub(crate) fn new(
+        a: &CsrMatrix<f64>,
+        preconditioner: Preconditioner,
+    ) -> Result<Self, PoissonError> {
+        Ok(match preconditioner {
+            Preconditioner::None => Self::Identity,
+            Preconditioner::Jacobi => {
+                Self::Jacobi(DVector::from_vec(diagonal(a)?).map(|d| 1.0 / d))
+            }
+            Preconditioner::Ssor { omega } => Self::Ssor {
+                a: a.clone(),
+                diag: diagonal(a)?,
+                omega,
+            },
+            Preconditioner::IncompleteCholesky => Self::IncompleteCholesky(incomplete_cholesky(a)?),
+            Preconditioner::SmoothedAggregation(config) => {
+                Self::Multigrid(Box::new(smoothed_aggregation(a, &config)?))
+            }
+        })
+    }
+}
+
+impl ApplyPreconditioner for PreconditionerOp {
+    fn apply(&self, r: &DVector<f64>) -> DVector<f64> {
+        match self {
+            Self::Identity => r.clone(),
+            Self::Jacobi(inv_diag) => r.component_mul(inv_diag),
+            Self::Ssor { a, diag, omega } => {
+                // M = (D + ωL) D⁻¹ (D + ωU) / (ω (2 - ω))
+                let n = r.len();
+                let mut y = DVector::zeros(n);
+                for i in 0..n {
+                    let row = a.row(i);
+                    let mut sum = omega * (2.0 - omega) * r[i];
+                    for (&j, &a_ij) in row.col_indices().iter().zip(row.values()) {
+                        if j < i {
+                            sum -= omega * a_ij * y[j];
+                        }
+                    }
+                    y[i] = sum / diag[i];
+                }
+                let mut z = DVector::zeros(n);
+                for i in (0..n).rev() {
+                    let row = a.row(i);
+                    let mut sum = diag[i] * y[i];
+                    for (&j, &a_ij) in row.col_indices().iter().zip(row.values()) {
+                        if j > i {
+                            sum -= omega * a_ij * z[j];
+                        }
+                    }
+                    z[i] = sum / diag[i];
+                }
+                z
+            }
+            Self::IncompleteCholesky(l) => {
+                // Forward substitution L y = r, then backward substitution Lᵀ z = y.
+                let n = r.len();
+                let mut y = r.clone();
+                for i in 0..n {
+                    let row = l.row(i);
+                    let (diag, off_diag) = row.values().split_last().unwrap();
+                    let mut sum = y[i];
+                    for (&j, &l_ij) in row.col_indices().iter().zip(off_diag) {
+                        sum -= l_ij * y[j];
+                    }
+                    y[i] = sum / diag;
+                }
+                for i in (0..n).rev() {
+                    let row = l.row(i);
+                    let (diag, off_diag) = row.values().split_last().unwrap();
+                    y[i] /= diag;
+                    let z_i = y[i];
+                    for (&j, &l_ij) in row.col_indices().iter().zip(off_diag) {
+                        y[j] -= l_ij * z_i;
+                    }
+                }
+                y
+            }
+            Self::Multigrid(multigrid) => multigrid.apply(r),
+        }
+    }
+}
+
+// ANCHOR: solve_iterative
+/// Solves `A x = b` with the method and preconditioner of the configuration.
+///
+/// Returns the solution and the history of the residual norms. Fails with `NotConverged`
+/// after `max_iterations` iterations, with `SingularSystem` if CG meets a direction of
+/// non-positive energy and with `Breakdown` if GMRES or BiCGStab cannot proceed.
+pub fn solve_iterative(
+    a: &CsrMatrix<f64>,
+    b: &DVector<f64>,
+    config: &IterativeConfig,
+) -> Result<(DVector<f64>, ConvergenceHistory), PoissonError> {
+    let precond = if b.iter().all(|&b_i| b_i == 0.0) {
+        PreconditionerOp::Identity
+    } else {
+        PreconditionerOp::new(a, config.preconditioner)?
+    };
+    solve_iterative_with(a, b, config, &precond)
+}
+
+/// Solves `A x = b` like `solve_iterative`, with a preconditioner supplied by the caller
+/// instead of `config.preconditioner`.
+pub fn solve_iterative_with(
+    a: &CsrMatrix<f64>,
+    b: &DVector<f64>,
+    config: &IterativeConfig,
+    precond: &dyn ApplyPreconditioner,
+) -> Result<(DVector<f64>, ConvergenceHistory), PoissonError> {
+    let x = match &config.initial_guess {
+        Some(x0) if x0.len() != b.len() => {
+            return Err(PoissonError::DimensionMismatch {
+                expected: b.len(),
+                found: x0.len(),
+            });
+        }
+        Some(x0) => x0.clone(),
+        None => DVector::zeros(b.len()),
+    };
+    let mut history = ConvergenceHistory {
+        residuals: Vec::new(),
+        rhs_norm: b.norm(),
+    };
+    if history.rhs_norm == 0.0 {
+        history.residuals.push(0.0);
+        return Ok((DVector::zeros(b.len()), history));
+    }
+
+    let threshold = config.tolerance * history.rhs_norm;
+    let result = match config.method {
+        IterativeMethod::ConjugateGradient => {
+            conjugate_gradient(a, b, x, precond, threshold, config, &mut history)
+        }
+        IterativeMethod::Gmres { restart } => gmres(
+            a,
+            b,
+            x,
+            precond,
+            restart.max(1),
+            threshold,
+            config,
+            &mut history,
+        ),
+        IterativeMethod::BiCgStab => bicgstab(a, b, x, precond, threshold, config, &mut history),
+    };
+    result.map(|x| (x, history))
+}
+// ANCHOR_END: solve_iterative
+
+fn not_converged(history: &ConvergenceHistory) -> PoissonError {
+    PoissonError::NotConverged {
+        iterations: history.iterations(),
+        residual: history.relative_residual(),
+    }
+}
+
+// ANCHOR: conjugate_gradient
+fn conjugate_gradient(
+    a: &CsrMatrix<f64>,
+    b: &DVector<f64>,
+    mut x: DVector<f64>,
+    precond: &dyn ApplyPreconditioner,
+    threshold: f64,
+    config: &IterativeConfig,
+    history: &mut ConvergenceHistory,
+) -> Result<DVector<f64>, PoissonError> {
+    let mut r = b - a * &x;
+    history.residuals.push(r.norm());
+    if r.norm() <= threshold {
+        return Ok(x);
+    }
+    let mut z = precond.apply(&r);
+    let mut p = z.clone();
+    let mut rz = r.dot(&z);
+    for _ in 0..config.max_iterations {
+        let ap = a * &p;
+        let p_ap = p.dot(&ap);
+        if p_ap <= 0.0 {
+            return Err(PoissonError::SingularSystem);
+        }
+        let alpha = rz / p_ap;
+        x.axpy(alpha, &p, 1.0);
+        r.axpy(-alpha, &ap, 1.0);
+        let r_norm = r.norm();
+        history.residuals.push(r_norm);
+        if r_norm <= threshold {
+            return Ok(x);
+        }
+        z = precond.apply(&r);
+        let rz_next = r.dot(&z);
+        p = &z + &p * (rz_next / rz);
+        rz = rz_next;
+    }
+    Err(not_converged(history))
+}
+// ANCHOR_END: conjugate_gradient
+
+// ANCHOR: gmres
+/// Restarted GMRES with right preconditioning, so that the recorded residuals are those
+/// of the original system.
+#[allow(clippy::too_many_arguments)]
+fn gmres(
+    a: &CsrMatrix<f64>,
+    b: &DVector<f64>,
+    mut x: DVector<f64>,
+    precond: &dyn ApplyPreconditioner,
+    restart: usize,
+    threshold: f64,
+    config: &IterativeConfig,
+    history: &mut ConvergenceHistory,
+) -> Result<DVector<f64>, PoissonError> {
+    let r = b - a * &x;
+    let mut beta = r.norm();
+    history.residuals.push(beta);
+    let mut basis = vec![r];
+    while beta > threshold {
+        if history.iterations() >= config.max_iterations {
+            return Err(not_converged(history));
+        }
+        basis.truncate(1);
+        basis[0] /= beta;
+        // Hessenberg matrix stored by columns, reduced to triangular form by Givens
+        // rotations as the columns are computed.
+        let mut h: Vec<Vec<f64>> = Vec::with_capacity(restart);
+        let mut rotations: Vec<(f64, f64)> = Vec::with_capacity(restart);
+        let mut g = vec![beta];
+        while h.len() < restart && history.iterations() < config.max_iterations {
+            let j = h.len();
+            let mut w = a * precond.apply(&basis[j]);
+            let mut column = Vec::with_capacity(j + 2);
+            for v in &basis {
+                let h_ij = w.dot(v);
+                w.axpy(-h_ij, v, 1.0);
+                column.push(h_ij);
+            }
+            let w_norm = w.norm();
+            column.push(w_norm);
+            for (i, &(c, s)) in rotations.iter().enumerate() {
+                let (h_i, h_next) = (column[i], column[i + 1]);
+                column[i] = c * h_i + s * h_next;
+                column[i + 1] = -s * h_i + c * h_next;
+            }
+            let denom = column[j].hypot(column[j + 1]);
+            if denom == 0.0 {
+                return Err(PoissonError::Breakdown {
+                    iterations: history.iterations(),
+                });
+            }
+            let (c, s) = (column[j] / denom, column[j + 1] / denom);
+            column[j] = denom;
+            column.pop();
+            rotations.push((c, s));
+            g.push(-s * g[j]);
+            g[j] *= c;
+            h.push(column);
+            history.residuals.push(g[j + 1].abs());
+            if g[j + 1].abs() <= threshold || w_norm == 0.0 {
+                break;
+            }
+            basis.push(w / w_norm);
+        }
+
+        // Solve the triangular system H y = g and update x += M⁻¹ V y.
+        let k = h.len();
+        let mut y = vec![0.0; k];
+        for i in (0..k).rev() {
+            let mut sum = g[i];
+            for (j, y_j) in y.iter().enumerate().skip(i + 1) {
+                sum -= h[j][i] * y_j;
+            }
+            y[i] = sum / h[i][i];
+        }
+        let mut update = DVector::zeros(x.len());
+        for (v, y_i) in basis.iter().zip(&y) {
+            update.axpy(*y_i, v, 1.0);
+        }
+        x += precond.apply(&update);
+
+        let r = b - a * &x;
+        beta = r.norm();
+        basis.truncate(0);
+        basis.push(r);
+    }
+    Ok(x)
+}
+// ANCHOR_END: gmres
+
+// ANCHOR: bicgstab
+/// Right-preconditioned BiCGStab.
+fn bicgstab(
+    a: &CsrMatrix<f64>,
+    b: &DVector<f64>,
+    mut x: DVector<f64>,
+    precond: &dyn ApplyPreconditioner,
+    threshold: f64,
+    config: &IterativeConfig,
+    history: &mut ConvergenceHistory,
+) -> Result<DVector<f64>, PoissonError> {
+    let mut r = b - a * &x;
+    history.residuals.push(r.norm());
+    if r.norm() <= threshold {
+        return Ok(x);
+    }
+    let r_hat = r.clone();
+    let (mut rho, mut alpha, mut omega) = (1.0, 1.0, 1.0);
+    let mut v = DVector::zeros(x.len());
+    let mut p = DVector::zeros(x.len());
+    let breakdown = |history: &ConvergenceHistory| PoissonError::Breakdown {
+        iterations: history.iterations(),
+    };
+    for _ in 0..config.max_iterations {
+        let rho_next = r_hat.dot(&r);
+        if rho_next == 0.0 {
+            return Err(breakdown(history));
+        }
+        let beta = (rho_next / rho) * (alpha / omega);
+        rho = rho_next;
+        p = &r + (&p - &v * omega) * beta;
+        let p_hat = precond.apply(&p);
+        v = a * &p_hat;
+        let r_hat_v = r_hat.dot(&v);
+        if r_hat_v == 0.0 {
+            return Err(breakdown(history));
+        }
+        alpha = rho / r_hat_v;
+        let s = &r - &v * alpha;
+        if s.norm() <= threshold {
+            x.axpy(alpha, &p_hat, 1.0);
+            history.residuals.push(s.norm());
+            return Ok(x);
+        }
+        let s_hat = precond.apply(&s);
+        let t = a * &s_hat;
+        let t_dot = t.dot(&t);
+        omega = if t_dot > 0.0 { t.dot(&s) / t_dot } else { 0.0 };
+        if omega == 0.0 {
+            return Err(breakdown(history));
+        }
+        x.axpy(alpha, &p_hat, 1.0);
+        x.axpy(omega, &s_hat, 1.0);
+        r = s - &t * omega;
+        history.residuals.push(r.norm());
+        if r.norm() <= threshold {
+            return Ok(x);
+        }
+    }
+    Err(not_converged(history))
+}
+// ANCHOR_END: bicgstab
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coefficient::Diffusion;
+    use crate::element::ElementType;
+    use crate::forms::ConvectionDiffusionReaction;
+    use crate::generators::unit_square;
+    use crate::solver::{apply_dirichlet_sparse, assemble_form_sparse, assemble_system_sparse};
+    use nalgebra::{DMatrix, Vector2};
+
+    /// Poisson system with homogeneous Dirichlet conditions and its exact algebraic solution.
+    fn poisson_system(n: usize) -> (CsrMatrix<f64>, DVector<f64>, DVector<f64>) {
+        let (mesh, boundary) = unit_square(n, ElementType::P1);
+        let (mut a, mut b) = assemble_system_sparse(&mesh, &|x, y| x * y + 1.0);
+        apply_dirichlet_sparse(&mut a, &mut b, &boundary.all(), &mesh, |_, _| 0.0);
+        let u = DMatrix::from(&a).cholesky().unwrap().solve(&b);
+        (a, b, u)
+    }
+
+    #[test]
+    fn test_preconditioned_cg() {
+        let (a, b, u) = poisson_system(16);
+        let mut iterations = Vec::new();
+        for preconditioner in [
+            Preconditioner::None,
+            Preconditioner::Jacobi,
+            Preconditioner::Ssor { omega: 1.5 },
+            Preconditioner::IncompleteCholesky,
+        ] {
+            let config = IterativeConfig::new(IterativeMethod::ConjugateGradient)
+                .with_preconditioner(preconditioner);
+            let (x, history) = solve_iterative(&a, &b, &config).unwrap();
+            assert!((x - &u).norm() < 1e-8 * u.norm());
+            assert!(history.relative_residual() <= 1e-10);
+            assert_eq!(history.residuals.len(), history.iterations() + 1);
+            iterations.push(history.iterations());
+        }
+        // SSOR and IC(0) need fewer iterations than plain CG.
+        assert!(iterations[2] < iterations[0]);
+        assert!(iterations[3] < iterations[0]);
+    }
+
+    #[test]
+    fn test_incomplete_cholesky_is_exact_for_tridiagonal() {
+        // IC(0) has no dropped fill-in on a tridiagonal matrix, so CG converges at once.
+        let n = 10;
+        let mut dense = DMatrix::zeros(n, n);
+        for i in 0..n {
+            dense[(i, i)] = 2.0;
+            if i + 1 < n {
+                dense[(i, i + 1)] = -1.0;
+                dense[(i + 1, i)] = -1.0;
+            }
+        }
+        let a = CsrMatrix::from(&dense);
+        let b = DVector::from_element(n, 1.0);
+        let config =
+            IterativeConfig::default().with_preconditioner(Preconditioner::IncompleteCholesky);
+        let (x, history) = solve_iterative(&a, &b, &config).unwrap();
+        assert_eq!(history.iterations(), 1);
+        assert!((dense * x - b).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_non_symmetric_solvers() {
+        let (mesh, boundary) = unit_square(12, ElementType::Q1);
+        let form = ConvectionDiffusionReaction::new(Diffusion::Constant(0.05))
+            .with_velocity(|_, _| Vector2::new(1.0, 0.5));
+        let (mut a, mut b) = assemble_form_sparse(&mesh, &form, &|_, _| 1.0).unwrap();
+        apply_dirichlet_sparse(&mut a, &mut b, &boundary.all(), &mesh, |_, _| 0.0);
+        let u = DMatrix::from(&a).lu().solve(&b).unwrap();
+
+        for method in [
+            IterativeMethod::Gmres { restart: 20 },
+            IterativeMethod::BiCgStab,
+        ] {
+            for preconditioner in [Preconditioner::None, Preconditioner::Ssor { omega: 1.0 }] {
+                let config = IterativeConfig::new(method).with_preconditioner(preconditioner);
+                let (x, history) = solve_iterative(&a, &b, &config).unwrap();
+                assert!(
+                    (&a * &x - &b).norm() <= 1e-10 * b.norm() * 1.01,
+                    "{method:?}"
+                );
+                assert!((x - &u).norm() < 1e-7 * u.norm(), "{method:?}");
+                assert!(history.relative_residual() <= 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn test_initial_guess_and_errors() {
+        let (a, b, u) = poisson_system(8);
+
+        // Starting from the solution, no iteration is needed.
+        let config = IterativeConfig::default().with_initial_guess(u.clone());
+        let (_, history) = solve_iterative(&a, &b, &config).unwrap();
+        assert_eq!(history.iterations(), 0);
+
+        let config = IterativeConfig::default().with_initial_guess(DVector::zeros(3));
+        assert!(matches!(
+            solve_iterative(&a, &b, &config),
+            Err(PoissonError::DimensionMismatch { found: 3, .. })
+        ));
+
+        let config =
+            IterativeConfig::new(IterativeMethod::Gmres { restart: 2 }).with_max_iterations(5);
+        match solve_iterative(&a, &b, &config) {
+            Err(PoissonError::NotConverged {
+                iterations,
+                residual,
+            }) => {
+                assert_eq!(iterations, 5);
+                assert!(residual > 1e-10);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let indefinite =
+            CsrMatrix::from(&DMatrix::from_diagonal(&DVector::from_vec(vec![1.0, -1.0])));
+        let rhs = DVector::from_vec(vec![1.0, 1.0]);
+        let config = IterativeConfig::default().with_preconditioner(Preconditioner::None);
+        assert!(matches!(
+            solve_iterative(&indefinite, &rhs, &config),
+            Err(PoissonError::SingularSystem)
+        ));
+        let config =
+            IterativeConfig::default().with_preconditioner(Preconditioner::IncompleteCholesky);
+        assert!(matches!(
+            solve_iterative(&indefinite, &rhs, &config),
+            Err(PoissonError::PreconditionerBreakdown { row: 1 })
+        ));
+    }
+}
+// ANCHOR_END: tests
@@ -3,12 +3,33 @@
 //!
 //! The crate includes modules for elements, mesh, quadrature rules, and solvers.
 
+pub mod adaptive;
+pub mod amg;
+pub mod coefficient;
+pub mod convergence;
+pub mod direct;
+pub mod eigen;
 pub mod element;
+pub mod forms;
+pub mod generators;
+pub mod gmsh;
+pub mod heat;
+pub mod iterative;
 pub mod mesh;
+pub mod multigrid;
+pub mod postprocess;
 pub mod quadrature;
+pub mod quality;
+pub mod refinement;
 pub mod solver;
+pub mod vtk;
 
-pub use solver::{assemble_and_solve_dense, assemble_and_solve_sparse};
+pub use direct::Reordering;
+pub use iterative::{ApplyPreconditioner, IterativeConfig, IterativeMethod, Preconditioner};
+pub use solver::{
+    PoissonError, assemble_and_solve_cholesky, assemble_and_solve_dense,
+    assemble_and_solve_iterative, assemble_and_solve_sparse,
+};
 
 pub use mesh::Mesh2d;
 pub use nalgebra::DVector;
@@ -18,6 +39,10 @@ pub use nalgebra::DVector;
 pub enum SolverType {
     Dense,
     Sparse,
+    /// Sparse system solved with the given iterative method and preconditioner
+    Iterative(IterativeConfig),
+    /// Sparse Cholesky factorization after renumbering the unknowns
+    SparseCholesky(Reordering),
 }
 // ANCHOR_END: solver_type
 
@@ -29,10 +54,11 @@ pub enum SolverType {
 /// - `boundary_nodes`: Indices of the nodes on the boundary.
 /// - `boundary_fn`: Function defining the boundary condition.
 /// - `source_fn`: Function defining the source term.
-/// - `solver_type`: Type of solver to use (Dense or Sparse).
+/// - `solver_type`: Type of solver to use (Dense, Sparse, Iterative or SparseCholesky).
 ///
 /// Returns:
-/// - A vector containing the solution at the mesh nodes.
+/// - A vector containing the solution at the mesh nodes, or an error if the mesh is
+///   invalid, a boundary node is out of range or the linear system cannot be solved.
 // ANCHOR: solve_poisson_2d
 pub fn solve_poisson_2d<F>(
     mesh: &Mesh2d,
@@ -40,15 +66,22 @@ pub fn solve_poisson_2d<F>(
     boundary_fn: &F,
     source_fn: &F,
     solver_type: SolverType,
-) -> DVector<f64>
+) -> Result<DVector<f64>, PoissonError>
 where
-    F: Fn(f64, f64) -> f64,
+    F: Fn(f64, f64) -> f64 + Sync,
 {
     match solver_type {
         SolverType::Dense => assemble_and_solve_dense(mesh, boundary_nodes, boundary_fn, source_fn),
         SolverType::Sparse => {
             assemble_and_solve_sparse(mesh, boundary_nodes, boundary_fn, source_fn)
         }
+        SolverType::Iterative(config) => {
+            assemble_and_solve_iterative(mesh, boundary_nodes, boundary_fn, source_fn, &config)
+                .map(|(u, _)| u)
+        }
+        SolverType::SparseCholesky(reordering) => {
+            assemble_and_solve_cholesky(mesh, boundary_nodes, boundary_fn, source_fn, reordering)
+        }
     }
 }
 // ANCHOR_END: solve_poisson_2d
@@ -1,28 +1,105 @@
 use crate::element::{Element, ElementType};
+use crate::quality::jacobian_determinants;
 use nalgebra::Point2;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// ANCHOR: error_enum
+/// Defects detected by `Mesh2d::validate`, or preventing an operation on the mesh.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum MeshError {
+    #[error("the mesh has no element")]
+    Empty,
+    #[error("vertex {0} has non-finite coordinates")]
+    NonFiniteVertex(usize),
+    #[error("element {element} has {found} nodes, {expected} expected")]
+    WrongNodeCount {
+        element: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("element {element} references vertex {vertex}, which is out of range")]
+    VertexOutOfRange { element: usize, vertex: usize },
+    #[error("element {element} references vertex {vertex} more than once")]
+    RepeatedVertex { element: usize, vertex: usize },
+    #[error("vertex {0} does not belong to any element")]
+    UnusedVertex(usize),
+    #[error("element {0} is degenerate (zero area)")]
+    DegenerateElement(usize),
+    #[error("element {0} is inverted (clockwise or self-intersecting)")]
+    InvertedElement(usize),
+    #[error("element {element} has type {element_type:?}, which this operation does not support")]
+    UnsupportedElementType {
+        element: usize,
+        element_type: ElementType,
+    },
+}
+// ANCHOR_END: error_enum
 
 // ANCHOR: mesh_struct
 #[derive(Clone, Debug)]
 pub struct Mesh2d {
     vertices: Vec<Point2<f64>>,
     elements: Vec<Element>,
-    element_type: ElementType,
+    element_types: Vec<ElementType>,
+    boundary_groups: BTreeMap<String, Vec<Edge>>,
+    regions: BTreeMap<String, Vec<usize>>,
 }
 // ANCHOR_END: mesh_struct
 
+// ANCHOR: edge_struct
+/// An edge stores the global indices of its two end nodes.
+///
+/// Edges returned by the mesh are oriented like in their (first) owning element, which means
+/// that boundary edges run counter-clockwise around the domain.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Edge {
+    pub indices: Vec<usize>,
+}
+
+impl Edge {
+    /// Orientation-independent key identifying the edge by its end nodes.
+    pub(crate) fn key(&self) -> (usize, usize) {
+        let (a, b) = (self.indices[0], self.indices[1]);
+        (a.min(b), a.max(b))
+    }
+}
+// ANCHOR_END: edge_struct
+
 // ANCHOR: mesh_impl
 impl Mesh2d {
+    /// Creates a mesh whose elements all have the type `element_type`.
     pub fn new(
         vertices: Vec<Point2<f64>>,
         elements: Vec<Element>,
         element_type: ElementType,
     ) -> Self {
+        let element_types = vec![element_type; elements.len()];
+        Self::new_mixed(vertices, elements, element_types)
+    }
+
+    /// Creates a mesh mixing several element types, `element_types[e]` being the type of
+    /// element `e`, e.g. triangles and quadrangles.
+    ///
+    /// Panics if there is not exactly one type per element.
+    pub fn new_mixed(
+        vertices: Vec<Point2<f64>>,
+        elements: Vec<Element>,
+        element_types: Vec<ElementType>,
+    ) -> Self {
+        assert_eq!(
+            elements.len(),
+            element_types.len(),
+            "every element needs an element type"
+        );
         Self {
             vertices,
             elements,
-            element_type,
+            element_types,
+            boundary_groups: BTreeMap::new(),
+            regions: BTreeMap::new(),
         }
     }
+
     pub fn vertices(&self) -> &[Point2<f64>] {
         &self.vertices
     }
@@ -31,12 +108,250 @@ impl Mesh2d {
         &self.elements
     }
 
-    pub fn element_type(&self) -> &ElementType {
-        &self.element_type
+    /// Returns the element type shared by all elements, or `None` for a mixed mesh.
+    pub fn element_type(&self) -> Option<&ElementType> {
+        let first = self.element_types.first()?;
+        self.element_types
+            .iter()
+            .all(|t| t == first)
+            .then_some(first)
+    }
+
+    /// Returns the type of every element.
+    pub fn element_types(&self) -> &[ElementType] {
+        &self.element_types
+    }
+
+    /// Returns the type of element `element`.
+    pub fn element_type_of(&self, element: usize) -> &ElementType {
+        &self.element_types[element]
     }
 }
 // ANCHOR_END: mesh_impl
 
+// ANCHOR: validation
+impl Mesh2d {
+    /// Checks that the mesh can be used by the solver, and returns the first defect found.
+    ///
+    /// Connectivity is checked first (node counts, vertex indices, unused vertices), then
+    /// the geometry: the Jacobian determinant must be positive at every element node.
+    pub fn validate(&self) -> Result<(), MeshError> {
+        if self.elements.is_empty() {
+            return Err(MeshError::Empty);
+        }
+        if let Some(i) = self
+            .vertices
+            .iter()
+            .position(|v| !(v.x.is_finite() && v.y.is_finite()))
+        {
+            return Err(MeshError::NonFiniteVertex(i));
+        }
+
+        let mut used = vec![false; self.vertices.len()];
+        for (e, (element, element_type)) in
+            self.elements.iter().zip(&self.element_types).enumerate()
+        {
+            let expected = element_type.reference_element().num_nodes();
+            if element.indices.len() != expected {
+                return Err(MeshError::WrongNodeCount {
+                    element: e,
+                    expected,
+                    found: element.indices.len(),
+                });
+            }
+            let mut seen = HashSet::new();
+            for &vertex in &element.indices {
+                if vertex >= self.vertices.len() {
+                    return Err(MeshError::VertexOutOfRange { element: e, vertex });
+                }
+                if !seen.insert(vertex) {
+                    return Err(MeshError::RepeatedVertex { element: e, vertex });
+                }
+                used[vertex] = true;
+            }
+        }
+        if let Some(i) = used.iter().position(|u| !u) {
+            return Err(MeshError::UnusedVertex(i));
+        }
+
+        for e in 0..self.elements.len() {
+            // Determinants scale like the element area, compare them to its diameter squared.
+            let h2 = self.elements[e]
+                .indices
+                .iter()
+                .flat_map(|&i| {
+                    self.elements[e]
+                        .indices
+                        .iter()
+                        .map(move |&j| (self.vertices[i] - self.vertices[j]).norm_squared())
+                })
+                .fold(0.0, f64::max);
+            let tolerance = 1e-12 * h2;
+            let determinants = jacobian_determinants(self, e);
+            if determinants.iter().any(|&det| det < -tolerance) {
+                return Err(MeshError::InvertedElement(e));
+            }
+            if determinants.iter().any(|&det| det <= tolerance) {
+                return Err(MeshError::DegenerateElement(e));
+            }
+        }
+        Ok(())
+    }
+}
+// ANCHOR_END: validation
+
+// ANCHOR: mesh_topology
+impl Mesh2d {
+    /// Returns the edges of every element, in element order, together with the number of
+    /// elements sharing each of them.
+    fn edges_with_multiplicity(&self) -> Vec<(Edge, usize)> {
+        let mut edges: Vec<(Edge, usize)> = Vec::new();
+        let mut positions: HashMap<(usize, usize), usize> = HashMap::new();
+        for (element, element_type) in self.elements.iter().zip(&self.element_types) {
+            for local_edge in &element_type.local_edges() {
+                let edge = Edge {
+                    indices: local_edge.iter().map(|&k| element.indices[k]).collect(),
+                };
+                match positions.get(&edge.key()) {
+                    Some(&pos) => edges[pos].1 += 1,
+                    None => {
+                        positions.insert(edge.key(), edges.len());
+                        edges.push((edge, 1));
+                    }
+                }
+            }
+        }
+        edges
+    }
+
+    /// Returns all edges of the mesh, each edge being listed once.
+    pub fn edges(&self) -> Vec<Edge> {
+        self.edges_with_multiplicity()
+            .into_iter()
+            .map(|(edge, _)| edge)
+            .collect()
+    }
+
+    /// Returns the boundary edges, i.e. the edges owned by exactly one element.
+    pub fn boundary_edges(&self) -> Vec<Edge> {
+        self.edges_with_multiplicity()
+            .into_iter()
+            .filter(|(_, count)| *count == 1)
+            .map(|(edge, _)| edge)
+            .collect()
+    }
+
+    /// Returns the nodes lying on the boundary, sorted and without duplicates.
+    pub fn boundary_nodes(&self) -> Vec<usize> {
+        nodes_of(&self.boundary_edges())
+    }
+}
+// ANCHOR_END: mesh_topology
+
+// ANCHOR: boundary_groups
+impl Mesh2d {
+    /// Adds the given edges to the boundary group `name`, creating the group if needed.
+    ///
+    /// Panics if one of the edges is not a boundary edge of the mesh.
+    pub fn add_boundary_group(&mut self, name: &str, edges: Vec<Edge>) {
+        let boundary: HashSet<(usize, usize)> =
+            self.boundary_edges().iter().map(Edge::key).collect();
+        for edge in &edges {
+            assert!(
+                boundary.contains(&edge.key()),
+                "edge {:?} is not a boundary edge",
+                edge.indices
+            );
+        }
+        self.insert_boundary_group(name, edges);
+    }
+
+    /// Adds to the boundary group `name` every boundary edge whose nodes all satisfy
+    /// `predicate`, and returns the number of tagged edges.
+    ///
+    /// For instance, `mesh.tag_boundary("inlet", |p| p.x == 0.0)` tags the edges lying on
+    /// the line `x = 0`.
+    pub fn tag_boundary<P>(&mut self, name: &str, predicate: P) -> usize
+    where
+        P: Fn(&Point2<f64>) -> bool,
+    {
+        let edges: Vec<Edge> = self
+            .boundary_edges()
+            .into_iter()
+            .filter(|edge| edge.indices.iter().all(|&i| predicate(&self.vertices[i])))
+            .collect();
+        let count = edges.len();
+        self.insert_boundary_group(name, edges);
+        count
+    }
+
+    fn insert_boundary_group(&mut self, name: &str, edges: Vec<Edge>) {
+        let group = self.boundary_groups.entry(name.to_string()).or_default();
+        let mut keys: HashSet<(usize, usize)> = group.iter().map(Edge::key).collect();
+        for edge in edges {
+            if keys.insert(edge.key()) {
+                group.push(edge);
+            }
+        }
+    }
+
+    /// Returns the names of the boundary groups.
+    pub fn boundary_group_names(&self) -> impl Iterator<Item = &str> {
+        self.boundary_groups.keys().map(|name| name.as_str())
+    }
+
+    /// Returns the edges of the boundary group `name`, if it exists.
+    pub fn boundary_group(&self, name: &str) -> Option<&[Edge]> {
+        self.boundary_groups.get(name).map(|edges| edges.as_slice())
+    }
+
+    /// Returns the nodes of the boundary group `name`, sorted and without duplicates.
+    ///
+    /// The result can be passed as `boundary_nodes` to the solver.
+    pub fn boundary_group_nodes(&self, name: &str) -> Option<Vec<usize>> {
+        self.boundary_group(name).map(nodes_of)
+    }
+}
+
+// ANCHOR: regions
+impl Mesh2d {
+    /// Adds the given elements to the region `name`, creating the region if needed.
+    ///
+    /// Regions group elements sharing a material or a source term. Panics if an element
+    /// index is out of range.
+    pub fn add_region(&mut self, name: &str, elements: Vec<usize>) {
+        let num_elements = self.elements.len();
+        let region = self.regions.entry(name.to_string()).or_default();
+        let mut seen: HashSet<usize> = region.iter().copied().collect();
+        for e in elements {
+            assert!(e < num_elements, "element {e} is out of range");
+            if seen.insert(e) {
+                region.push(e);
+            }
+        }
+    }
+
+    /// Returns the names of the regions.
+    pub fn region_names(&self) -> impl Iterator<Item = &str> {
+        self.regions.keys().map(|name| name.as_str())
+    }
+
+    /// Returns the element indices of the region `name`, if it exists.
+    pub fn region(&self, name: &str) -> Option<&[usize]> {
+        self.regions.get(name).map(|elements| elements.as_slice())
+    }
+}
+// ANCHOR_END: regions
+
+/// Collects the nodes of a set of edges, sorted and without duplicates.
+fn nodes_of(edges: &[Edge]) -> Vec<usize> {
+    let mut nodes: Vec<usize> = edges.iter().flat_map(|e| e.indices.clone()).collect();
+    nodes.sort_unstable();
+    nodes.dedup();
+    nodes
+}
+// ANCHOR_END: boundary_groups
+
 // ANCHOR: tests
 #[cfg(test)]
 mod tests {
@@ -56,12 +371,206 @@ mod tests {
         let mesh = Mesh2d {
             vertices,
             elements,
-            element_type: ElementType::Q1,
+            element_types: vec![ElementType::Q1],
+            boundary_groups: BTreeMap::new(),
+            regions: BTreeMap::new(),
         };
 
         assert_eq!(mesh.vertices().len(), 4);
         assert_eq!(mesh.elements().len(), 1);
-        assert_eq!(*mesh.element_type(), ElementType::Q1);
+        assert_eq!(mesh.element_type(), Some(&ElementType::Q1));
+    }
+
+    /// Two unit squares side by side, [0, 2] x [0, 1].
+    fn two_quads() -> Mesh2d {
+        let vertices = vec![
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 0.0),
+            Point2::new(2.0, 0.0),
+            Point2::new(0.0, 1.0),
+            Point2::new(1.0, 1.0),
+            Point2::new(2.0, 1.0),
+        ];
+        let elements = vec![
+            Element {
+                indices: vec![0, 1, 4, 3],
+            },
+            Element {
+                indices: vec![1, 2, 5, 4],
+            },
+        ];
+        Mesh2d::new(vertices, elements, ElementType::Q1)
+    }
+
+    /// A unit square and two triangles, [0, 2] x [0, 1].
+    fn quad_and_triangles() -> Mesh2d {
+        let quads = two_quads();
+        let elements = vec![
+            quads.elements[0].clone(),
+            Element {
+                indices: vec![1, 2, 5],
+            },
+            Element {
+                indices: vec![1, 5, 4],
+            },
+        ];
+        let element_types = vec![ElementType::Q1, ElementType::P1, ElementType::P1];
+        Mesh2d::new_mixed(quads.vertices, elements, element_types)
+    }
+
+    #[test]
+    fn test_mixed_mesh() {
+        let mesh = quad_and_triangles();
+        assert_eq!(mesh.element_type(), None);
+        assert_eq!(mesh.element_type_of(0), &ElementType::Q1);
+        assert_eq!(mesh.element_type_of(2), &ElementType::P1);
+        assert_eq!(two_quads().element_type(), Some(&ElementType::Q1));
+
+        // The quadrangle and the triangles share the edge (1, 4).
+        assert_eq!(mesh.edges().len(), 8);
+        assert_eq!(mesh.boundary_edges().len(), 6);
+        assert_eq!(mesh.boundary_nodes(), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "every element needs an element type")]
+    fn test_mixed_mesh_missing_types() {
+        let mesh = two_quads();
+        Mesh2d::new_mixed(mesh.vertices, mesh.elements, vec![ElementType::Q1]);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(two_quads().validate(), Ok(()));
+        assert_eq!(quad_and_triangles().validate(), Ok(()));
+
+        // The vertices of `two_quads`, split into triangles in various ways.
+        let triangles = |elements: &[[usize; 3]]| {
+            let elements = elements
+                .iter()
+                .map(|indices| Element {
+                    indices: indices.to_vec(),
+                })
+                .collect();
+            Mesh2d::new(two_quads().vertices, elements, ElementType::P1)
+        };
+        assert_eq!(
+            triangles(&[[0, 1, 4], [0, 4, 3], [1, 2, 5], [1, 5, 4]]).validate(),
+            Ok(())
+        );
+        let cases = [
+            (vec![], MeshError::Empty),
+            (
+                vec![[0, 1, 4], [0, 4, 3], [1, 2, 5], [1, 5, 7]],
+                MeshError::VertexOutOfRange {
+                    element: 3,
+                    vertex: 7,
+                },
+            ),
+            (
+                vec![[0, 1, 1], [0, 4, 3], [1, 2, 5], [1, 5, 4]],
+                MeshError::RepeatedVertex {
+                    element: 0,
+                    vertex: 1,
+                },
+            ),
+            (vec![[0, 1, 4], [0, 4, 3]], MeshError::UnusedVertex(2)),
+            (
+                vec![[0, 1, 4], [0, 4, 3], [1, 5, 2], [1, 5, 4]],
+                MeshError::InvertedElement(2),
+            ),
+            (
+                vec![[0, 1, 2], [0, 4, 3], [1, 2, 5], [1, 5, 4]],
+                MeshError::DegenerateElement(0),
+            ),
+        ];
+        for (elements, expected) in cases {
+            assert_eq!(triangles(&elements).validate(), Err(expected));
+        }
+
+        let mut wrong_type = two_quads();
+        wrong_type.element_types[1] = ElementType::P1;
+        assert_eq!(
+            wrong_type.validate(),
+            Err(MeshError::WrongNodeCount {
+                element: 1,
+                expected: 3,
+                found: 4,
+            })
+        );
+
+        let mut nan = two_quads();
+        nan.vertices[2].x = f64::NAN;
+        assert_eq!(nan.validate(), Err(MeshError::NonFiniteVertex(2)));
+    }
+
+    #[test]
+    fn test_topology() {
+        let mesh = two_quads();
+        assert_eq!(mesh.edges().len(), 7);
+
+        let boundary = mesh.boundary_edges();
+        assert_eq!(boundary.len(), 6);
+        assert!(!boundary.iter().any(|e| e.key() == (1, 4)));
+        assert_eq!(mesh.boundary_nodes(), vec![0, 1, 2, 3, 4, 5]);
+
+        // Boundary edges run counter-clockwise: the domain lies on their left.
+        for edge in &boundary {
+            let a = mesh.vertices()[edge.indices[0]];
+            let b = mesh.vertices()[edge.indices[1]];
+            let mid = nalgebra::center(&a, &b);
+            let tangent = b - a;
+            let to_center = Point2::new(1.0, 0.5) - mid;
+            assert!(tangent.x * to_center.y - tangent.y * to_center.x > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_boundary_groups() {
+        let mut mesh = two_quads();
+        assert_eq!(mesh.tag_boundary("inlet", |p| p.x == 0.0), 1);
+        assert_eq!(mesh.tag_boundary("wall", |p| p.y == 0.0), 2);
+        assert_eq!(mesh.tag_boundary("wall", |p| p.y == 1.0), 2);
+        mesh.add_boundary_group(
+            "outlet",
+            vec![Edge {
+                indices: vec![2, 5],
+            }],
+        );
+
+        let names: Vec<&str> = mesh.boundary_group_names().collect();
+        assert_eq!(names, vec!["inlet", "outlet", "wall"]);
+        assert_eq!(mesh.boundary_group_nodes("inlet"), Some(vec![0, 3]));
+        assert_eq!(mesh.boundary_group_nodes("outlet"), Some(vec![2, 5]));
+        assert_eq!(mesh.boundary_group("wall").map(|g| g.len()), Some(4));
+        assert_eq!(mesh.boundary_group("missing"), None);
+
+        // Tagging twice does not duplicate edges.
+        mesh.tag_boundary("inlet", |p| p.x == 0.0);
+        assert_eq!(mesh.boundary_group("inlet").map(|g| g.len()), Some(1));
+    }
+
+    #[test]
+    fn test_regions() {
+        let mut mesh = two_quads();
+        mesh.add_region("copper", vec![0]);
+        mesh.add_region("steel", vec![1]);
+        mesh.add_region("copper", vec![0]);
+        assert_eq!(mesh.region("copper"), Some(&[0][..]));
+        assert_eq!(mesh.region_names().count(), 2);
+        assert_eq!(mesh.region("air"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a boundary edge")]
+    fn test_add_interior_edge_to_group() {
+        let mut mesh = two_quads();
+        mesh.add_boundary_group(
+            "interface",
+            vec![Edge {
+                indices: vec![1, 4],
+            }],
+        );
     }
 }
 // ANCHOR_END: tests
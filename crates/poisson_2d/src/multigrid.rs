@@ -0,0 +1,468 @@
+//! Module that implements a multigrid solver for symmetric positive definite systems.
+//!
+//! The hierarchy is described by prolongation operators from each level to the next finer
+//! one. Coarse operators are built with the Galerkin product `Pᵀ A P`, and the coarsest
+//! system is solved with the sparse Cholesky factorization. For geometric multigrid, the
+//! prolongations come from uniform refinements of a coarse `Mesh2d`. A multigrid cycle can
+//! be iterated on its own or used as a preconditioner of the conjugate gradient.
+use crate::direct::{Reordering, SparseCholesky};
+use crate::iterative::{ApplyPreconditioner, ConvergenceHistory, diagonal};
+use crate::mesh::{Mesh2d, MeshError};
+use crate::solver::{PoissonError, apply_dirichlet_sparse, assemble_system_sparse, check_problem};
+use nalgebra::DVector;
+use nalgebra_sparse::CsrMatrix;
+
+// ANCHOR: config
+/// Smoother applied on every level but the coarsest one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Smoother {
+    /// Damped Jacobi with damping factor `0 < omega <= 1`
+    Jacobi { omega: f64 },
+    /// Gauss-Seidel, sweeping forward before the coarse correction and backward after it so
+    /// that the cycle stays symmetric
+    GaussSeidel,
+}
+
+/// Number of coarse corrections per level: one for a V-cycle, two for a W-cycle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CycleType {
+    V,
+    W,
+}
+
+/// Settings of the multigrid cycle and of the standalone solver.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MultigridConfig {
+    pub cycle: CycleType,
+    pub smoother: Smoother,
+    /// Number of smoothing sweeps before the coarse correction
+    pub pre_smoothing: usize,
+    /// Number of smoothing sweeps after the coarse correction
+    pub post_smoothing: usize,
+    /// Relative tolerance on the residual norm of the standalone solver
+    pub tolerance: f64,
+    /// Maximum number of cycles of the standalone solver
+    pub max_cycles: usize,
+}
+
+impl Default for MultigridConfig {
+    /// V-cycle with two Gauss-Seidel sweeps before and after the coarse correction, a
+    /// relative tolerance of `1e-10` and at most 100 cycles.
+    fn default() -> Self {
+        Self {
+            cycle: CycleType::V,
+            smoother: Smoother::GaussSeidel,
+            pre_smoothing: 2,
+            post_smoothing: 2,
+            tolerance: 1e-10,
+            max_cycles: 100,
+        }
+    }
+}
+
+impl MultigridConfig {
+    pub fn new(cycle: CycleType, smoother: Smoother) -> Self {
+        Self {
+            cycle,
+            smoother,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_smoothing(mut self, pre_smoothing: usize, post_smoothing: usize) -> Self {
+        self.pre_smoothing = pre_smoothing;
+        self.post_smoothing = post_smoothing;
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_max_cycles(mut self, max_cycles: usize) -> Self {
+        self.max_cycles = max_cycles;
+        self
+    }
+}
+// ANCHOR_END: config
+
+/// Operator and transfer matrices of one level above the coarsest one.
+struct Level {
+    a: CsrMatrix<f64>,
+    diag: Vec<f64>,
+    /// Prolongation from the next coarser level
+    prolongation: CsrMatrix<f64>,
+    restriction: CsrMatrix<f64>,
+}
+
+// ANCHOR: multigrid
+/// Multigrid hierarchy, from the finest level to the coarsest one.
+pub struct Multigrid {
+    levels: Vec<Level>,
+    coarse: SparseCholesky,
+    config: MultigridConfig,
+}
+
+impl Multigrid {
+    /// Builds the hierarchy of the fine matrix `a`.
+    ///
+    /// `prolongations[k]` maps level `k` to level `k + 1`, level 0 being the coarsest and
+    /// the last prolongation having as many rows as `a`. Fails with `DimensionMismatch` if
+    /// the sizes do not chain, and with `SingularSystem` if the coarsest operator cannot be
+    /// factored.
+    pub fn new(
+        a: &CsrMatrix<f64>,
+        prolongations: &[CsrMatrix<f64>],
+        config: MultigridConfig,
+    ) -> Result<Self, PoissonError> {
+        let mut operators = vec![a.clone()];
+        for prolongation in prolongations.iter().rev() {
+            let a = operators.last().unwrap();
+            if prolongation.nrows() != a.nrows() {
+                return Err(PoissonError::DimensionMismatch {
+                    expected: a.nrows(),
+                    found: prolongation.nrows(),
+                });
+            }
+            operators.push(&prolongation.transpose() * &(a * prolongation));
+        }
+        let prolongations = prolongations.iter().rev().cloned().collect();
+        Self::from_operators(operators, prolongations, config)
+    }
+
+    /// Builds the hierarchy from the operators of all levels, the finest one first, and the
+    /// prolongations `prolongations[k]` from level `k + 1` to level `k`.
+    pub(crate) fn from_operators(
+        mut operators: Vec<CsrMatrix<f64>>,
+        prolongations: Vec<CsrMatrix<f64>>,
+        config: MultigridConfig,
+    ) -> Result<Self, PoissonError> {
+        let coarse = operators
+            .pop()
+            .expect("the hierarchy has at least one level");
+        let coarse = SparseCholesky::factor(&coarse, Reordering::ReverseCuthillMcKee)?;
+        let levels = operators
+            .into_iter()
+            .zip(prolongations)
+            .map(|(a, prolongation)| {
+                Ok(Level {
+                    diag: diagonal(&a)?,
+                    a,
+                    restriction: prolongation.transpose(),
+                    prolongation,
+                })
+            })
+            .collect::<Result<_, PoissonError>>()?;
+        Ok(Self {
+            levels,
+            coarse,
+            config,
+        })
+    }
+
+    /// Number of levels, the coarsest one included.
+    pub fn num_levels(&self) -> usize {
+        self.levels.len() + 1
+    }
+
+    /// Applies one cycle to `A x = b` starting from `x`, and returns the new iterate.
+    pub fn cycle(&self, b: &DVector<f64>, x: DVector<f64>) -> DVector<f64> {
+        self.cycle_at(0, b, x)
+    }
+
+    fn cycle_at(&self, k: usize, b: &DVector<f64>, mut x: DVector<f64>) -> DVector<f64> {
+        let Some(level) = self.levels.get(k) else {
+            return self
+                .coarse
+                .solve(b)
+                .expect("the coarse system matches the hierarchy");
+        };
+        for _ in 0..self.config.pre_smoothing {
+            self.smooth(level, b, &mut x, true);
+        }
+        let r = b - &level.a * &x;
+        let r_coarse = &level.restriction * &r;
+        let corrections = match self.config.cycle {
+            CycleType::V => 1,
+            CycleType::W => 2,
+        };
+        let mut e_coarse = DVector::zeros(r_coarse.len());
+        for _ in 0..corrections {
+            e_coarse = self.cycle_at(k + 1, &r_coarse, e_coarse);
+        }
+        x += &level.prolongation * e_coarse;
+        for _ in 0..self.config.post_smoothing {
+            self.smooth(level, b, &mut x, false);
+        }
+        x
+    }
+
+    /// One smoothing sweep, forward or backward for Gauss-Seidel.
+    fn smooth(&self, level: &Level, b: &DVector<f64>, x: &mut DVector<f64>, forward: bool) {
+        match self.config.smoother {
+            Smoother::Jacobi { omega } => {
+                let r = b - &level.a * &*x;
+                for (i, r_i) in r.iter().enumerate() {
+                    x[i] += omega * r_i / level.diag[i];
+                }
+            }
+            Smoother::GaussSeidel => {
+                let n = x.len();
+                for k in 0..n {
+                    let i = if forward { k } else { n - 1 - k };
+                    let row = level.a.row(i);
+                    let mut sum = b[i];
+                    for (&j, &a_ij) in row.col_indices().iter().zip(row.values()) {
+                        if j != i {
+                            sum -= a_ij * x[j];
+                        }
+                    }
+                    x[i] = sum / level.diag[i];
+                }
+            }
+        }
+    }
+
+    /// Iterates cycles from `x = 0` until `‖b - Ax‖ ≤ tolerance ‖b‖`.
+    ///
+    /// Returns the solution and the residual norm after each cycle, or `NotConverged` after
+    /// `max_cycles` cycles.
+    pub fn solve(
+        &self,
+        b: &DVector<f64>,
+    ) -> Result<(DVector<f64>, ConvergenceHistory), PoissonError> {
+        let a = self.levels.first().map(|level| &level.a);
+        let mut history = ConvergenceHistory {
+            residuals: vec![b.norm()],
+            rhs_norm: b.norm(),
+        };
+        let threshold = self.config.tolerance * history.rhs_norm;
+        let mut x = DVector::zeros(b.len());
+        let Some(a) = a else {
+            // A single level is solved directly.
+            x = self.coarse.solve(b)?;
+            history.residuals.push(0.0);
+            return Ok((x, history));
+        };
+        while history.residuals.last().unwrap() > &threshold {
+            if history.iterations() >= self.config.max_cycles {
+                return Err(PoissonError::NotConverged {
+                    iterations: history.iterations(),
+                    residual: history.relative_residual(),
+                });
+            }
+            x = self.cycle(b, x);
+            history.residuals.push((b - a * &x).norm());
+        }
+        Ok((x, history))
+    }
+}
+
+impl ApplyPreconditioner for Multigrid {
+    /// One cycle started from zero.
+    fn apply(&self, r: &DVector<f64>) -> DVector<f64> {
+        self.cycle(r, DVector::zeros(r.len()))
+    }
+}
+// ANCHOR_END: multigrid
+
+// ANCHOR: mesh_hierarchy
+/// Sequence of uniformly refined meshes, from the coarsest to the finest.
+pub struct MeshHierarchy {
+    pub meshes: Vec<Mesh2d>,
+    /// `prolongations[k]` interpolates nodal vectors from `meshes[k]` to `meshes[k + 1]`
+    pub prolongations: Vec<CsrMatrix<f64>>,
+}
+
+impl MeshHierarchy {
+    /// Refines `coarse` uniformly `num_refinements` times, see `Mesh2d::refine_uniform`.
+    pub fn new(coarse: Mesh2d, num_refinements: usize) -> Result<Self, MeshError> {
+        let mut meshes = vec![coarse];
+        let mut prolongations = Vec::with_capacity(num_refinements);
+        for _ in 0..num_refinements {
+            let (fine, prolongation) = meshes.last().unwrap().refine_uniform()?;
+            meshes.push(fine);
+            prolongations.push(prolongation);
+        }
+        Ok(Self {
+            meshes,
+            prolongations,
+        })
+    }
+
+    /// The finest mesh, on which the problem is solved.
+    pub fn finest(&self) -> &Mesh2d {
+        self.meshes.last().unwrap()
+    }
+}
+// ANCHOR_END: mesh_hierarchy
+
+// ANCHOR: assemble_and_solve_multigrid
+/// Solves the Poisson problem on the finest mesh of the hierarchy with the multigrid
+/// solver, `boundary_nodes` being nodes of the finest mesh.
+///
+/// Returns the solution and the residual norm after each cycle.
+pub fn assemble_and_solve_multigrid<G, F>(
+    hierarchy: &MeshHierarchy,
+    boundary_nodes: &[usize],
+    boundary_fn: G,
+    source_fn: F,
+    config: MultigridConfig,
+) -> Result<(DVector<f64>, ConvergenceHistory), PoissonError>
+where
+    G: Fn(f64, f64) -> f64,
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    let mesh = hierarchy.finest();
+    let boundary_nodes = check_problem(mesh, boundary_nodes)?;
+
+    // Assemble sparse system
+    let (mut a, mut b) = assemble_system_sparse(mesh, &source_fn);
+
+    // Apply BCs
+    apply_dirichlet_sparse(&mut a, &mut b, &boundary_nodes, mesh, boundary_fn);
+
+    // Build the hierarchy and iterate cycles
+    Multigrid::new(&a, &hierarchy.prolongations, config)?.solve(&b)
+}
+// ANCHOR_END: assemble_and_solve_multigrid
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::ElementType;
+    use crate::generators::{annulus, unit_square};
+    use crate::iterative::{
+        IterativeConfig, IterativeMethod, Preconditioner, solve_iterative, solve_iterative_with,
+    };
+
+    /// Poisson system on the finest mesh of a hierarchy, with `u = x + y` on the boundary.
+    fn poisson_system(hierarchy: &MeshHierarchy) -> (CsrMatrix<f64>, DVector<f64>) {
+        let mesh = hierarchy.finest();
+        let (mut a, mut b) = assemble_system_sparse(mesh, &|x, y| 1.0 + x * y);
+        apply_dirichlet_sparse(&mut a, &mut b, &mesh.boundary_nodes(), mesh, |x, y| x + y);
+        (a, b)
+    }
+
+    #[test]
+    fn test_mesh_independent_cycles() {
+        for element_type in [ElementType::P1, ElementType::Q1] {
+            let mut cycles = Vec::new();
+            for num_refinements in 1..=5 {
+                let (coarse, _) = unit_square(2, element_type.clone());
+                let hierarchy = MeshHierarchy::new(coarse, num_refinements).unwrap();
+                let (a, b) = poisson_system(&hierarchy);
+                let multigrid =
+                    Multigrid::new(&a, &hierarchy.prolongations, Default::default()).unwrap();
+                assert_eq!(multigrid.num_levels(), num_refinements + 1);
+                let (x, history) = multigrid.solve(&b).unwrap();
+
+                let expected = SparseCholesky::factor(&a, Reordering::ReverseCuthillMcKee)
+                    .unwrap()
+                    .solve(&b)
+                    .unwrap();
+                assert!((x - &expected).norm() < 1e-8 * expected.norm());
+                cycles.push(history.iterations());
+            }
+            assert!(cycles.iter().all(|&n| n <= 12), "cycles {cycles:?}");
+            assert!(cycles[4] <= cycles[1] + 2, "cycles {cycles:?}");
+        }
+    }
+
+    #[test]
+    fn test_smoothers_and_cycles() {
+        let (coarse, _) = annulus(0.5, 1.0, 2, 8, ElementType::Q1);
+        let hierarchy = MeshHierarchy::new(coarse, 3).unwrap();
+        let (a, b) = poisson_system(&hierarchy);
+        let mut cycles = Vec::new();
+        for (cycle, smoother) in [
+            (CycleType::V, Smoother::Jacobi { omega: 2.0 / 3.0 }),
+            (CycleType::V, Smoother::GaussSeidel),
+            (CycleType::W, Smoother::Jacobi { omega: 2.0 / 3.0 }),
+            (CycleType::W, Smoother::GaussSeidel),
+        ] {
+            let config = MultigridConfig::new(cycle, smoother).with_max_cycles(50);
+            let multigrid = Multigrid::new(&a, &hierarchy.prolongations, config).unwrap();
+            let (x, history) = multigrid.solve(&b).unwrap();
+            assert!((&b - &a * x).norm() <= 1e-10 * b.norm());
+            cycles.push(history.iterations());
+        }
+        // Gauss-Seidel smooths better than damped Jacobi, W-cycles converge faster.
+        assert!(cycles[1] <= cycles[0]);
+        assert!(cycles[2] <= cycles[0]);
+        assert!(cycles[3] <= cycles[1]);
+    }
+
+    #[test]
+    fn test_multigrid_preconditioned_cg() {
+        let mut multigrid_iterations = Vec::new();
+        let mut jacobi_iterations = Vec::new();
+        for num_refinements in [2, 4] {
+            let (coarse, _) = unit_square(2, ElementType::P1);
+            let hierarchy = MeshHierarchy::new(coarse, num_refinements).unwrap();
+            let (a, b) = poisson_system(&hierarchy);
+            let config = MultigridConfig::default().with_smoothing(1, 1);
+            let multigrid = Multigrid::new(&a, &hierarchy.prolongations, config).unwrap();
+
+            let cg = IterativeConfig::new(IterativeMethod::ConjugateGradient);
+            let (x, history) = solve_iterative_with(&a, &b, &cg, &multigrid).unwrap();
+            assert!((&b - &a * x).norm() <= 1e-10 * b.norm());
+            multigrid_iterations.push(history.iterations());
+
+            let (_, history) =
+                solve_iterative(&a, &b, &cg.with_preconditioner(Preconditioner::Jacobi)).unwrap();
+            jacobi_iterations.push(history.iterations());
+        }
+        // The Jacobi-preconditioned iteration count doubles with each refinement.
+        assert!(multigrid_iterations[1] <= multigrid_iterations[0] + 1);
+        assert!(multigrid_iterations[1] <= 10);
+        assert!(jacobi_iterations[1] > 3 * jacobi_iterations[0]);
+    }
+
+    #[test]
+    fn test_assemble_and_solve_multigrid() {
+        // Linear solutions are reproduced exactly.
+        let (coarse, _) = unit_square(2, ElementType::P1);
+        let hierarchy = MeshHierarchy::new(coarse, 3).unwrap();
+        let boundary_nodes = hierarchy.finest().boundary_nodes();
+        let (u, _) = assemble_and_solve_multigrid(
+            &hierarchy,
+            &boundary_nodes,
+            |x, y| 2.0 * x - y,
+            |_, _| 0.0,
+            MultigridConfig::default(),
+        )
+        .unwrap();
+        for (p, u_i) in hierarchy.finest().vertices().iter().zip(u.iter()) {
+            assert!((u_i - (2.0 * p.x - p.y)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_multigrid_errors() {
+        let (coarse, _) = unit_square(2, ElementType::P1);
+        let hierarchy = MeshHierarchy::new(coarse, 2).unwrap();
+        let (a, _) = poisson_system(&hierarchy);
+        assert!(matches!(
+            Multigrid::new(&a, &hierarchy.prolongations[..1], Default::default()),
+            Err(PoissonError::DimensionMismatch { .. })
+        ));
+
+        let config = MultigridConfig::default().with_max_cycles(1);
+        let multigrid = Multigrid::new(&a, &hierarchy.prolongations, config).unwrap();
+        let b = DVector::from_element(a.nrows(), 1.0);
+        assert!(matches!(
+            multigrid.solve(&b),
+            Err(PoissonError::NotConverged { iterations: 1, .. })
+        ));
+
+        let (quadratic, _) = unit_square(2, ElementType::P2);
+        assert!(matches!(
+            MeshHierarchy::new(quadratic, 1),
+            Err(MeshError::UnsupportedElementType { .. })
+        ));
+    }
+}
+// ANCHOR_END: tests
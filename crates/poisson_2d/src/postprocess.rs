@@ -0,0 +1,532 @@
+//! Module that post-processes nodal solutions: point evaluation, gradients, fluxes and
+//! integrals over the domain or over named regions.
+//!
+//! Points are located with a uniform grid of buckets holding the element bounding boxes,
+//! then mapped back to the reference element: directly for affine triangles, with Newton
+//! iterations on the isoparametric mapping for the other elements.
+use crate::coefficient::Diffusion;
+use crate::element::{ElementType, ReferenceElement};
+use crate::mesh::Mesh2d;
+use crate::quadrature::QuadRule;
+use crate::solver::PoissonError;
+use nalgebra::{DVector, Point2, Vector2};
+
+/// Relative tolerance on the reference coordinates when testing if a point is inside.
+const INSIDE_TOLERANCE: f64 = 1e-10;
+
+/// Maximum number of Newton iterations of the inverse isoparametric mapping.
+const MAX_NEWTON_ITERATIONS: usize = 25;
+
+/// Coordinates of the nodes of element `element`.
+fn element_nodes(mesh: &Mesh2d, element: usize) -> Vec<Point2<f64>> {
+    mesh.elements()[element]
+        .indices
+        .iter()
+        .map(|&i| mesh.vertices()[i])
+        .collect()
+}
+
+/// Centroid of the reference element.
+fn reference_centre(element_type: &ElementType) -> Point2<f64> {
+    if element_type.num_corners() == 3 {
+        Point2::new(1.0 / 3.0, 1.0 / 3.0)
+    } else {
+        Point2::origin()
+    }
+}
+
+/// Whether the local coordinates lie in the reference element, up to `tolerance`.
+fn is_inside(element_type: &ElementType, xi: &Point2<f64>, tolerance: f64) -> bool {
+    if element_type.num_corners() == 3 {
+        xi.x >= -tolerance && xi.y >= -tolerance && xi.x + xi.y <= 1.0 + tolerance
+    } else {
+        xi.x.abs() <= 1.0 + tolerance && xi.y.abs() <= 1.0 + tolerance
+    }
+}
+
+// ANCHOR: local_coordinates
+/// Inverts the isoparametric mapping of an element, returning the local coordinates of
+/// `point`.
+///
+/// Affine triangles are inverted directly, the other elements with Newton iterations
+/// started from the reference centroid. Returns `None` if the iterations do not converge,
+/// which happens for points far outside a distorted element.
+pub fn local_coordinates(
+    element_type: &ElementType,
+    nodes: &[Point2<f64>],
+    point: &Point2<f64>,
+) -> Option<Point2<f64>> {
+    let ref_element = element_type.reference_element();
+    let mut xi = reference_centre(element_type);
+    for _ in 0..MAX_NEWTON_ITERATIONS {
+        let mapped = map_to_physical(&ref_element, nodes, &xi);
+        let jac_inv = ref_element.jacobian(nodes, &xi).try_inverse()?;
+        let step = jac_inv * (point - mapped);
+        xi += step;
+        if matches!(element_type, ElementType::P1) || step.norm() < 1e-14 {
+            return Some(xi);
+        }
+    }
+    None
+}
+// ANCHOR_END: local_coordinates
+
+/// Physical point `x = Σ N_i(ξ) x_i` of the local coordinates `xi`.
+fn map_to_physical(
+    ref_element: &ReferenceElement,
+    nodes: &[Point2<f64>],
+    xi: &Point2<f64>,
+) -> Point2<f64> {
+    let mut x = Point2::origin();
+    for (val, vtx) in ref_element.shape_functions(xi).iter().zip(nodes) {
+        x.coords += vtx.coords * *val;
+    }
+    x
+}
+
+/// Gradient of the nodal field `u` in element `element` at the local coordinates `xi`.
+fn gradient_at(mesh: &Mesh2d, u: &DVector<f64>, element: usize, xi: &Point2<f64>) -> Vector2<f64> {
+    let ref_element = mesh.element_type_of(element).reference_element();
+    let nodes = element_nodes(mesh, element);
+    let jac_inv_t = ref_element
+        .jacobian(&nodes, xi)
+        .try_inverse()
+        .expect("degenerate element, see Mesh2d::validate")
+        .transpose();
+    let mut grad = Vector2::zeros();
+    for (grad_ref, &i) in ref_element
+        .shape_gradients(xi)
+        .iter()
+        .zip(&mesh.elements()[element].indices)
+    {
+        grad += jac_inv_t * grad_ref * u[i];
+    }
+    grad
+}
+
+/// Panics unless `u` holds one value per vertex of the mesh.
+fn check_length(mesh: &Mesh2d, u: &DVector<f64>) {
+    assert_eq!(
+        u.len(),
+        mesh.vertices().len(),
+        "the field needs one value per mesh vertex"
+    );
+}
+
+// ANCHOR: point_locator
+/// Finds the element containing a point, using a uniform grid of buckets.
+///
+/// Each bucket lists the elements whose bounding box overlaps it, so only a few elements
+/// are tested per query. The bounding boxes are built from the element nodes, which
+/// contain the elements with straight edges.
+pub struct PointLocator<'a> {
+    mesh: &'a Mesh2d,
+    lower: Point2<f64>,
+    cell_size: Vector2<f64>,
+    nx: usize,
+    ny: usize,
+    buckets: Vec<Vec<usize>>,
+}
+
+impl<'a> PointLocator<'a> {
+    /// Builds the buckets, about one per element.
+    pub fn new(mesh: &'a Mesh2d) -> Self {
+        let boxes: Vec<(Point2<f64>, Point2<f64>)> = (0..mesh.elements().len())
+            .map(|e| bounding_box(&element_nodes(mesh, e)))
+            .collect();
+        let (mut lower, mut upper) = bounding_box(mesh.vertices());
+        let margin = (upper - lower).norm() * INSIDE_TOLERANCE;
+        lower -= Vector2::repeat(margin);
+        upper += Vector2::repeat(margin);
+
+        let n = (mesh.elements().len() as f64).sqrt().ceil().max(1.0) as usize;
+        let extent = upper - lower;
+        let cell_size = extent / n as f64;
+        let mut locator = Self {
+            mesh,
+            lower,
+            cell_size,
+            nx: n,
+            ny: n,
+            buckets: vec![Vec::new(); n * n],
+        };
+        for (e, (p, q)) in boxes.iter().enumerate() {
+            let (i0, j0) = locator.cell_of(p);
+            let (i1, j1) = locator.cell_of(q);
+            for j in j0..=j1 {
+                for i in i0..=i1 {
+                    locator.buckets[j * n + i].push(e);
+                }
+            }
+        }
+        locator
+    }
+
+    /// Grid cell containing `point`, clamped to the grid.
+    fn cell_of(&self, point: &Point2<f64>) -> (usize, usize) {
+        let cell = |x: f64, x0: f64, h: f64, n: usize| {
+            (((x - x0) / h).floor().max(0.0) as usize).min(n - 1)
+        };
+        (
+            cell(point.x, self.lower.x, self.cell_size.x, self.nx),
+            cell(point.y, self.lower.y, self.cell_size.y, self.ny),
+        )
+    }
+
+    /// Returns the element containing `point` and the local coordinates of the point in
+    /// that element, or `None` if the point is outside the mesh.
+    ///
+    /// Points on a shared edge or vertex are attributed to the first element found.
+    pub fn locate(&self, point: &Point2<f64>) -> Option<(usize, Point2<f64>)> {
+        let outside = |x: f64, x0: f64, h: f64, n: usize| x < x0 || x > x0 + h * n as f64;
+        if outside(point.x, self.lower.x, self.cell_size.x, self.nx)
+            || outside(point.y, self.lower.y, self.cell_size.y, self.ny)
+        {
+            return None;
+        }
+        let (i, j) = self.cell_of(point);
+        self.buckets[j * self.nx + i].iter().find_map(|&e| {
+            let element_type = self.mesh.element_type_of(e);
+            let xi = local_coordinates(element_type, &element_nodes(self.mesh, e), point)?;
+            is_inside(element_type, &xi, INSIDE_TOLERANCE).then_some((e, xi))
+        })
+    }
+
+    /// Value of the nodal field `u` at `point`, or `None` outside the mesh.
+    ///
+    /// Panics if `u` does not have one value per mesh vertex.
+    pub fn evaluate(&self, u: &DVector<f64>, point: &Point2<f64>) -> Option<f64> {
+        check_length(self.mesh, u);
+        let (e, xi) = self.locate(point)?;
+        let ref_element = self.mesh.element_type_of(e).reference_element();
+        let value = ref_element
+            .shape_functions(&xi)
+            .iter()
+            .zip(&self.mesh.elements()[e].indices)
+            .map(|(val, &i)| val * u[i])
+            .sum();
+        Some(value)
+    }
+
+    /// Gradient of the nodal field `u` at `point`, or `None` outside the mesh.
+    ///
+    /// The gradient is discontinuous across elements, points on a shared edge take the
+    /// value of the element returned by `locate`.
+    pub fn evaluate_gradient(&self, u: &DVector<f64>, point: &Point2<f64>) -> Option<Vector2<f64>> {
+        check_length(self.mesh, u);
+        let (e, xi) = self.locate(point)?;
+        Some(gradient_at(self.mesh, u, e, &xi))
+    }
+}
+// ANCHOR_END: point_locator
+
+/// Smallest axis-aligned box containing the points.
+fn bounding_box(points: &[Point2<f64>]) -> (Point2<f64>, Point2<f64>) {
+    let mut lower = Point2::new(f64::INFINITY, f64::INFINITY);
+    let mut upper = Point2::new(f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for p in points {
+        lower = lower.inf(p);
+        upper = upper.sup(p);
+    }
+    (lower, upper)
+}
+
+// ANCHOR: gradients
+/// Gradient of the nodal field `u` at the centroid of each element.
+///
+/// Panics if `u` does not have one value per mesh vertex.
+pub fn element_gradients(mesh: &Mesh2d, u: &DVector<f64>) -> Vec<Vector2<f64>> {
+    check_length(mesh, u);
+    (0..mesh.elements().len())
+        .map(|e| gradient_at(mesh, u, e, &reference_centre(mesh.element_type_of(e))))
+        .collect()
+}
+
+/// Continuous gradient field obtained by averaging, at each node, the gradients of the
+/// elements sharing it.
+///
+/// The element contributions are weighted by the Jacobian determinant at the node, so
+/// that larger elements count more. Panics if `u` does not have one value per mesh vertex.
+pub fn nodal_gradients(mesh: &Mesh2d, u: &DVector<f64>) -> Vec<Vector2<f64>> {
+    check_length(mesh, u);
+    nodal_average(mesh, |e, _, xi| gradient_at(mesh, u, e, xi))
+}
+
+/// Flux `-κ ∇u` at the centroid of each element.
+///
+/// Panics if `u` does not have one value per mesh vertex. Fails with `UnknownRegion` if
+/// `kappa` refers to a region unknown to the mesh.
+pub fn element_fluxes(
+    mesh: &Mesh2d,
+    u: &DVector<f64>,
+    kappa: &Diffusion,
+) -> Result<Vec<Vector2<f64>>, PoissonError> {
+    check_length(mesh, u);
+    let values = kappa.element_values(mesh)?;
+    Ok((0..mesh.elements().len())
+        .map(|e| {
+            let xi = reference_centre(mesh.element_type_of(e));
+            let ref_element = mesh.element_type_of(e).reference_element();
+            let x = map_to_physical(&ref_element, &element_nodes(mesh, e), &xi);
+            -kappa.eval(e, x.x, x.y, &values) * gradient_at(mesh, u, e, &xi)
+        })
+        .collect())
+}
+
+/// Continuous flux field `-κ ∇u`, averaged at the nodes like `nodal_gradients`.
+///
+/// Panics if `u` does not have one value per mesh vertex. Fails with `UnknownRegion` if
+/// `kappa` refers to a region unknown to the mesh.
+pub fn nodal_fluxes(
+    mesh: &Mesh2d,
+    u: &DVector<f64>,
+    kappa: &Diffusion,
+) -> Result<Vec<Vector2<f64>>, PoissonError> {
+    check_length(mesh, u);
+    let values = kappa.element_values(mesh)?;
+    Ok(nodal_average(mesh, |e, x, xi| {
+        -kappa.eval(e, x.x, x.y, &values) * gradient_at(mesh, u, e, xi)
+    }))
+}
+// ANCHOR_END: gradients
+
+/// Averages at each node the values `field(element, x, xi)` of the elements sharing it,
+/// weighted by the Jacobian determinant at the node.
+fn nodal_average<V>(mesh: &Mesh2d, field: V) -> Vec<Vector2<f64>>
+where
+    V: Fn(usize, &Point2<f64>, &Point2<f64>) -> Vector2<f64>,
+{
+    let num_vertices = mesh.vertices().len();
+    let mut sums = vec![Vector2::zeros(); num_vertices];
+    let mut weights = vec![0.0; num_vertices];
+    for (e, element) in mesh.elements().iter().enumerate() {
+        let ref_element = mesh.element_type_of(e).reference_element();
+        let nodes = element_nodes(mesh, e);
+        for ((xi, x), &i) in ref_element.nodes().iter().zip(&nodes).zip(&element.indices) {
+            let weight = ref_element.jacobian(&nodes, xi).determinant().abs();
+            sums[i] += field(e, x, xi) * weight;
+            weights[i] += weight;
+        }
+    }
+    sums.iter()
+        .zip(&weights)
+        .map(|(sum, &w)| if w > 0.0 { sum / w } else { *sum })
+        .collect()
+}
+
+// ANCHOR: integrals
+/// Integral of the nodal field `u` over the whole mesh.
+///
+/// Panics if `u` does not have one value per mesh vertex.
+pub fn integrate(mesh: &Mesh2d, u: &DVector<f64>) -> f64 {
+    integrate_elements(mesh, u, 0..mesh.elements().len())
+}
+
+/// Integral of the nodal field `u` over the region `name`, or `None` if the mesh has no
+/// such region.
+///
+/// Panics if `u` does not have one value per mesh vertex.
+pub fn integrate_region(mesh: &Mesh2d, u: &DVector<f64>, name: &str) -> Option<f64> {
+    let elements = mesh.region(name)?;
+    Some(integrate_elements(mesh, u, elements.iter().copied()))
+}
+// ANCHOR_END: integrals
+
+/// Integral of `u` over the given elements, with the default quadrature rule of each type.
+fn integrate_elements<I>(mesh: &Mesh2d, u: &DVector<f64>, elements: I) -> f64
+where
+    I: Iterator<Item = usize>,
+{
+    check_length(mesh, u);
+    let mut total = 0.0;
+    for e in elements {
+        let element_type = mesh.element_type_of(e);
+        let ref_element = element_type.reference_element();
+        let quad_rule = QuadRule::for_element(element_type);
+        let nodes = element_nodes(mesh, e);
+        for (p, w) in quad_rule.points.iter().zip(&quad_rule.weights) {
+            let det = ref_element.jacobian(&nodes, p).determinant();
+            let value: f64 = ref_element
+                .shape_functions(p)
+                .iter()
+                .zip(&mesh.elements()[e].indices)
+                .map(|(val, &i)| val * u[i])
+                .sum();
+            total += value * w * det.abs();
+        }
+    }
+    total
+}
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::{annulus, unit_square};
+    use crate::{SolverType, solve_poisson_2d};
+    use std::f64::consts::PI;
+
+    const ALL_TYPES: [ElementType; 5] = [
+        ElementType::P1,
+        ElementType::Q1,
+        ElementType::P2,
+        ElementType::Q2,
+        ElementType::Q8,
+    ];
+
+    fn interpolate<F: Fn(f64, f64) -> f64>(mesh: &Mesh2d, f: F) -> DVector<f64> {
+        DVector::from_iterator(
+            mesh.vertices().len(),
+            mesh.vertices().iter().map(|p| f(p.x, p.y)),
+        )
+    }
+
+    #[test]
+    fn test_local_coordinates() {
+        // Trapezoid, whose bilinear mapping is not affine.
+        let nodes = [
+            Point2::new(0.0, 0.0),
+            Point2::new(2.0, 0.0),
+            Point2::new(1.5, 1.0),
+            Point2::new(0.5, 1.0),
+        ];
+        let ref_element = ElementType::Q1.reference_element();
+        let xi = Point2::new(0.3, -0.7);
+        let x = map_to_physical(&ref_element, &nodes, &xi);
+        let found = local_coordinates(&ElementType::Q1, &nodes, &x).unwrap();
+        assert!((found - xi).norm() < 1e-12);
+
+        let triangle = [
+            Point2::new(1.0, 1.0),
+            Point2::new(3.0, 1.0),
+            Point2::new(1.0, 2.0),
+        ];
+        let xi = local_coordinates(&ElementType::P1, &triangle, &Point2::new(2.0, 1.5)).unwrap();
+        assert!((xi - Point2::new(0.5, 0.5)).norm() < 1e-14);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let linear = |x: f64, y: f64| 1.0 + x - 2.0 * y;
+        let points = [
+            Point2::new(0.13, 0.71),
+            Point2::new(0.5, 0.5),
+            Point2::new(0.0, 1.0),
+            Point2::new(0.999, 0.001),
+        ];
+        for element_type in ALL_TYPES {
+            let (mesh, _) = unit_square(5, element_type);
+            let u = interpolate(&mesh, linear);
+            let locator = PointLocator::new(&mesh);
+            for p in &points {
+                let value = locator.evaluate(&u, p).unwrap();
+                assert!((value - linear(p.x, p.y)).abs() < 1e-12);
+                let grad = locator.evaluate_gradient(&u, p).unwrap();
+                assert!((grad - Vector2::new(1.0, -2.0)).norm() < 1e-12);
+            }
+            assert_eq!(locator.evaluate(&u, &Point2::new(1.2, 0.5)), None);
+            assert_eq!(locator.evaluate(&u, &Point2::new(0.5, -1e-3)), None);
+        }
+    }
+
+    #[test]
+    fn test_locate_distorted_quadrangles() {
+        // The annulus cells are trapezoids and the point at the centre lies in the hole.
+        let (mesh, _) = annulus(0.5, 1.0, 4, 16, ElementType::Q1);
+        let u = interpolate(&mesh, |x, y| 3.0 * x + y);
+        let locator = PointLocator::new(&mesh);
+        for k in 0..20 {
+            let theta = 2.0 * PI * k as f64 / 20.0 + 0.1;
+            let r = 0.52 + 0.023 * k as f64;
+            let p = Point2::new(r * theta.cos(), r * theta.sin());
+            let (e, xi) = locator.locate(&p).unwrap();
+            let nodes = element_nodes(&mesh, e);
+            let mapped = map_to_physical(&mesh.element_type_of(e).reference_element(), &nodes, &xi);
+            assert!((mapped - p).norm() < 1e-12);
+            assert!((locator.evaluate(&u, &p).unwrap() - (3.0 * p.x + p.y)).abs() < 1e-12);
+        }
+        assert_eq!(locator.locate(&Point2::origin()), None);
+    }
+
+    #[test]
+    fn test_gradients_and_fluxes() {
+        for element_type in ALL_TYPES {
+            let (mesh, _) = unit_square(4, element_type);
+            let u = interpolate(&mesh, |x, y| 2.0 * x + 3.0 * y);
+            let expected = Vector2::new(2.0, 3.0);
+            for grad in element_gradients(&mesh, &u)
+                .iter()
+                .chain(&nodal_gradients(&mesh, &u))
+            {
+                assert!((grad - expected).norm() < 1e-12);
+            }
+
+            let kappa = Diffusion::Constant(0.5);
+            for flux in element_fluxes(&mesh, &u, &kappa)
+                .unwrap()
+                .iter()
+                .chain(&nodal_fluxes(&mesh, &u, &kappa).unwrap())
+            {
+                assert!((flux + expected * 0.5).norm() < 1e-12);
+            }
+        }
+
+        // Averaging smooths the piecewise constant gradient of a P1 interpolant.
+        let (mesh, _) = unit_square(16, ElementType::P1);
+        let u = interpolate(&mesh, |x, y| x * x + y);
+        let nodal = nodal_gradients(&mesh, &u);
+        let centre = mesh
+            .vertices()
+            .iter()
+            .position(|p| (p - Point2::new(0.5, 0.5)).norm() < 1e-12)
+            .unwrap();
+        assert!((nodal[centre] - Vector2::new(1.0, 1.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_integrals() {
+        for element_type in ALL_TYPES {
+            let (mut mesh, _) = unit_square(4, element_type);
+            let u = interpolate(&mesh, |x, y| x + y);
+            assert!((integrate(&mesh, &u) - 1.0).abs() < 1e-12);
+
+            // The left half of the square.
+            let left: Vec<usize> = (0..mesh.elements().len())
+                .filter(|&e| element_nodes(&mesh, e).iter().all(|p| p.x <= 0.5))
+                .collect();
+            mesh.add_region("left", left);
+            let value = integrate_region(&mesh, &u, "left").unwrap();
+            assert!((value - 0.375).abs() < 1e-12);
+            assert_eq!(integrate_region(&mesh, &u, "right"), None);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_solution() {
+        // -Δu = 2π² sin(πx) sin(πy) has the solution u = sin(πx) sin(πy).
+        let exact = |x: f64, y: f64| (PI * x).sin() * (PI * y).sin();
+        let zero: fn(f64, f64) -> f64 = |_, _| 0.0;
+        let source: fn(f64, f64) -> f64 = |x, y| 2.0 * PI * PI * (PI * x).sin() * (PI * y).sin();
+        let (mesh, boundary) = unit_square(16, ElementType::Q2);
+        let u =
+            solve_poisson_2d(&mesh, &boundary.all(), &zero, &source, SolverType::Sparse).unwrap();
+        let locator = PointLocator::new(&mesh);
+        let p = Point2::new(0.37, 0.61);
+        assert!((locator.evaluate(&u, &p).unwrap() - exact(p.x, p.y)).abs() < 1e-4);
+        let grad = locator.evaluate_gradient(&u, &p).unwrap();
+        let exact_grad = Vector2::new(
+            PI * (PI * p.x).cos() * (PI * p.y).sin(),
+            PI * (PI * p.x).sin() * (PI * p.y).cos(),
+        );
+        assert!((grad - exact_grad).norm() < 1e-2);
+        assert!((integrate(&mesh, &u) - 4.0 / (PI * PI)).abs() < 1e-5);
+    }
+
+    #[test]
+    #[should_panic(expected = "one value per mesh vertex")]
+    fn test_wrong_length() {
+        let (mesh, _) = unit_square(2, ElementType::P1);
+        integrate(&mesh, &DVector::zeros(4));
+    }
+}
+// ANCHOR_END: tests
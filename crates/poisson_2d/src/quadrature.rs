@@ -1,4 +1,22 @@
+//! Module that implements the quadrature rules used to integrate over elements and edges.
+//!
+//! Quadrangles and segments use Gauss-Legendre rules with any number of points, triangles
+//! use the symmetric rules tabulated by Dunavant (1985) up to degree 10.
+use crate::element::ElementType;
 use nalgebra::Point2;
+use std::f64::consts::PI;
+
+// ANCHOR: error_enum
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QuadratureError {
+    /// No triangle rule of the requested degree is tabulated
+    #[error("triangle quadrature of order {order} is not available (maximum order is {max})")]
+    UnsupportedOrder { order: usize, max: usize },
+    /// A Gauss rule needs at least one point
+    #[error("a Gauss-Legendre rule needs at least one point")]
+    NoPoints,
+}
+// ANCHOR_END: error_enum
 
 // ANCHOR: quad_rule_struct
 #[derive(Clone, Debug)]
@@ -10,66 +28,395 @@ pub struct QuadRule {
 
 // ANCHOR: quad_rule_impl
 impl QuadRule {
-    pub fn triangle(order: usize) -> Self {
-        match order {
-            1 => QuadRule {
-                points: vec![Point2::new(1.0 / 3.0, 1.0 / 3.0)],
-                weights: vec![0.5],
-            },
-            2 => QuadRule {
-                points: vec![
-                    Point2::new(1.0 / 6.0, 1.0 / 6.0),
-                    Point2::new(2.0 / 3.0, 1.0 / 6.0),
-                    Point2::new(1.0 / 6.0, 2.0 / 3.0),
-                ],
-                weights: vec![1.0 / 6.0; 3],
-            },
-            _ => panic!("triangle quadratule of order > 2 not implemented"),
+    /// Highest polynomial degree integrated exactly by `QuadRule::triangle`.
+    pub const MAX_TRIANGLE_ORDER: usize = 10;
+
+    /// Default rule used to assemble the system on an element type.
+    ///
+    /// Linear and bilinear elements use second-order rules, quadratic elements rules
+    /// exact for the mass matrix on affine elements.
+    pub fn for_element(element_type: &ElementType) -> Self {
+        let rule = match element_type {
+            ElementType::P1 => QuadRule::triangle(2),
+            ElementType::Q1 => QuadRule::quadrilateral(2),
+            ElementType::P2 => QuadRule::triangle(4),
+            ElementType::Q2 | ElementType::Q8 => QuadRule::quadrilateral(3),
+        };
+        rule.expect("default rules are always available")
+    }
+
+    /// Symmetric rule on the reference triangle exact for polynomials of degree `order`.
+    ///
+    /// All rules have positive weights and points inside the triangle. Orders 3 and 7
+    /// use the rules of the next degree, which avoids Dunavant's negative weights.
+    pub fn triangle(order: usize) -> Result<Self, QuadratureError> {
+        let orbits = match order {
+            0 | 1 => DUNAVANT_1,
+            2 => DUNAVANT_2,
+            3 | 4 => DUNAVANT_4,
+            5 => DUNAVANT_5,
+            6 => DUNAVANT_6,
+            7 | 8 => DUNAVANT_8,
+            9 => DUNAVANT_9,
+            10 => DUNAVANT_10,
+            _ => {
+                return Err(QuadratureError::UnsupportedOrder {
+                    order,
+                    max: Self::MAX_TRIANGLE_ORDER,
+                });
+            }
+        };
+
+        let mut points = Vec::new();
+        let mut weights = Vec::new();
+        for (orbit, weight) in orbits {
+            let barycentric = orbit.points();
+            // The tabulated weights sum to one, the reference triangle has area 1/2.
+            weights.extend(std::iter::repeat_n(0.5 * weight, barycentric.len()));
+            points.extend(barycentric.iter().map(|[_, l1, l2]| Point2::new(*l1, *l2)));
         }
+        Ok(QuadRule { points, weights })
     }
 
-    pub fn quadrilateral(n: usize) -> Self {
-        match n {
-            1 => QuadRule {
-                points: vec![Point2::new(0.0, 0.0)],
-                weights: vec![4.0],
-            },
-            2 => {
-                let a = 1.0 / 3.0f64.sqrt();
-                let pts = [-a, a];
-                let mut points = Vec::with_capacity(4);
-                let mut weights = Vec::with_capacity(4);
-                for xi in pts {
-                    for eta in pts {
-                        points.push(Point2::new(xi, eta));
-                        weights.push(1.0);
-                    }
-                }
-                QuadRule { points, weights }
+    /// Tensor-product Gauss-Legendre rule with `n` × `n` points on the reference square,
+    /// exact for polynomials of degree `2n - 1` in each variable.
+    pub fn quadrilateral(n: usize) -> Result<Self, QuadratureError> {
+        let line = LineQuadRule::gauss_legendre(n)?;
+        let mut points = Vec::with_capacity(n * n);
+        let mut weights = Vec::with_capacity(n * n);
+        for (xi, w_xi) in line.points.iter().zip(&line.weights) {
+            for (eta, w_eta) in line.points.iter().zip(&line.weights) {
+                points.push(Point2::new(*xi, *eta));
+                weights.push(w_xi * w_eta);
             }
-            _ => panic!("quadrilateral quadrature with n > 2 points not implemented"),
         }
+        Ok(QuadRule { points, weights })
     }
 }
 // ANCHOR_END: quad_rule_impl
 
+// ANCHOR: dunavant
+/// Orbit of points of a symmetric triangle rule, in barycentric coordinates.
+#[derive(Clone, Copy)]
+enum Orbit {
+    /// The centroid
+    S3,
+    /// The 3 permutations of (a, b, b)
+    S21(f64, f64),
+    /// The 6 permutations of (a, b, c)
+    S111(f64, f64, f64),
+}
+
+impl Orbit {
+    fn points(&self) -> Vec<[f64; 3]> {
+        match *self {
+            Orbit::S3 => vec![[1.0 / 3.0; 3]],
+            Orbit::S21(a, b) => vec![[a, b, b], [b, a, b], [b, b, a]],
+            Orbit::S111(a, b, c) => vec![
+                [a, b, c],
+                [a, c, b],
+                [b, a, c],
+                [b, c, a],
+                [c, a, b],
+                [c, b, a],
+            ],
+        }
+    }
+}
+
+// Tables from D. A. Dunavant, "High degree efficient symmetrical Gaussian quadrature
+// rules for the triangle", IJNME 21 (1985). Weights are normalized to sum to one.
+const DUNAVANT_1: &[(Orbit, f64)] = &[(Orbit::S3, 1.0)];
+
+const DUNAVANT_2: &[(Orbit, f64)] = &[(Orbit::S21(2.0 / 3.0, 1.0 / 6.0), 1.0 / 3.0)];
+
+const DUNAVANT_4: &[(Orbit, f64)] = &[
+    (
+        Orbit::S21(0.108103018168070, 0.445948490915965),
+        0.223381589678011,
+    ),
+    (
+        Orbit::S21(0.816847572980459, 0.091576213509771),
+        0.109951743655322,
+    ),
+];
+
+const DUNAVANT_5: &[(Orbit, f64)] = &[
+    (Orbit::S3, 0.225),
+    (
+        Orbit::S21(0.059715871789770, 0.470142064105115),
+        0.132394152788506,
+    ),
+    (
+        Orbit::S21(0.797426985353087, 0.101286507323456),
+        0.125939180544827,
+    ),
+];
+
+const DUNAVANT_6: &[(Orbit, f64)] = &[
+    (
+        Orbit::S21(0.501426509658179, 0.249286745170910),
+        0.116786275726379,
+    ),
+    (
+        Orbit::S21(0.873821971016996, 0.063089014491502),
+        0.050844906370207,
+    ),
+    (
+        Orbit::S111(0.053145049844817, 0.310352451033784, 0.636502499121399),
+        0.082851075618374,
+    ),
+];
+
+const DUNAVANT_8: &[(Orbit, f64)] = &[
+    (Orbit::S3, 0.144315607677787),
+    (
+        Orbit::S21(0.081414823414554, 0.459292588292723),
+        0.095091634267285,
+    ),
+    (
+        Orbit::S21(0.658861384496480, 0.170569307751760),
+        0.103217370534718,
+    ),
+    (
+        Orbit::S21(0.898905543365938, 0.050547228317031),
+        0.032458497623198,
+    ),
+    (
+        Orbit::S111(0.008394777409958, 0.263112829634638, 0.728492392955404),
+        0.027230314174435,
+    ),
+];
+
+const DUNAVANT_9: &[(Orbit, f64)] = &[
+    (Orbit::S3, 0.097135796282799),
+    (
+        Orbit::S21(0.020634961602525, 0.489682519198738),
+        0.031334700227139,
+    ),
+    (
+        Orbit::S21(0.125820817014127, 0.437089591492937),
+        0.077827541004774,
+    ),
+    (
+        Orbit::S21(0.623592928761935, 0.188203535619033),
+        0.079647738927210,
+    ),
+    (
+        Orbit::S21(0.910540973211095, 0.044729513394453),
+        0.025577675658698,
+    ),
+    (
+        Orbit::S111(0.036838412054736, 0.221962989160766, 0.741198598784498),
+        0.043283539377289,
+    ),
+];
+
+const DUNAVANT_10: &[(Orbit, f64)] = &[
+    (Orbit::S3, 0.090817990382754),
+    (
+        Orbit::S21(0.028844733232685, 0.485577633383657),
+        0.036725957756467,
+    ),
+    (
+        Orbit::S21(0.781036849029926, 0.109481575485037),
+        0.045321059435528,
+    ),
+    (
+        Orbit::S111(0.141707219414880, 0.307939838764121, 0.550352941820999),
+        0.072757916845420,
+    ),
+    (
+        Orbit::S111(0.025003534762686, 0.246672560639903, 0.728323904597411),
+        0.028327242531057,
+    ),
+    (
+        Orbit::S111(0.009540815400299, 0.066803251012200, 0.923655933587500),
+        0.009421666963733,
+    ),
+];
+// ANCHOR_END: dunavant
+
+// ANCHOR: line_quad_rule
+/// Gauss-Legendre quadrature rule on the reference segment [-1, 1].
+#[derive(Clone, Debug)]
+pub struct LineQuadRule {
+    pub points: Vec<f64>,
+    pub weights: Vec<f64>,
+}
+
+impl LineQuadRule {
+    /// Rule with `n` points, exact for polynomials of degree `2n - 1`.
+    ///
+    /// The points are the roots of the Legendre polynomial `P_n`, found by Newton's method
+    /// from the asymptotic guess `cos(π (i - 1/4) / (n + 1/2))`. They are sorted in
+    /// increasing order.
+    pub fn gauss_legendre(n: usize) -> Result<Self, QuadratureError> {
+        if n == 0 {
+            return Err(QuadratureError::NoPoints);
+        }
+        let mut points = vec![0.0; n];
+        let mut weights = vec![0.0; n];
+        // Roots are symmetric about 0: compute the positive half only.
+        for i in 0..n.div_ceil(2) {
+            let mut x = (PI * (i as f64 + 0.75) / (n as f64 + 0.5)).cos();
+            for _ in 0..100 {
+                let (p, dp) = legendre(n, x);
+                let dx = p / dp;
+                x -= dx;
+                if dx.abs() < 1e-15 {
+                    break;
+                }
+            }
+            let derivative = legendre(n, x).1;
+            let weight = 2.0 / ((1.0 - x * x) * derivative * derivative);
+            points[i] = -x;
+            points[n - 1 - i] = x;
+            weights[i] = weight;
+            weights[n - 1 - i] = weight;
+        }
+        if n % 2 == 1 {
+            points[n / 2] = 0.0;
+        }
+        Ok(LineQuadRule { points, weights })
+    }
+}
+
+/// Value and derivative of the Legendre polynomial `P_n` at `x`, from the three-term
+/// recurrence `(k + 1) P_{k+1} = (2k + 1) x P_k - k P_{k-1}`.
+fn legendre(n: usize, x: f64) -> (f64, f64) {
+    if n == 0 {
+        return (1.0, 0.0);
+    }
+    let (mut p_prev, mut p) = (1.0, x);
+    for k in 1..n {
+        let k = k as f64;
+        let p_next = ((2.0 * k + 1.0) * x * p - k * p_prev) / (k + 1.0);
+        p_prev = p;
+        p = p_next;
+    }
+    let dp = n as f64 * (x * p - p_prev) / (x * x - 1.0);
+    (p, dp)
+}
+// ANCHOR_END: line_quad_rule
+
 // ANCHOR: tests
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Exact integral of x^a y^b over the reference triangle: a! b! / (a + b + 2)!.
+    fn triangle_monomial(a: usize, b: usize) -> f64 {
+        let factorial = |n: usize| (1..=n).map(|k| k as f64).product::<f64>();
+        factorial(a) * factorial(b) / factorial(a + b + 2)
+    }
+
+    /// Exact integral of s^degree over [-1, 1].
+    fn line_monomial(degree: usize) -> f64 {
+        if degree.is_multiple_of(2) {
+            2.0 / (degree as f64 + 1.0)
+        } else {
+            0.0
+        }
+    }
+
     #[test]
     fn test_triangle_quadrature() {
-        let rule = QuadRule::triangle(2);
+        let rule = QuadRule::triangle(2).unwrap();
         assert_eq!(rule.points.len(), 3);
         assert_eq!(rule.weights.len(), 3);
     }
 
+    #[test]
+    fn test_triangle_quadrature_exactness() {
+        let sizes = [1, 1, 3, 6, 6, 7, 12, 16, 16, 19, 25];
+        for (order, &size) in sizes.iter().enumerate() {
+            let rule = QuadRule::triangle(order).unwrap();
+            assert_eq!(rule.points.len(), size);
+            for p in &rule.points {
+                assert!(p.x > 0.0 && p.y > 0.0 && p.x + p.y < 1.0);
+            }
+            assert!(rule.weights.iter().all(|w| *w > 0.0));
+            for a in 0..=order {
+                for b in 0..=order - a {
+                    let approx: f64 = rule
+                        .points
+                        .iter()
+                        .zip(&rule.weights)
+                        .map(|(p, w)| w * p.x.powi(a as i32) * p.y.powi(b as i32))
+                        .sum();
+                    let exact = triangle_monomial(a, b);
+                    assert!((approx - exact).abs() < 1e-14, "order {order}: x^{a} y^{b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsupported_orders() {
+        assert_eq!(
+            QuadRule::triangle(11).unwrap_err(),
+            QuadratureError::UnsupportedOrder { order: 11, max: 10 }
+        );
+        assert_eq!(
+            QuadRule::quadrilateral(0).unwrap_err(),
+            QuadratureError::NoPoints
+        );
+        assert!(LineQuadRule::gauss_legendre(0).is_err());
+    }
+
     #[test]
     fn test_quadrilateral_quadrature() {
-        let rule = QuadRule::quadrilateral(2);
+        let rule = QuadRule::quadrilateral(2).unwrap();
         assert_eq!(rule.points.len(), 4);
         assert_eq!(rule.weights.len(), 4);
+
+        // A n × n rule integrates ξ^a η^b exactly for a, b < 2n.
+        for n in 1..=6 {
+            let rule = QuadRule::quadrilateral(n).unwrap();
+            assert_eq!(rule.points.len(), n * n);
+            for a in 0..2 * n {
+                for b in 0..2 * n {
+                    let approx: f64 = rule
+                        .points
+                        .iter()
+                        .zip(&rule.weights)
+                        .map(|(p, w)| w * p.x.powi(a as i32) * p.y.powi(b as i32))
+                        .sum();
+                    let exact = line_monomial(a) * line_monomial(b);
+                    assert!((approx - exact).abs() < 1e-13, "n = {n}: ξ^{a} η^{b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_quadrature() {
+        // The first rules match their closed forms.
+        let rule = LineQuadRule::gauss_legendre(2).unwrap();
+        assert!((rule.points[1] - 1.0 / 3.0f64.sqrt()).abs() < 1e-15);
+        let rule = LineQuadRule::gauss_legendre(3).unwrap();
+        assert_eq!(rule.points[1], 0.0);
+        assert!((rule.weights[1] - 8.0 / 9.0).abs() < 1e-15);
+
+        for n in 1..=20 {
+            let rule = LineQuadRule::gauss_legendre(n).unwrap();
+            assert_eq!(rule.points.len(), n);
+            assert!(rule.points.windows(2).all(|p| p[0] < p[1]));
+            // A n-point rule integrates polynomials of degree 2n - 1 exactly.
+            for degree in 0..2 * n {
+                let approx: f64 = rule
+                    .points
+                    .iter()
+                    .zip(&rule.weights)
+                    .map(|(s, w)| w * s.powi(degree as i32))
+                    .sum();
+                assert!(
+                    (approx - line_monomial(degree)).abs() < 1e-13,
+                    "n = {n}, degree {degree}"
+                );
+            }
+        }
     }
 }
 // ANCHOR_END: tests
@@ -0,0 +1,271 @@
+//! Module that computes quality metrics of the mesh elements.
+//!
+//! Metrics are computed from the corner nodes, except the signed area and the Jacobian
+//! ratio which use the isoparametric mapping of the element, midside nodes included.
+use crate::mesh::Mesh2d;
+use crate::quadrature::QuadRule;
+use nalgebra::Point2;
+
+// ANCHOR: element_quality
+/// Quality metrics of one element.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ElementQuality {
+    /// Area of the element, negative if it is oriented clockwise
+    pub signed_area: f64,
+    /// Equal to one for an equilateral triangle or a square, larger for stretched elements
+    ///
+    /// Triangles use the ratio `R / 2r` of the circumradius to the inradius, quadrangles
+    /// the ratio of the longest to the shortest edge.
+    pub aspect_ratio: f64,
+    /// Smallest interior angle at the corners, in degrees
+    pub min_angle: f64,
+    /// Ratio of the smallest to the largest Jacobian determinant over the element nodes
+    ///
+    /// Equal to one for affine elements and negative for inverted ones.
+    pub jacobian_ratio: f64,
+}
+
+/// Summary statistics of the element qualities of a mesh.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct QualitySummary {
+    pub num_elements: usize,
+    /// Number of elements with a non-positive Jacobian determinant at some node
+    pub num_inverted: usize,
+    pub min_area: f64,
+    pub max_aspect_ratio: f64,
+    pub mean_aspect_ratio: f64,
+    pub min_angle: f64,
+    pub mean_min_angle: f64,
+    pub min_jacobian_ratio: f64,
+}
+// ANCHOR_END: element_quality
+
+// ANCHOR: quality_impl
+impl Mesh2d {
+    /// Computes the quality metrics of element `element`.
+    pub fn element_quality(&self, element: usize) -> ElementQuality {
+        let element_type = self.element_type_of(element);
+        let nodes = self.element_nodes(element);
+        let corners = &nodes[..element_type.num_corners()];
+
+        let ref_element = element_type.reference_element();
+        let rule = QuadRule::for_element(element_type);
+        let signed_area = rule
+            .points
+            .iter()
+            .zip(&rule.weights)
+            .map(|(p, w)| w * ref_element.jacobian(&nodes, p).determinant())
+            .sum();
+
+        let determinants = jacobian_determinants(self, element);
+        let max_det = determinants.iter().fold(f64::MIN, |a, &b| a.max(b));
+        let min_det = determinants.iter().fold(f64::MAX, |a, &b| a.min(b));
+        let jacobian_ratio = if max_det > 0.0 {
+            min_det / max_det
+        } else {
+            -1.0
+        };
+
+        ElementQuality {
+            signed_area,
+            aspect_ratio: aspect_ratio(corners),
+            min_angle: min_angle(corners),
+            jacobian_ratio,
+        }
+    }
+
+    /// Computes the quality metrics of every element.
+    pub fn quality(&self) -> Vec<ElementQuality> {
+        (0..self.elements().len())
+            .map(|e| self.element_quality(e))
+            .collect()
+    }
+
+    /// Computes summary statistics of the element qualities.
+    ///
+    /// Meshes with inverted elements, a very small minimum angle or a very large aspect
+    /// ratio should be fixed before solving.
+    pub fn quality_summary(&self) -> QualitySummary {
+        let qualities = self.quality();
+        let n = qualities.len().max(1) as f64;
+        let mut summary = QualitySummary {
+            num_elements: qualities.len(),
+            num_inverted: 0,
+            min_area: f64::INFINITY,
+            max_aspect_ratio: 0.0,
+            mean_aspect_ratio: 0.0,
+            min_angle: f64::INFINITY,
+            mean_min_angle: 0.0,
+            min_jacobian_ratio: f64::INFINITY,
+        };
+        for q in &qualities {
+            if q.jacobian_ratio <= 0.0 {
+                summary.num_inverted += 1;
+            }
+            summary.min_area = summary.min_area.min(q.signed_area);
+            summary.max_aspect_ratio = summary.max_aspect_ratio.max(q.aspect_ratio);
+            summary.mean_aspect_ratio += q.aspect_ratio / n;
+            summary.min_angle = summary.min_angle.min(q.min_angle);
+            summary.mean_min_angle += q.min_angle / n;
+            summary.min_jacobian_ratio = summary.min_jacobian_ratio.min(q.jacobian_ratio);
+        }
+        summary
+    }
+
+    /// Coordinates of the nodes of element `element`.
+    fn element_nodes(&self, element: usize) -> Vec<Point2<f64>> {
+        self.elements()[element]
+            .indices
+            .iter()
+            .map(|&i| self.vertices()[i])
+            .collect()
+    }
+}
+// ANCHOR_END: quality_impl
+
+/// Jacobian determinants of the element mapping at the reference nodes.
+///
+/// The determinant of a bilinear quadrangle is extremal at its corners, so this detects
+/// inverted quadrangles as well as inverted or degenerate triangles.
+pub(crate) fn jacobian_determinants(mesh: &Mesh2d, element: usize) -> Vec<f64> {
+    let ref_element = mesh.element_type_of(element).reference_element();
+    let nodes = mesh.element_nodes(element);
+    ref_element
+        .nodes()
+        .iter()
+        .map(|p| ref_element.jacobian(&nodes, p).determinant())
+        .collect()
+}
+
+/// Aspect ratio of a triangle or quadrangle given by its corners.
+fn aspect_ratio(corners: &[Point2<f64>]) -> f64 {
+    let n = corners.len();
+    let edges: Vec<f64> = (0..n)
+        .map(|k| (corners[(k + 1) % n] - corners[k]).norm())
+        .collect();
+    let longest = edges.iter().fold(0.0f64, |a, &b| a.max(b));
+    if n == 3 {
+        // R = abc / 4A and r = 2A / (a + b + c).
+        let area = 0.5
+            * (corners[1] - corners[0])
+                .perp(&(corners[2] - corners[0]))
+                .abs();
+        let product: f64 = edges.iter().product();
+        let perimeter: f64 = edges.iter().sum();
+        if area == 0.0 {
+            return f64::INFINITY;
+        }
+        product * perimeter / (16.0 * area * area)
+    } else {
+        let shortest = edges.iter().fold(f64::INFINITY, |a, &b| a.min(b));
+        longest / shortest
+    }
+}
+
+/// Smallest interior angle of a polygon given by its corners, in degrees.
+fn min_angle(corners: &[Point2<f64>]) -> f64 {
+    let n = corners.len();
+    (0..n)
+        .map(|k| {
+            let previous = corners[(k + n - 1) % n] - corners[k];
+            let next = corners[(k + 1) % n] - corners[k];
+            previous.angle(&next).to_degrees()
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::{Element, ElementType};
+    use crate::generators::{annulus, unit_square};
+
+    fn single_element(vertices: Vec<Point2<f64>>, element_type: ElementType) -> Mesh2d {
+        let indices = (0..vertices.len()).collect();
+        Mesh2d::new(vertices, vec![Element { indices }], element_type)
+    }
+
+    #[test]
+    fn test_ideal_elements() {
+        let s = 3.0f64.sqrt() / 2.0;
+        let equilateral = single_element(
+            vec![
+                Point2::new(0.0, 0.0),
+                Point2::new(1.0, 0.0),
+                Point2::new(0.5, s),
+            ],
+            ElementType::P1,
+        );
+        let q = equilateral.element_quality(0);
+        assert!((q.aspect_ratio - 1.0).abs() < 1e-12);
+        assert!((q.min_angle - 60.0).abs() < 1e-12);
+        assert!((q.signed_area - s / 2.0).abs() < 1e-12);
+        assert!((q.jacobian_ratio - 1.0).abs() < 1e-12);
+
+        let (square, _) = unit_square(1, ElementType::Q1);
+        let q = square.element_quality(0);
+        assert_eq!(q.aspect_ratio, 1.0);
+        assert!((q.min_angle - 90.0).abs() < 1e-12);
+        assert_eq!(q.jacobian_ratio, 1.0);
+    }
+
+    #[test]
+    fn test_distorted_elements() {
+        let sliver = single_element(
+            vec![
+                Point2::new(0.0, 0.0),
+                Point2::new(1.0, 0.0),
+                Point2::new(0.5, 0.01),
+            ],
+            ElementType::P1,
+        );
+        let q = sliver.element_quality(0);
+        assert!(q.aspect_ratio > 100.0);
+        assert!(q.min_angle < 2.0);
+
+        // A non-convex quadrangle: the Jacobian changes sign at the re-entrant corner.
+        let dart = single_element(
+            vec![
+                Point2::new(0.0, 0.0),
+                Point2::new(2.0, 0.0),
+                Point2::new(0.5, 0.5),
+                Point2::new(0.0, 2.0),
+            ],
+            ElementType::Q1,
+        );
+        let q = dart.element_quality(0);
+        assert!(q.jacobian_ratio < 0.0);
+        assert!((q.signed_area - 1.0).abs() < 1e-12);
+
+        let clockwise = single_element(
+            vec![
+                Point2::new(0.0, 0.0),
+                Point2::new(0.0, 1.0),
+                Point2::new(1.0, 0.0),
+            ],
+            ElementType::P1,
+        );
+        assert!(clockwise.element_quality(0).signed_area < 0.0);
+        assert_eq!(clockwise.quality_summary().num_inverted, 1);
+    }
+
+    #[test]
+    fn test_quality_summary() {
+        let (mesh, _) = unit_square(4, ElementType::P1);
+        let summary = mesh.quality_summary();
+        assert_eq!(summary.num_elements, 32);
+        assert_eq!(summary.num_inverted, 0);
+        assert!((summary.min_area - 1.0 / 32.0).abs() < 1e-12);
+        assert!((summary.min_angle - 45.0).abs() < 1e-12);
+        assert!((summary.mean_min_angle - 45.0).abs() < 1e-12);
+        // Right isosceles triangles: R / 2r = (1 + √2) / 2.
+        assert!((summary.max_aspect_ratio - (1.0 + 2.0f64.sqrt()) / 2.0).abs() < 1e-12);
+
+        // Curved quadratic elements are no longer affine.
+        let (curved, _) = annulus(0.5, 1.0, 2, 8, ElementType::Q2);
+        let summary = curved.quality_summary();
+        assert!(summary.min_jacobian_ratio < 1.0 && summary.min_jacobian_ratio > 0.5);
+    }
+}
+// ANCHOR_END: tests
@@ -0,0 +1,245 @@
+//! Module that refines a mesh uniformly and transfers nodal vectors to the refined mesh.
+//!
+//! Every triangle and quadrangle is split into four by its edge midpoints (and its centre
+//! for quadrangles). The finite element spaces are nested, so the prolongation operator
+//! interpolates a coarse nodal vector exactly.
+use crate::element::{Element, ElementType};
+use crate::mesh::{Edge, Mesh2d, MeshError};
+use nalgebra::{Point2, Vector2};
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+use std::collections::HashMap;
+
+// ANCHOR: refine_uniform
+impl Mesh2d {
+    /// Splits each element into four and returns the refined mesh together with the
+    /// prolongation operator.
+    ///
+    /// The coarse vertices keep their indices, followed by the edge midpoints and the
+    /// centres of the quadrangles. The children of element `e` are the elements `4e` to
+    /// `4e + 3`, and boundary groups and regions are inherited. The prolongation `P` maps
+    /// coarse nodal vectors to fine ones, `u_fine = P u_coarse`. Only meshes of `P1`
+    /// triangles and `Q1` quadrangles are supported.
+    pub fn refine_uniform(&self) -> Result<(Mesh2d, CsrMatrix<f64>), MeshError> {
+        for (e, element_type) in self.element_types().iter().enumerate() {
+            if !matches!(element_type, ElementType::P1 | ElementType::Q1) {
+                return Err(MeshError::UnsupportedElementType {
+                    element: e,
+                    element_type: element_type.clone(),
+                });
+            }
+        }
+
+        let num_coarse = self.vertices().len();
+        let mut vertices = self.vertices().to_vec();
+        // Coarse nodes and weights defining each new vertex.
+        let mut parents: Vec<Vec<(usize, f64)>> = Vec::new();
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        for edge in self.edges() {
+            let key = edge.key();
+            midpoints.insert(key, vertices.len());
+            vertices.push(nalgebra::center(
+                &vertices[edge.indices[0]],
+                &vertices[edge.indices[1]],
+            ));
+            parents.push(vec![(key.0, 0.5), (key.1, 0.5)]);
+        }
+        let midpoint = |a: usize, b: usize| midpoints[&(a.min(b), a.max(b))];
+
+        let mut elements = Vec::with_capacity(4 * self.elements().len());
+        for element in self.elements() {
+            let children: Vec<Vec<usize>> = match element.indices[..] {
+                [a, b, c] => {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    vec![
+                        vec![a, ab, ca],
+                        vec![ab, b, bc],
+                        vec![ca, bc, c],
+                        vec![ab, bc, ca],
+                    ]
+                }
+                [a, b, c, d] => {
+                    let (ab, bc, cd, da) = (
+                        midpoint(a, b),
+                        midpoint(b, c),
+                        midpoint(c, d),
+                        midpoint(d, a),
+                    );
+                    let centre = vertices.len();
+                    let corners = [a, b, c, d].map(|i| vertices[i].coords);
+                    vertices.push(Point2::from(corners.iter().sum::<Vector2<f64>>() / 4.0));
+                    parents.push([a, b, c, d].iter().map(|&i| (i, 0.25)).collect());
+                    vec![
+                        vec![a, ab, centre, da],
+                        vec![ab, b, bc, centre],
+                        vec![centre, bc, c, cd],
+                        vec![da, centre, cd, d],
+                    ]
+                }
+                _ => unreachable!("linear elements have three or four nodes"),
+            };
+            elements.extend(children.into_iter().map(|indices| Element { indices }));
+        }
+
+        let element_types = self
+            .element_types()
+            .iter()
+            .flat_map(|element_type| std::iter::repeat_n(element_type.clone(), 4))
+            .collect();
+        let mut refined = Mesh2d::new_mixed(vertices, elements, element_types);
+        for name in self.boundary_group_names() {
+            let group = self
+                .boundary_group(name)
+                .unwrap()
+                .iter()
+                .flat_map(|edge| {
+                    let [a, b] = [edge.indices[0], edge.indices[1]];
+                    let m = midpoint(a, b);
+                    [
+                        Edge {
+                            indices: vec![a, m],
+                        },
+                        Edge {
+                            indices: vec![m, b],
+                        },
+                    ]
+                })
+                .collect();
+            refined.add_boundary_group(name, group);
+        }
+        for name in self.region_names() {
+            let elements = self
+                .region(name)
+                .unwrap()
+                .iter()
+                .flat_map(|&e| 4 * e..4 * e + 4)
+                .collect();
+            refined.add_region(name, elements);
+        }
+
+        let num_fine = refined.vertices().len();
+        let mut prolongation = CooMatrix::new(num_fine, num_coarse);
+        for i in 0..num_coarse {
+            prolongation.push(i, i, 1.0);
+        }
+        for (k, weights) in parents.iter().enumerate() {
+            for &(j, w) in weights {
+                prolongation.push(num_coarse + k, j, w);
+            }
+        }
+        Ok((refined, CsrMatrix::from(&prolongation)))
+    }
+}
+// ANCHOR_END: refine_uniform
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::{annulus, unit_square};
+    use crate::postprocess::{PointLocator, integrate};
+    use nalgebra::DVector;
+
+    /// A quadrangle and two triangles covering [0, 2] x [0, 1].
+    fn mixed_mesh() -> Mesh2d {
+        let vertices = vec![
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 0.0),
+            Point2::new(2.0, 0.0),
+            Point2::new(0.0, 1.0),
+            Point2::new(1.0, 1.0),
+            Point2::new(2.0, 1.0),
+        ];
+        let elements = vec![
+            Element {
+                indices: vec![0, 1, 4, 3],
+            },
+            Element {
+                indices: vec![1, 2, 5],
+            },
+            Element {
+                indices: vec![1, 5, 4],
+            },
+        ];
+        let element_types = vec![ElementType::Q1, ElementType::P1, ElementType::P1];
+        let mut mesh = Mesh2d::new_mixed(vertices, elements, element_types);
+        mesh.tag_boundary("right", |p| p.x == 2.0);
+        mesh.add_region("triangles", vec![1, 2]);
+        mesh
+    }
+
+    fn boundary_length(mesh: &Mesh2d, name: &str) -> f64 {
+        mesh.boundary_group(name)
+            .unwrap()
+            .iter()
+            .map(|e| (mesh.vertices()[e.indices[1]] - mesh.vertices()[e.indices[0]]).norm())
+            .sum()
+    }
+
+    #[test]
+    fn test_refine_uniform() {
+        let meshes = [
+            mixed_mesh(),
+            unit_square(3, ElementType::P1).0,
+            unit_square(3, ElementType::Q1).0,
+            annulus(0.5, 1.0, 2, 8, ElementType::Q1).0,
+        ];
+        for coarse in meshes {
+            let (fine, _) = coarse.refine_uniform().unwrap();
+            fine.validate().unwrap();
+            assert_eq!(fine.elements().len(), 4 * coarse.elements().len());
+            assert_eq!(
+                &fine.vertices()[..coarse.vertices().len()],
+                coarse.vertices()
+            );
+            assert_eq!(
+                fine.boundary_edges().len(),
+                2 * coarse.boundary_edges().len()
+            );
+
+            // Same area, boundary groups and regions.
+            let ones = |mesh: &Mesh2d| DVector::from_element(mesh.vertices().len(), 1.0);
+            let area = integrate(&coarse, &ones(&coarse));
+            assert!((integrate(&fine, &ones(&fine)) - area).abs() < 1e-13);
+            for name in coarse.boundary_group_names() {
+                let length = boundary_length(&coarse, name);
+                assert!((boundary_length(&fine, name) - length).abs() < 1e-13);
+            }
+            for name in coarse.region_names() {
+                assert_eq!(
+                    fine.region(name).unwrap().len(),
+                    4 * coarse.region(name).unwrap().len()
+                );
+            }
+        }
+
+        let (mesh, _) = unit_square(2, ElementType::P2);
+        assert!(matches!(
+            mesh.refine_uniform(),
+            Err(MeshError::UnsupportedElementType { element: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_prolongation() {
+        for coarse in [mixed_mesh(), annulus(0.5, 1.0, 2, 8, ElementType::Q1).0] {
+            let (fine, prolongation) = coarse.refine_uniform().unwrap();
+            assert_eq!(prolongation.nrows(), fine.vertices().len());
+            assert_eq!(prolongation.ncols(), coarse.vertices().len());
+
+            // The prolongated vector represents the same finite element function.
+            let u = DVector::from_iterator(
+                coarse.vertices().len(),
+                coarse
+                    .vertices()
+                    .iter()
+                    .map(|p| (3.0 * p.x).sin() + p.x * p.y),
+            );
+            let u_fine = &prolongation * &u;
+            let locator = PointLocator::new(&coarse);
+            for (p, value) in fine.vertices().iter().zip(u_fine.iter()) {
+                assert!((locator.evaluate(&u, p).unwrap() - value).abs() < 1e-12);
+            }
+        }
+    }
+}
+// ANCHOR_END: tests
@@ -1,174 +1,553 @@
-use crate::element::{ElementType, ReferenceElement};
-use crate::mesh::Mesh2d;
-use crate::quadrature::QuadRule;
+use crate::coefficient::Diffusion;
+use crate::direct::{Reordering, SparseCholesky};
+use crate::element::{ElementType, ReferenceEdge, ReferenceElement};
+use crate::forms::{BilinearForm, Mass, QuadPoint};
+use crate::iterative::{ConvergenceHistory, IterativeConfig, solve_iterative};
+use crate::mesh::{Edge, Mesh2d, MeshError};
+use crate::quadrature::{LineQuadRule, QuadRule};
 use nalgebra::{DMatrix, DVector, Point2, Vector2};
+use nalgebra_sparse::pattern::SparsityPattern;
 use nalgebra_sparse::{CooMatrix, CsrMatrix};
-use nalgebra_sparse_linalg::iteratives::conjugate_gradient;
+use std::collections::HashMap;
+
+// ANCHOR: error_enum
+/// Errors returned by the high-level solver functions.
+#[derive(Debug, thiserror::Error)]
+pub enum PoissonError {
+    /// The mesh failed `Mesh2d::validate`
+    #[error("invalid mesh: {0}")]
+    InvalidMesh(#[from] MeshError),
+    /// A boundary node index does not refer to a mesh vertex
+    #[error("boundary node {node} is out of range (the mesh has {num_vertices} vertices)")]
+    BoundaryNodeOutOfRange { node: usize, num_vertices: usize },
+    /// The system matrix is singular or not positive definite, e.g. because the boundary
+    /// conditions do not fix the solution
+    #[error("the system matrix is singular or not positive definite")]
+    SingularSystem,
+    /// A coefficient refers to a region the mesh does not have
+    #[error("the mesh has no region named \"{0}\"")]
+    UnknownRegion(String),
+    /// The matrix does not have the sparsity pattern expected by the operation
+    #[error("the matrix does not have the sparsity pattern of the mesh")]
+    PatternMismatch,
+    /// The iterative solver stopped before reaching the tolerance
+    #[error(
+        "the iterative solver did not converge in {iterations} iterations (relative residual {residual:e})"
+    )]
+    NotConverged { iterations: usize, residual: f64 },
+    /// GMRES or BiCGStab cannot build the next iterate
+    #[error("the iterative solver broke down after {iterations} iterations")]
+    Breakdown { iterations: usize },
+    /// The preconditioner met a zero or non-positive pivot
+    #[error("the preconditioner could not be built (pivot of row {row} is not positive)")]
+    PreconditionerBreakdown { row: usize },
+    /// A vector does not have the size of the system
+    #[error("expected a vector of length {expected}, got {found}")]
+    DimensionMismatch { expected: usize, found: usize },
+    /// More eigenpairs are requested than the problem has unknowns
+    #[error("requested {requested} eigenpairs but the problem only has {available} unknowns")]
+    TooManyEigenpairs { requested: usize, available: usize },
+}
+// ANCHOR_END: error_enum
 
 /// Function that assembles the FEM system using a dense matrix.
 // ANCHOR: assemble_system_dense
 pub fn assemble_system_dense<F>(mesh: &Mesh2d, source_fn: &F) -> (DMatrix<f64>, DVector<f64>)
 where
-    F: Fn(f64, f64) -> f64,
+    F: Fn(f64, f64) -> f64 + Sync,
 {
-    let num_vertices = mesh.vertices().len();
-    let mut a = DMatrix::zeros(num_vertices, num_vertices);
-    let mut b = DVector::zeros(num_vertices);
+    assemble_form_dense(mesh, &Diffusion::Constant(1.0), source_fn)
+        .expect("a constant coefficient does not refer to mesh regions")
+}
+// ANCHOR_END: assemble_system_dense
 
-    // Pick the right reference element based on the element type in the mesh.
-    let ref_element = match mesh.element_type() {
-        ElementType::P1 => ReferenceElement::Tri3,
-        ElementType::Q1 => ReferenceElement::Quad4,
-    };
+/// Function that assembles the FEM system of `-div(κ ∇u) = f` using a dense matrix.
+// ANCHOR: assemble_diffusion_system_dense
+pub fn assemble_diffusion_system_dense<F>(
+    mesh: &Mesh2d,
+    kappa: &Diffusion,
+    source_fn: &F,
+) -> Result<(DMatrix<f64>, DVector<f64>), PoissonError>
+where
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    assemble_form_dense(mesh, kappa, source_fn)
+}
+// ANCHOR_END: assemble_diffusion_system_dense
 
-    // Pick the right quadrature rule based on the element type in the mesh.
-    // We use second-order quadrature rules by default.
-    let quad_rule = match mesh.element_type() {
-        ElementType::P1 => QuadRule::triangle(2),
-        ElementType::Q1 => QuadRule::quadrilateral(2),
-    };
+/// Reference element and quadrature rule of every element type present in the mesh.
+fn element_rules(mesh: &Mesh2d) -> HashMap<ElementType, (ReferenceElement, QuadRule)> {
+    let mut rules = HashMap::new();
+    for element_type in mesh.element_types() {
+        if !rules.contains_key(element_type) {
+            let rule = (
+                element_type.reference_element(),
+                QuadRule::for_element(element_type),
+            );
+            rules.insert(element_type.clone(), rule);
+        }
+    }
+    rules
+}
 
+/// Largest distance between two nodes of an element.
+fn element_diameter(nodes: &[Point2<f64>]) -> f64 {
+    let mut h: f64 = 0.0;
+    for (k, p) in nodes.iter().enumerate() {
+        for q in &nodes[k + 1..] {
+            h = h.max((q - p).norm());
+        }
+    }
+    h
+}
+
+/// Function that computes the element matrix `ke` and load vector `fe` of one element.
+// ANCHOR: element_system
+fn element_system<B, F>(
+    mesh: &Mesh2d,
+    rules: &HashMap<ElementType, (ReferenceElement, QuadRule)>,
+    form: &B,
+    form_data: &B::MeshData,
+    source_fn: &F,
+    element_index: usize,
+) -> (Vec<Vec<f64>>, Vec<f64>)
+where
+    B: BilinearForm,
+    F: Fn(f64, f64) -> f64,
+{
+    // Pick the right reference element and quadrature rule based on the element type.
+    let (ref_element, quad_rule) = &rules[mesh.element_type_of(element_index)];
     let n: usize = ref_element.num_nodes();
-    for element in mesh.elements() {
-        // Get the coordinates of the element nodes
-        let mut nodes: Vec<Point2<f64>> = Vec::with_capacity(n);
-        for vid in &element.indices {
-            let vertex = mesh.vertices()[*vid];
-            nodes.push(vertex);
-        }
-
-        // Compute the local stiff and load vectors
-        let mut ke = vec![vec![0.0; n]; n];
-        let mut fe = vec![0.0; n];
-        for (quad_points, quad_weights) in quad_rule.points.iter().zip(quad_rule.weights.iter()) {
-            // Compute local quantities in the reference element
-            let grads_ref = ref_element.shape_gradients(quad_points);
-            let jac_ref = ref_element.jacobian(&nodes, quad_points);
-            let det_jac_ref = jac_ref.determinant();
-            let jac_inv_t = jac_ref.try_inverse().unwrap().transpose();
-
-            // Compute gradient in the physical space
-            let mut grads_global: Vec<Vector2<f64>> = Vec::with_capacity(n);
-            for grad_ref in grads_ref {
-                let grad = jac_inv_t * grad_ref;
-                grads_global.push(grad);
-            }
 
-            // Evaluate physical coordinates of quadrature point
-            let shape_vals = ref_element.shape_functions(quad_points);
-            let mut x = 0.0;
-            let mut y = 0.0;
-            for (val, vtx) in shape_vals.iter().zip(&nodes) {
-                x += val * vtx.x;
-                y += val * vtx.y;
-            }
+    // Get the coordinates of the element nodes
+    let mut nodes: Vec<Point2<f64>> = Vec::with_capacity(n);
+    for vid in &mesh.elements()[element_index].indices {
+        let vertex = mesh.vertices()[*vid];
+        nodes.push(vertex);
+    }
+    let h = element_diameter(&nodes);
 
-            // Fill ke and fe
-            let f_val = source_fn(x, y);
-            let weight = quad_weights * det_jac_ref.abs();
-            for i in 0..n {
-                for j in 0..n {
-                    ke[i][j] += grads_global[i].dot(&grads_global[j]) * weight;
-                }
-                fe[i] += shape_vals[i] * f_val * weight;
-            }
+    // Compute the local stiff and load vectors
+    let mut ke = vec![vec![0.0; n]; n];
+    let mut fe = vec![0.0; n];
+    for (quad_points, quad_weights) in quad_rule.points.iter().zip(quad_rule.weights.iter()) {
+        // Compute local quantities in the reference element
+        let grads_ref = ref_element.shape_gradients(quad_points);
+        let jac_ref = ref_element.jacobian(&nodes, quad_points);
+        let det_jac_ref = jac_ref.determinant();
+        let jac_inv_t = jac_ref
+            .try_inverse()
+            .expect("degenerate element, see Mesh2d::validate")
+            .transpose();
+
+        // Compute gradient in the physical space
+        let mut grads_global: Vec<Vector2<f64>> = Vec::with_capacity(n);
+        for grad_ref in grads_ref {
+            let grad = jac_inv_t * grad_ref;
+            grads_global.push(grad);
+        }
+
+        let hessians_global = ref_element.physical_hessians(&nodes, quad_points, &jac_inv_t);
+
+        // Evaluate physical coordinates of quadrature point
+        let shape_vals = ref_element.shape_functions(quad_points);
+        let mut x = 0.0;
+        let mut y = 0.0;
+        for (val, vtx) in shape_vals.iter().zip(&nodes) {
+            x += val * vtx.x;
+            y += val * vtx.y;
         }
 
-        // Assemble into global matrix/vector
-        for (local_i, &global_i) in element.indices.iter().enumerate() {
-            b[global_i] += fe[local_i];
-            for (local_j, &global_j) in element.indices.iter().enumerate() {
-                a[(global_i, global_j)] += ke[local_i][local_j];
+        // Fill ke and fe
+        let qp = QuadPoint {
+            element: element_index,
+            x,
+            y,
+            weight: quad_weights * det_jac_ref.abs(),
+            shape_values: &shape_vals,
+            shape_gradients: &grads_global,
+            shape_hessians: &hessians_global,
+            h,
+        };
+        form.add_element_matrix(form_data, &qp, &mut ke);
+        form.add_element_load(form_data, &qp, source_fn(x, y), &mut fe);
+    }
+    (ke, fe)
+}
+// ANCHOR_END: element_system
+
+/// Global contributions of a set of elements, filled element by element by
+/// `assemble_elements`.
+trait Accumulator {
+    fn add(&mut self, element_index: usize, indices: &[usize], ke: &[Vec<f64>], fe: &[f64]);
+
+    /// Adds the contributions gathered by another thread.
+    #[cfg(feature = "parallel")]
+    fn merge(self, other: Self) -> Self;
+}
+
+/// Matrix entries stored as triplets, duplicates included, along with the load vector.
+struct Triplets {
+    entries: Vec<(usize, usize, f64)>,
+    b: DVector<f64>,
+}
+
+impl Triplets {
+    fn new(num_vertices: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            b: DVector::zeros(num_vertices),
+        }
+    }
+}
+
+impl Accumulator for Triplets {
+    fn add(&mut self, _element_index: usize, indices: &[usize], ke: &[Vec<f64>], fe: &[f64]) {
+        for (local_i, &global_i) in indices.iter().enumerate() {
+            self.b[global_i] += fe[local_i];
+            for (local_j, &global_j) in indices.iter().enumerate() {
+                self.entries
+                    .push((global_i, global_j, ke[local_i][local_j]));
             }
         }
     }
 
-    (a, b)
+    #[cfg(feature = "parallel")]
+    fn merge(mut self, mut other: Self) -> Self {
+        self.entries.append(&mut other.entries);
+        self.b += other.b;
+        self
+    }
 }
-// ANCHOR_END: assemble_system_dense
+
+/// Assembly core shared by the dense and sparse routines: computes the element matrices
+/// and load vectors and adds them to an accumulator created by `empty`.
+///
+/// With the `parallel` feature, the elements are split between the rayon threads, each
+/// thread filling its own accumulator, and the accumulators are merged at the end.
+// ANCHOR: assemble_elements
+fn assemble_elements<B, F, A, E>(
+    mesh: &Mesh2d,
+    form: &B,
+    source_fn: &F,
+    empty: E,
+) -> Result<A, PoissonError>
+where
+    B: BilinearForm,
+    F: Fn(f64, f64) -> f64 + Sync,
+    A: Accumulator + Send,
+    E: Fn() -> A + Sync,
+{
+    // Reference elements and quadrature rules of the element types in the mesh.
+    let rules = element_rules(mesh);
+
+    // Data needed by the form on the whole mesh (e.g. region coefficients)
+    let form_data = form.mesh_data(mesh)?;
+
+    let add = |mut acc: A, element_index: usize| {
+        let (ke, fe) = element_system(mesh, &rules, form, &form_data, source_fn, element_index);
+        let indices = &mesh.elements()[element_index].indices;
+        acc.add(element_index, indices, &ke, &fe);
+        acc
+    };
+
+    #[cfg(not(feature = "parallel"))]
+    let acc = (0..mesh.elements().len()).fold(empty(), add);
+
+    #[cfg(feature = "parallel")]
+    let acc = {
+        use rayon::prelude::*;
+
+        (0..mesh.elements().len())
+            .into_par_iter()
+            .fold(&empty, add)
+            .reduce(&empty, A::merge)
+    };
+
+    Ok(acc)
+}
+// ANCHOR_END: assemble_elements
+
+/// Function that assembles the FEM system of any bilinear form using a dense matrix.
+// ANCHOR: assemble_form_dense
+pub fn assemble_form_dense<B, F>(
+    mesh: &Mesh2d,
+    form: &B,
+    source_fn: &F,
+) -> Result<(DMatrix<f64>, DVector<f64>), PoissonError>
+where
+    B: BilinearForm,
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    let num_vertices = mesh.vertices().len();
+    let triplets = assemble_elements(mesh, form, source_fn, || Triplets::new(num_vertices))?;
+
+    // Assemble into global matrix
+    let mut a = DMatrix::zeros(num_vertices, num_vertices);
+    for (i, j, v) in triplets.entries {
+        a[(i, j)] += v;
+    }
+
+    Ok((a, triplets.b))
+}
+// ANCHOR_END: assemble_form_dense
 
 /// Function that assembles the FEM using a sparse matrix.
 // ANCHOR: assemble_system_sparse
 pub fn assemble_system_sparse<F>(mesh: &Mesh2d, source_fn: &F) -> (CsrMatrix<f64>, DVector<f64>)
 where
-    F: Fn(f64, f64) -> f64,
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    assemble_form_sparse(mesh, &Diffusion::Constant(1.0), source_fn)
+        .expect("a constant coefficient does not refer to mesh regions")
+}
+// ANCHOR_END: assemble_system_sparse
+
+/// Function that assembles the FEM system of `-div(κ ∇u) = f` using a sparse matrix.
+// ANCHOR: assemble_diffusion_system_sparse
+pub fn assemble_diffusion_system_sparse<F>(
+    mesh: &Mesh2d,
+    kappa: &Diffusion,
+    source_fn: &F,
+) -> Result<(CsrMatrix<f64>, DVector<f64>), PoissonError>
+where
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    assemble_form_sparse(mesh, kappa, source_fn)
+}
+// ANCHOR_END: assemble_diffusion_system_sparse
+
+/// Function that assembles the FEM system of any bilinear form using a sparse matrix.
+// ANCHOR: assemble_form_sparse
+pub fn assemble_form_sparse<B, F>(
+    mesh: &Mesh2d,
+    form: &B,
+    source_fn: &F,
+) -> Result<(CsrMatrix<f64>, DVector<f64>), PoissonError>
+where
+    B: BilinearForm,
+    F: Fn(f64, f64) -> f64 + Sync,
 {
     let num_vertices = mesh.vertices().len();
+    let triplets = assemble_elements(mesh, form, source_fn, || Triplets::new(num_vertices))?;
+
     let mut coo = CooMatrix::new(num_vertices, num_vertices);
-    let mut b = DVector::zeros(num_vertices);
+    for (i, j, v) in triplets.entries {
+        coo.push(i, j, v);
+    }
 
-    // Pick the right reference element based on the element type in the mesh.
-    let ref_element = match mesh.element_type() {
-        ElementType::P1 => ReferenceElement::Tri3,
-        ElementType::Q1 => ReferenceElement::Quad4,
-    };
+    let a = CsrMatrix::from(&coo);
+    Ok((a, triplets.b))
+}
+// ANCHOR_END: assemble_form_sparse
 
-    // Pick the right quadrature rule based on the element type in the mesh.
-    // We use second-order quadrature rules by default.
-    let quad_rule = match mesh.element_type() {
-        ElementType::P1 => QuadRule::triangle(2),
-        ElementType::Q1 => QuadRule::quadrilateral(2),
-    };
+// ANCHOR: system_pattern
+/// Sparsity pattern of the FEM matrix of a mesh, computed from its connectivity.
+///
+/// Also stores where the entries of each element matrix go in the CSR values, so that the
+/// system can be reassembled in place with `reassemble_form_sparse`.
+pub struct SystemPattern {
+    pattern: SparsityPattern,
+    /// Positions of the entries of the element matrices in the CSR values, element by
+    /// element and row-major within each element
+    positions: Vec<usize>,
+    /// Start of the positions of each element, with a final entry equal to `positions.len()`
+    element_offsets: Vec<usize>,
+}
 
-    let n: usize = ref_element.num_nodes();
-    for element in mesh.elements() {
-        // Get the coordinates of the element nodes
-        let mut nodes: Vec<Point2<f64>> = Vec::with_capacity(n);
-        for vid in &element.indices {
-            let vertex = mesh.vertices()[*vid];
-            nodes.push(vertex);
-        }
-
-        // Compute the local stiff and load vectors
-        let mut ke = vec![vec![0.0; n]; n];
-        let mut fe = vec![0.0; n];
-        for (quad_points, quad_weights) in quad_rule.points.iter().zip(quad_rule.weights.iter()) {
-            // Compute local quantities in the reference element
-            let grads_ref = ref_element.shape_gradients(quad_points);
-            let jac_ref = ref_element.jacobian(&nodes, quad_points);
-            let det_jac_ref = jac_ref.determinant();
-            let jac_inv_t = jac_ref.try_inverse().unwrap().transpose();
-
-            // Compute gradient in the physical space
-            let mut grads_global: Vec<Vector2<f64>> = Vec::with_capacity(n);
-            for grad_ref in grads_ref {
-                let grad = jac_inv_t * grad_ref;
-                grads_global.push(grad);
-            }
+impl SystemPattern {
+    pub fn new(mesh: &Mesh2d) -> Self {
+        let num_vertices = mesh.vertices().len();
 
-            // Evaluate physical coordinates of quadrature point
-            let shape_vals = ref_element.shape_functions(quad_points);
-            let mut x = 0.0;
-            let mut y = 0.0;
-            for (val, vtx) in shape_vals.iter().zip(&nodes) {
-                x += val * vtx.x;
-                y += val * vtx.y;
+        // Two nodes are coupled when they share an element.
+        let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); num_vertices];
+        for element in mesh.elements() {
+            for &i in &element.indices {
+                neighbours[i].extend_from_slice(&element.indices);
             }
+        }
+        let mut offsets = Vec::with_capacity(num_vertices + 1);
+        let mut indices = Vec::new();
+        offsets.push(0);
+        for mut row in neighbours {
+            row.sort_unstable();
+            row.dedup();
+            indices.extend(row);
+            offsets.push(indices.len());
+        }
+        let pattern = SparsityPattern::try_from_offsets_and_indices(
+            num_vertices,
+            num_vertices,
+            offsets,
+            indices,
+        )
+        .expect("rows are sorted and within the mesh");
 
-            // Fill ke and fe
-            let f_val = source_fn(x, y);
-            let weight = quad_weights * det_jac_ref.abs();
-            for i in 0..n {
-                for j in 0..n {
-                    ke[i][j] += grads_global[i].dot(&grads_global[j]) * weight;
+        let mut positions = Vec::new();
+        let mut element_offsets = Vec::with_capacity(mesh.elements().len() + 1);
+        element_offsets.push(0);
+        for element in mesh.elements() {
+            for &i in &element.indices {
+                let row_start = pattern.major_offsets()[i];
+                let row = pattern.lane(i);
+                for j in &element.indices {
+                    let pos = row
+                        .binary_search(j)
+                        .expect("nodes of an element are coupled");
+                    positions.push(row_start + pos);
                 }
-                fe[i] += shape_vals[i] * f_val * weight;
             }
+            element_offsets.push(positions.len());
+        }
+
+        Self {
+            pattern,
+            positions,
+            element_offsets,
         }
+    }
+
+    pub fn pattern(&self) -> &SparsityPattern {
+        &self.pattern
+    }
+
+    /// Matrix with this pattern and all its values set to zero.
+    pub fn zeros(&self) -> CsrMatrix<f64> {
+        let values = vec![0.0; self.pattern.nnz()];
+        CsrMatrix::try_from_pattern_and_values(self.pattern.clone(), values)
+            .expect("one value per entry of the pattern")
+    }
+}
+// ANCHOR_END: system_pattern
+
+/// CSR values and load vector of a system with a fixed pattern.
+struct PatternValues<'a> {
+    pattern: &'a SystemPattern,
+    values: Vec<f64>,
+    b: DVector<f64>,
+}
 
-        for (local_i, &global_i) in element.indices.iter().enumerate() {
-            b[global_i] += fe[local_i];
-            for (local_j, &global_j) in element.indices.iter().enumerate() {
-                let stiffness: f64 = ke[local_i][local_j];
-                coo.push(global_i, global_j, stiffness);
+impl Accumulator for PatternValues<'_> {
+    fn add(&mut self, element_index: usize, indices: &[usize], ke: &[Vec<f64>], fe: &[f64]) {
+        let start = self.pattern.element_offsets[element_index];
+        let mut positions = self.pattern.positions[start..].iter();
+        for (local_i, &global_i) in indices.iter().enumerate() {
+            self.b[global_i] += fe[local_i];
+            for ke_ij in &ke[local_i] {
+                self.values[*positions.next().unwrap()] += ke_ij;
             }
         }
     }
 
-    let a = CsrMatrix::from(&coo);
-    (a, b)
+    #[cfg(feature = "parallel")]
+    fn merge(mut self, other: Self) -> Self {
+        for (value, other_value) in self.values.iter_mut().zip(&other.values) {
+            *value += other_value;
+        }
+        self.b += other.b;
+        self
+    }
 }
-// ANCHOR_END: assemble_system_sparse
+
+/// Function that assembles the FEM system of any bilinear form into an existing sparse
+/// matrix and load vector, overwriting their values without reallocating them.
+///
+/// The matrix must have the pattern of `pattern`, e.g. be created by `SystemPattern::zeros`
+/// or a previous call, and must not have been modified by `apply_dirichlet_sparse`, which
+/// removes entries; use `apply_dirichlet_sparse_in_place` instead.
+// ANCHOR: reassemble_form_sparse
+pub fn reassemble_form_sparse<B, F>(
+    pattern: &SystemPattern,
+    mesh: &Mesh2d,
+    form: &B,
+    source_fn: &F,
+    a: &mut CsrMatrix<f64>,
+    b: &mut DVector<f64>,
+) -> Result<(), PoissonError>
+where
+    B: BilinearForm,
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    if pattern.element_offsets.len() != mesh.elements().len() + 1 || a.pattern() != &pattern.pattern
+    {
+        return Err(PoissonError::PatternMismatch);
+    }
+    if b.len() != a.nrows() {
+        return Err(PoissonError::DimensionMismatch {
+            expected: a.nrows(),
+            found: b.len(),
+        });
+    }
+
+    let nnz = a.nnz();
+    let num_vertices = b.len();
+    let acc = assemble_elements(mesh, form, source_fn, || PatternValues {
+        pattern,
+        values: vec![0.0; nnz],
+        b: DVector::zeros(num_vertices),
+    })?;
+    a.values_mut().copy_from_slice(&acc.values);
+    b.copy_from(&acc.b);
+    Ok(())
+}
+// ANCHOR_END: reassemble_form_sparse
+
+/// Function that assembles the mass matrix `M_ij = ∫ φ_i φ_j` using a dense matrix.
+// ANCHOR: assemble_mass_dense
+pub fn assemble_mass_dense(mesh: &Mesh2d) -> DMatrix<f64> {
+    assemble_form_dense(mesh, &Mass, &|_, _| 0.0)
+        .expect("the mass form does not refer to mesh regions")
+        .0
+}
+// ANCHOR_END: assemble_mass_dense
+
+/// Function that assembles the mass matrix `M_ij = ∫ φ_i φ_j` using a sparse matrix.
+// ANCHOR: assemble_mass_sparse
+pub fn assemble_mass_sparse(mesh: &Mesh2d) -> CsrMatrix<f64> {
+    assemble_form_sparse(mesh, &Mass, &|_, _| 0.0)
+        .expect("the mass form does not refer to mesh regions")
+        .0
+}
+// ANCHOR_END: assemble_mass_sparse
+
+/// Load vector alone, for forms without matrix contribution.
+impl Accumulator for DVector<f64> {
+    fn add(&mut self, _element_index: usize, indices: &[usize], _ke: &[Vec<f64>], fe: &[f64]) {
+        for (local_i, &global_i) in indices.iter().enumerate() {
+            self[global_i] += fe[local_i];
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    fn merge(self, other: Self) -> Self {
+        self + other
+    }
+}
+
+/// Form without matrix contribution, used to assemble load vectors alone.
+struct LoadOnly;
+
+impl BilinearForm for LoadOnly {
+    type MeshData = ();
+
+    fn mesh_data(&self, _mesh: &Mesh2d) -> Result<Self::MeshData, PoissonError> {
+        Ok(())
+    }
+
+    fn add_element_matrix(&self, _data: &Self::MeshData, _qp: &QuadPoint, _ke: &mut [Vec<f64>]) {}
+}
+
+/// Function that assembles the load vector `b_i = ∫ f φ_i` alone.
+// ANCHOR: assemble_load_vector
+pub fn assemble_load_vector<F>(mesh: &Mesh2d, source_fn: &F) -> DVector<f64>
+where
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    let num_vertices = mesh.vertices().len();
+    assemble_elements(mesh, &LoadOnly, source_fn, || DVector::zeros(num_vertices))
+        .expect("the load form does not refer to mesh regions")
+}
+// ANCHOR_END: assemble_load_vector
 
 /// Function that applies Dirichlet boundary conditions to the dense FEM system.
 // ANCHOR: apply_dirichlet_dense
@@ -208,6 +587,11 @@ pub fn apply_dirichlet_dense<G>(
 } // ANCHOR_END: apply_dirichlet_dense
 
 /// Function that applies Dirichlet boundary conditions to the sparse FEM system.
+///
+/// All the constraints are applied in a single pass over the matrix: the columns of the
+/// boundary nodes are moved to the right-hand side and the rows of the boundary nodes are
+/// replaced by identity rows. The matrix is rebuilt without the removed entries, so that no
+/// explicit zeros are left in its pattern and the system stays symmetric.
 // ANCHOR: apply_dirichlet_sparse
 pub fn apply_dirichlet_sparse<G>(
     a: &mut CsrMatrix<f64>,
@@ -218,72 +602,273 @@ pub fn apply_dirichlet_sparse<G>(
 ) where
     G: Fn(f64, f64) -> f64,
 {
-    // Compute the boundary conditions values at each boundary node
-    let mut bc_vals = Vec::with_capacity(boundary_nodes.len());
+    let n = a.nrows();
+
+    // Boundary condition value of each constrained node
+    let mut constraints: Vec<Option<f64>> = vec![None; n];
     for &j in boundary_nodes {
         let v = &mesh.vertices()[j];
-        bc_vals.push((j, g(v.x, v.y)));
+        constraints[j] = Some(g(v.x, v.y));
+    }
+
+    let mut row_offsets = Vec::with_capacity(n + 1);
+    let mut col_indices = Vec::with_capacity(a.nnz());
+    let mut values = Vec::with_capacity(a.nnz());
+    row_offsets.push(0);
+    for i in 0..n {
+        if let Some(g_i) = constraints[i] {
+            // Identity row
+            col_indices.push(i);
+            values.push(1.0);
+            b[i] = g_i;
+        } else {
+            // b_i -= a_ij * g_j for the constrained columns j, other entries are kept
+            let row = a.row(i);
+            for (&j, &a_ij) in row.col_indices().iter().zip(row.values()) {
+                match constraints[j] {
+                    Some(g_j) => b[i] -= a_ij * g_j,
+                    None => {
+                        col_indices.push(j);
+                        values.push(a_ij);
+                    }
+                }
+            }
+        }
+        row_offsets.push(col_indices.len());
     }
 
+    *a = CsrMatrix::try_from_csr_data(n, n, row_offsets, col_indices, values)
+        .expect("rows keep their sorted column indices");
+}
+// ANCHOR_END: apply_dirichlet_sparse
+
+/// Function that applies Dirichlet boundary conditions to the sparse FEM system without
+/// changing its sparsity pattern.
+///
+/// Same elimination as `apply_dirichlet_sparse`, but the eliminated entries are set to zero
+/// instead of being removed, so that the matrix can later be reassembled in place.
+// ANCHOR: apply_dirichlet_sparse_in_place
+pub fn apply_dirichlet_sparse_in_place<G>(
+    a: &mut CsrMatrix<f64>,
+    b: &mut DVector<f64>,
+    boundary_nodes: &[usize],
+    mesh: &Mesh2d,
+    g: G,
+) where
+    G: Fn(f64, f64) -> f64,
+{
     let n = a.nrows();
 
-    for &(j, g_j) in &bc_vals {
-        // For each boundary node j, update rhs: b_i -= a_ij * g_j for all i
+    // Boundary condition value of each constrained node
+    let mut constraints: Vec<Option<f64>> = vec![None; n];
+    for &j in boundary_nodes {
+        let v = &mesh.vertices()[j];
+        constraints[j] = Some(g(v.x, v.y));
+    }
 
-        for i in 0..n {
-            let row = a.row(i);
-            let cols = row.col_indices();
-            let vals = row.values();
-            if let Some(pos) = cols.iter().position(|&c| c == j) {
-                b[i] -= vals[pos] * g_j;
+    for i in 0..n {
+        let mut row = a.row_mut(i);
+        let (cols, vals) = row.cols_and_values_mut();
+        if let Some(g_i) = constraints[i] {
+            for (&j, v) in cols.iter().zip(vals.iter_mut()) {
+                *v = if j == i { 1.0 } else { 0.0 };
+            }
+            b[i] = g_i;
+        } else {
+            for (&j, v) in cols.iter().zip(vals.iter_mut()) {
+                if let Some(g_j) = constraints[j] {
+                    b[i] -= *v * g_j;
+                    *v = 0.0;
+                }
             }
         }
+    }
+}
+// ANCHOR_END: apply_dirichlet_sparse_in_place
+
+/// Function that integrates the boundary terms of a Robin condition on a single edge.
+///
+/// Returns the edge mass matrix weighted by `alpha`, `me[i][j] = ∫ alpha N_i N_j ds`, and
+/// the edge load vector `ge[i] = ∫ g N_i ds`.
+// ANCHOR: edge_contributions
+fn edge_contributions<A, G>(
+    mesh: &Mesh2d,
+    edge: &Edge,
+    alpha: &A,
+    g: &G,
+) -> (Vec<Vec<f64>>, Vec<f64>)
+where
+    A: Fn(f64, f64) -> f64,
+    G: Fn(f64, f64) -> f64,
+{
+    // Edges of linear elements have two nodes, edges of quadratic elements three.
+    let ref_edge = match edge.indices.len() {
+        2 => ReferenceEdge::Line2,
+        _ => ReferenceEdge::Line3,
+    };
+    // n Gauss points integrate exactly the products of the n-node shape functions.
+    let quad_rule =
+        LineQuadRule::gauss_legendre(ref_edge.num_nodes()).expect("edges have at least two nodes");
+
+    let n = ref_edge.num_nodes();
+    let nodes: Vec<Point2<f64>> = edge.indices.iter().map(|&i| mesh.vertices()[i]).collect();
+    let mut me = vec![vec![0.0; n]; n];
+    let mut ge = vec![0.0; n];
+    for (s, w) in quad_rule.points.iter().zip(quad_rule.weights.iter()) {
+        let shape_vals = ref_edge.shape_functions(*s);
+        let weight = w * ref_edge.tangent(&nodes, *s).norm();
 
-        // Zero out row j
-        for v in a.row_mut(j).values_mut() {
-            *v = 0.0;
+        // Evaluate physical coordinates of quadrature point
+        let mut x = 0.0;
+        let mut y = 0.0;
+        for (val, vtx) in shape_vals.iter().zip(&nodes) {
+            x += val * vtx.x;
+            y += val * vtx.y;
         }
 
-        // Zero out column j to preserve symmetry
-        // We first collect the positions
-        let mut to_zero: Vec<(usize, usize)> = Vec::new();
+        let alpha_val = alpha(x, y);
+        let g_val = g(x, y);
         for i in 0..n {
-            let row_i = a.row(i);
-            let cols = row_i.col_indices();
-            if let Some(pos) = cols.iter().position(|&c| c == j) {
-                to_zero.push((i, pos));
+            for j in 0..n {
+                me[i][j] += alpha_val * shape_vals[i] * shape_vals[j] * weight;
             }
+            ge[i] += g_val * shape_vals[i] * weight;
         }
-        // Zero out the collected positions
-        for (i, pos) in to_zero {
-            a.row_mut(i).values_mut()[pos] = 0.0;
+    }
+    (me, ge)
+}
+// ANCHOR_END: edge_contributions
+
+/// Function that applies Neumann boundary conditions `∂u/∂n = h` on the given edges.
+///
+/// Only the load vector is modified, so the function serves both the dense and sparse
+/// systems. It must be called before applying the Dirichlet conditions.
+// ANCHOR: apply_neumann
+pub fn apply_neumann<H>(b: &mut DVector<f64>, mesh: &Mesh2d, edges: &[Edge], h: H)
+where
+    H: Fn(f64, f64) -> f64,
+{
+    let zero = |_: f64, _: f64| 0.0;
+    for edge in edges {
+        let (_, ge) = edge_contributions(mesh, edge, &zero, &h);
+        for (local_i, &global_i) in edge.indices.iter().enumerate() {
+            b[global_i] += ge[local_i];
         }
+    }
+}
+// ANCHOR_END: apply_neumann
 
-        // Set diagonal to 1.0
-        if let Some(pos) = a.row(j).col_indices().iter().position(|&c| c == j) {
-            a.row_mut(j).values_mut()[pos] = 1.0;
+/// Function that applies Robin boundary conditions `∂u/∂n + alpha u = g` to the dense FEM
+/// system. It must be called before applying the Dirichlet conditions.
+// ANCHOR: apply_robin_dense
+pub fn apply_robin_dense<A, G>(
+    a: &mut DMatrix<f64>,
+    b: &mut DVector<f64>,
+    mesh: &Mesh2d,
+    edges: &[Edge],
+    alpha: A,
+    g: G,
+) where
+    A: Fn(f64, f64) -> f64,
+    G: Fn(f64, f64) -> f64,
+{
+    for edge in edges {
+        let (me, ge) = edge_contributions(mesh, edge, &alpha, &g);
+        for (local_i, &global_i) in edge.indices.iter().enumerate() {
+            b[global_i] += ge[local_i];
+            for (local_j, &global_j) in edge.indices.iter().enumerate() {
+                a[(global_i, global_j)] += me[local_i][local_j];
+            }
         }
+    }
+}
+// ANCHOR_END: apply_robin_dense
 
-        b[j] = g_j;
+/// Function that applies Robin boundary conditions `∂u/∂n + alpha u = g` to the sparse FEM
+/// system. It must be called before applying the Dirichlet conditions.
+///
+/// The nodes of an edge belong to a common element, so the entries to update are part of
+/// the assembled sparsity pattern. Fails with `PatternMismatch` if one of them is missing,
+/// e.g. because the Dirichlet conditions already removed it.
+// ANCHOR: apply_robin_sparse
+pub fn apply_robin_sparse<A, G>(
+    a: &mut CsrMatrix<f64>,
+    b: &mut DVector<f64>,
+    mesh: &Mesh2d,
+    edges: &[Edge],
+    alpha: A,
+    g: G,
+) -> Result<(), PoissonError>
+where
+    A: Fn(f64, f64) -> f64,
+    G: Fn(f64, f64) -> f64,
+{
+    for edge in edges {
+        let (me, ge) = edge_contributions(mesh, edge, &alpha, &g);
+        for (local_i, &global_i) in edge.indices.iter().enumerate() {
+            b[global_i] += ge[local_i];
+            let mut row = a.row_mut(global_i);
+            let (cols, vals) = row.cols_and_values_mut();
+            for (local_j, &global_j) in edge.indices.iter().enumerate() {
+                let pos = cols
+                    .binary_search(&global_j)
+                    .map_err(|_| PoissonError::PatternMismatch)?;
+                vals[pos] += me[local_i][local_j];
+            }
+        }
     }
+    Ok(())
 }
-// ANCHOR_END: apply_dirichlet_sparse
+// ANCHOR_END: apply_robin_sparse
 
 /// Function that solves the dense FEM system.
+///
+/// Uses a Cholesky factorization, hence fails if the matrix is not positive definite.
+/// Pivots that vanish up to rounding errors are also reported as a singular system.
 // ANCHOR: dense_solver
-pub fn dense_solver(a: &DMatrix<f64>, b: &DVector<f64>) -> Option<DVector<f64>> {
-    let chol = a.clone().cholesky()?;
-    Some(chol.solve(b))
+pub fn dense_solver(a: &DMatrix<f64>, b: &DVector<f64>) -> Result<DVector<f64>, PoissonError> {
+    let chol = a.clone().cholesky().ok_or(PoissonError::SingularSystem)?;
+    let scale = a.diagonal().amax();
+    let threshold = f64::EPSILON * a.nrows() as f64 * scale;
+    if chol.l_dirty().diagonal().iter().any(|l| l * l <= threshold) {
+        return Err(PoissonError::SingularSystem);
+    }
+    Ok(chol.solve(b))
 }
 // ANCHOR_END: dense_solver
 
 /// Function that solves the sparse FEM system.
+///
+/// Runs the Jacobi-preconditioned conjugate gradient of `IterativeConfig::default()`, with
+/// at least as many iterations as unknowns. Use `solve_iterative` to choose the method, the
+/// preconditioner or the tolerance, and to get the convergence history.
 // ANCHOR: sparse_solver
-pub fn sparse_solver(a: &CsrMatrix<f64>, b: &DVector<f64>) -> Option<DVector<f64>> {
-    conjugate_gradient::solve(a, b, 1000, 1e-10)
+pub fn sparse_solver(a: &CsrMatrix<f64>, b: &DVector<f64>) -> Result<DVector<f64>, PoissonError> {
+    let config = IterativeConfig::default();
+    let max_iterations = config.max_iterations.max(a.nrows());
+    let (x, _) = solve_iterative(a, b, &config.with_max_iterations(max_iterations))?;
+    Ok(x)
 }
 // ANCHOR_END: sparse_solver
 
+/// Checks the mesh and the boundary nodes before assembling the system.
+///
+/// Returns the boundary nodes sorted and without duplicates.
+pub(crate) fn check_problem(
+    mesh: &Mesh2d,
+    boundary_nodes: &[usize],
+) -> Result<Vec<usize>, PoissonError> {
+    mesh.validate()?;
+    let num_vertices = mesh.vertices().len();
+    if let Some(&node) = boundary_nodes.iter().find(|&&node| node >= num_vertices) {
+        return Err(PoissonError::BoundaryNodeOutOfRange { node, num_vertices });
+    }
+    let mut nodes = boundary_nodes.to_vec();
+    nodes.sort_unstable();
+    nodes.dedup();
+    Ok(nodes)
+}
+
 /// Dense Poisson solver
 // ANCHOR: assemble_and_solve_dense
 pub fn assemble_and_solve_dense<F>(
@@ -291,18 +876,20 @@ pub fn assemble_and_solve_dense<F>(
     boundary_nodes: &[usize],
     boundary_fn: F,
     source_fn: F,
-) -> DVector<f64>
+) -> Result<DVector<f64>, PoissonError>
 where
-    F: Fn(f64, f64) -> f64,
+    F: Fn(f64, f64) -> f64 + Sync,
 {
+    let boundary_nodes = check_problem(mesh, boundary_nodes)?;
+
     // Assemble dense system
     let (mut a, mut b) = assemble_system_dense(mesh, &source_fn);
 
     // Apply BCs
-    apply_dirichlet_dense(&mut a, &mut b, boundary_nodes, mesh, boundary_fn);
+    apply_dirichlet_dense(&mut a, &mut b, &boundary_nodes, mesh, boundary_fn);
 
     // Solve linear system
-    dense_solver(&a, &b).expect("failed to solve")
+    dense_solver(&a, &b)
 }
 // ANCHOR_END: assemble_and_solve_dense
 
@@ -312,26 +899,85 @@ pub fn assemble_and_solve_sparse<F>(
     boundary_nodes: &[usize],
     boundary_fn: F,
     source_fn: F,
-) -> DVector<f64>
+) -> Result<DVector<f64>, PoissonError>
 where
-    F: Fn(f64, f64) -> f64,
+    F: Fn(f64, f64) -> f64 + Sync,
 {
+    let boundary_nodes = check_problem(mesh, boundary_nodes)?;
+
     // Assemble sparse system
     let (mut a, mut b) = assemble_system_sparse(mesh, &source_fn);
 
     // Apply BCs
-    apply_dirichlet_sparse(&mut a, &mut b, boundary_nodes, mesh, boundary_fn);
+    apply_dirichlet_sparse(&mut a, &mut b, &boundary_nodes, mesh, boundary_fn);
 
     // Solve linear system
-    sparse_solver(&a, &b).expect("failed to solve")
+    sparse_solver(&a, &b)
 }
 // ANCHOR_END: assemble_and_solve_sparse
 
+/// Sparse Poisson solver using an iterative method
+///
+/// Also returns the convergence history of the solve.
+// ANCHOR: assemble_and_solve_iterative
+pub fn assemble_and_solve_iterative<F>(
+    mesh: &Mesh2d,
+    boundary_nodes: &[usize],
+    boundary_fn: F,
+    source_fn: F,
+    config: &IterativeConfig,
+) -> Result<(DVector<f64>, ConvergenceHistory), PoissonError>
+where
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    let boundary_nodes = check_problem(mesh, boundary_nodes)?;
+
+    // Assemble sparse system
+    let (mut a, mut b) = assemble_system_sparse(mesh, &source_fn);
+
+    // Apply BCs
+    apply_dirichlet_sparse(&mut a, &mut b, &boundary_nodes, mesh, boundary_fn);
+
+    // Solve linear system
+    solve_iterative(&a, &b, config)
+}
+// ANCHOR_END: assemble_and_solve_iterative
+
+/// Sparse Poisson solver using a Cholesky factorization
+// ANCHOR: assemble_and_solve_cholesky
+pub fn assemble_and_solve_cholesky<F>(
+    mesh: &Mesh2d,
+    boundary_nodes: &[usize],
+    boundary_fn: F,
+    source_fn: F,
+    reordering: Reordering,
+) -> Result<DVector<f64>, PoissonError>
+where
+    F: Fn(f64, f64) -> f64 + Sync,
+{
+    let boundary_nodes = check_problem(mesh, boundary_nodes)?;
+
+    // Assemble sparse system
+    let (mut a, mut b) = assemble_system_sparse(mesh, &source_fn);
+
+    // Apply BCs
+    apply_dirichlet_sparse(&mut a, &mut b, &boundary_nodes, mesh, boundary_fn);
+
+    // Factor and solve linear system
+    SparseCholesky::factor(&a, reordering)?.solve(&b)
+}
+// ANCHOR_END: assemble_and_solve_cholesky
+
 // ANCHOR: tests
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::element::Element;
+    use crate::coefficient::PerRegion;
+    use crate::convergence::l2_error;
+    use crate::element::{Element, ElementType};
+    use crate::forms::{ConvectionDiffusionReaction, Stabilization};
+    use crate::generators::unit_square;
+    use nalgebra::Matrix2;
 
     #[test]
     fn test_assemble_system_dense() {
@@ -372,5 +1018,544 @@ mod tests {
         assert_eq!(a.nrows(), 4);
         assert_eq!(b.len(), 4);
     }
+
+    /// Solves `-Δu = 0` on the unit square for `u = 1 + 2x + 3y`, with Dirichlet conditions
+    /// on the left and bottom sides, Neumann on the top side and Robin on the right side.
+    /// Linear solutions are reproduced exactly by P1 and Q1 elements.
+    fn check_mixed_conditions(element_type: ElementType, sparse: bool) {
+        let (mesh, _) = unit_square(4, element_type);
+        let exact = |x: f64, y: f64| 1.0 + 2.0 * x + 3.0 * y;
+        let alpha = |_: f64, y: f64| 1.0 + y;
+        let zero = |_: f64, _: f64| 0.0;
+
+        let top = mesh.boundary_group("top").unwrap();
+        let right = mesh.boundary_group("right").unwrap();
+        let mut dirichlet = mesh.boundary_group_nodes("left").unwrap();
+        dirichlet.extend(mesh.boundary_group_nodes("bottom").unwrap());
+        dirichlet.sort_unstable();
+        dirichlet.dedup();
+
+        // On the top side ∂u/∂n = ∂u/∂y, on the right side ∂u/∂n = ∂u/∂x.
+        let neumann = |_: f64, _: f64| 3.0;
+        let robin = |x: f64, y: f64| 2.0 + alpha(x, y) * exact(x, y);
+
+        let u = if sparse {
+            let (mut a, mut b) = assemble_system_sparse(&mesh, &zero);
+            apply_neumann(&mut b, &mesh, top, neumann);
+            apply_robin_sparse(&mut a, &mut b, &mesh, right, alpha, robin).unwrap();
+            apply_dirichlet_sparse(&mut a, &mut b, &dirichlet, &mesh, exact);
+            sparse_solver(&a, &b).unwrap()
+        } else {
+            let (mut a, mut b) = assemble_system_dense(&mesh, &zero);
+            apply_neumann(&mut b, &mesh, top, neumann);
+            apply_robin_dense(&mut a, &mut b, &mesh, right, alpha, robin);
+            apply_dirichlet_dense(&mut a, &mut b, &dirichlet, &mesh, exact);
+            dense_solver(&a, &b).unwrap()
+        };
+
+        for (v, u_h) in mesh.vertices().iter().zip(u.iter()) {
+            assert!((u_h - exact(v.x, v.y)).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn test_neumann_and_robin_conditions() {
+        for element_type in [ElementType::P1, ElementType::Q1] {
+            check_mixed_conditions(element_type.clone(), false);
+            check_mixed_conditions(element_type, true);
+        }
+
+        // The Dirichlet conditions remove the entries coupling two boundary nodes.
+        let (mesh, boundary) = unit_square(2, ElementType::P1);
+        let right = mesh.boundary_group("right").unwrap();
+        let (mut a, mut b) = assemble_system_sparse(&mesh, &|_, _| 0.0);
+        apply_dirichlet_sparse(&mut a, &mut b, &boundary.all(), &mesh, |_, _| 0.0);
+        assert!(matches!(
+            apply_robin_sparse(&mut a, &mut b, &mesh, right, |_, _| 1.0, |_, _| 0.0),
+            Err(PoissonError::PatternMismatch)
+        ));
+    }
+
+    /// Solves `-div(κ ∇u) = f` with Dirichlet conditions on the whole boundary, using the
+    /// exact solution as boundary data, and returns the maximum nodal error.
+    fn diffusion_nodal_error<U, F>(
+        mesh: &Mesh2d,
+        kappa: &Diffusion,
+        exact: U,
+        source: F,
+        sparse: bool,
+    ) -> f64
+    where
+        U: Fn(f64, f64) -> f64,
+        F: Fn(f64, f64) -> f64 + Sync,
+    {
+        let boundary = mesh.boundary_nodes();
+        let u = if sparse {
+            let (mut a, mut b) = assemble_diffusion_system_sparse(mesh, kappa, &source).unwrap();
+            apply_dirichlet_sparse(&mut a, &mut b, &boundary, mesh, &exact);
+            sparse_solver(&a, &b).unwrap()
+        } else {
+            let (mut a, mut b) = assemble_diffusion_system_dense(mesh, kappa, &source).unwrap();
+            apply_dirichlet_dense(&mut a, &mut b, &boundary, mesh, &exact);
+            dense_solver(&a, &b).unwrap()
+        };
+        mesh.vertices()
+            .iter()
+            .zip(u.iter())
+            .map(|(v, u_h)| (u_h - exact(v.x, v.y)).abs())
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn test_variable_diffusion() {
+        // -d/dx((1 + x) d/dx x) = -1
+        let kappa = Diffusion::scalar(|x, _| 1.0 + x);
+        for element_type in [ElementType::P1, ElementType::Q1] {
+            let (mesh, _) = unit_square(4, element_type);
+            for sparse in [false, true] {
+                let error = diffusion_nodal_error(&mesh, &kappa, |x, _| x, |_, _| -1.0, sparse);
+                assert!(error < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_anisotropic_diffusion() {
+        // With K = [[2, 0.5], [0.5, 1]] and u = xy, -div(K ∇u) = -1. The bilinear solution
+        // is reproduced exactly by Q1 elements, which checks the off-diagonal terms.
+        let kappa = Diffusion::tensor(|_, _| Matrix2::new(2.0, 0.5, 0.5, 1.0));
+        let (mesh, _) = unit_square(4, ElementType::Q1);
+        for sparse in [false, true] {
+            let error = diffusion_nodal_error(&mesh, &kappa, |x, y| x * y, |_, _| -1.0, sparse);
+            assert!(error < 1e-8);
+        }
+    }
+
+    #[test]
+    fn test_layered_materials() {
+        // Two layers on [0, 2] x [0, 1] with κ = 1 for x < 1 and κ = 2 for x > 1. With
+        // u(0) = 0 and u(2) = 3, flux continuity gives slopes 2 and 1.
+        let exact = |x: f64, _: f64| if x < 1.0 { 2.0 * x } else { 1.0 + x };
+        for element_type in [ElementType::P1, ElementType::Q1] {
+            let (mut mesh, _) = crate::generators::rectangle(
+                Point2::new(0.0, 0.0),
+                Point2::new(2.0, 1.0),
+                4,
+                2,
+                element_type,
+            );
+            let right_layer: Vec<usize> = (0..mesh.elements().len())
+                .filter(|&e| {
+                    mesh.elements()[e]
+                        .indices
+                        .iter()
+                        .all(|&i| mesh.vertices()[i].x >= 1.0)
+                })
+                .collect();
+            mesh.add_region("right layer", right_layer);
+            let kappa = Diffusion::from(PerRegion::new(1.0).with_region("right layer", 2.0));
+
+            let mut dirichlet = mesh.boundary_group_nodes("left").unwrap();
+            dirichlet.extend(mesh.boundary_group_nodes("right").unwrap());
+            let (mut a, mut b) =
+                assemble_diffusion_system_dense(&mesh, &kappa, &|_, _| 0.0).unwrap();
+            apply_dirichlet_dense(&mut a, &mut b, &dirichlet, &mesh, exact);
+            let u = dense_solver(&a, &b).unwrap();
+            for (v, u_h) in mesh.vertices().iter().zip(u.iter()) {
+                assert!((u_h - exact(v.x, v.y)).abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn test_convection_diffusion_reaction_linear_solution() {
+        // -Δu + b·∇u + c u = f is solved exactly for u = x + y, with or without SUPG.
+        let exact = |x: f64, y: f64| x + y;
+        let source = |x: f64, y: f64| 3.0 + 0.5 * (x + y);
+        for element_type in [ElementType::P1, ElementType::Q1] {
+            let (mesh, _) = unit_square(5, element_type);
+            let boundary = mesh.boundary_nodes();
+            for stabilization in [Stabilization::None, Stabilization::Supg] {
+                let form = ConvectionDiffusionReaction::new(Diffusion::Constant(1.0))
+                    .with_velocity(|_, _| Vector2::new(1.0, 2.0))
+                    .with_reaction(|_, _| 0.5)
+                    .with_stabilization(stabilization);
+
+                let (mut a, mut b) = assemble_form_dense(&mesh, &form, &source).unwrap();
+                apply_dirichlet_dense(&mut a, &mut b, &boundary, &mesh, exact);
+                let u = a.lu().solve(&b).unwrap();
+                for (v, u_h) in mesh.vertices().iter().zip(u.iter()) {
+                    assert!((u_h - exact(v.x, v.y)).abs() < 1e-10);
+                }
+
+                let (mut a, mut b) = assemble_form_sparse(&mesh, &form, &source).unwrap();
+                apply_dirichlet_sparse(&mut a, &mut b, &boundary, &mesh, exact);
+                let u_sparse = DMatrix::from(&a).lu().solve(&b).unwrap();
+                assert!((u_sparse - u).norm() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn test_supg_quadratic_solution() {
+        // u = x² + xy is in the quadratic spaces, and SUPG stays consistent because the
+        // residual includes the diffusion term -κΔu = -2κ. The velocity varies so that τ does.
+        let exact = |x: f64, y: f64| x * x + x * y;
+        let source =
+            |x: f64, y: f64| -0.2 + (1.0 + y) * (2.0 * x + y) + (2.0 - x) * x + 0.5 * exact(x, y);
+        for element_type in [ElementType::P2, ElementType::Q2, ElementType::Q8] {
+            let (mesh, _) = unit_square(3, element_type);
+            let boundary = mesh.boundary_nodes();
+            let form = ConvectionDiffusionReaction::new(Diffusion::Constant(0.1))
+                .with_velocity(|x, y| Vector2::new(1.0 + y, 2.0 - x))
+                .with_reaction(|_, _| 0.5)
+                .with_stabilization(Stabilization::Supg);
+
+            let (mut a, mut b) = assemble_form_dense(&mesh, &form, &source).unwrap();
+            apply_dirichlet_dense(&mut a, &mut b, &boundary, &mesh, exact);
+            let u = a.lu().solve(&b).unwrap();
+            for (v, u_h) in mesh.vertices().iter().zip(u.iter()) {
+                assert!((u_h - exact(v.x, v.y)).abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn test_supg_removes_oscillations() {
+        // Boundary layer -ε u'' + u' = 0 with u(0) = 0 and u(1) = 1 at a mesh Péclet
+        // number of 25: Galerkin oscillates while SUPG stays within [0, 1].
+        let (mesh, _) = unit_square(20, ElementType::Q1);
+        let mut dirichlet = mesh.boundary_group_nodes("left").unwrap();
+        dirichlet.extend(mesh.boundary_group_nodes("right").unwrap());
+        let g = |x: f64, _: f64| x;
+
+        let solve = |stabilization| {
+            let form = ConvectionDiffusionReaction::new(Diffusion::Constant(1e-3))
+                .with_velocity(|_, _| Vector2::new(1.0, 0.0))
+                .with_stabilization(stabilization);
+            let (mut a, mut b) = assemble_form_dense(&mesh, &form, &|_, _| 0.0).unwrap();
+            apply_dirichlet_dense(&mut a, &mut b, &dirichlet, &mesh, g);
+            a.lu().solve(&b).unwrap()
+        };
+
+        let galerkin = solve(Stabilization::None);
+        assert!(galerkin.min() < -0.1 || galerkin.max() > 1.1);
+
+        let supg = solve(Stabilization::Supg);
+        assert!(supg.min() > -1e-8 && supg.max() < 1.0 + 1e-8);
+    }
+
+    #[test]
+    fn test_neumann_load() {
+        // The total flux through the boundary is ∫ h ds = perimeter for h = 1.
+        let (mesh, _) = unit_square(3, ElementType::Q1);
+        let mut b = DVector::zeros(mesh.vertices().len());
+        apply_neumann(&mut b, &mesh, &mesh.boundary_edges(), |_, _| 1.0);
+        assert!((b.sum() - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_mixed_mesh_linear_solution() {
+        // A unit square and two triangles covering [0, 2] x [0, 1].
+        let vertices = vec![
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 0.0),
+            Point2::new(2.0, 0.0),
+            Point2::new(0.0, 1.0),
+            Point2::new(1.0, 1.2),
+            Point2::new(2.0, 1.0),
+        ];
+        let elements = vec![
+            Element {
+                indices: vec![0, 1, 4, 3],
+            },
+            Element {
+                indices: vec![1, 2, 5],
+            },
+            Element {
+                indices: vec![1, 5, 4],
+            },
+        ];
+        let element_types = vec![ElementType::Q1, ElementType::P1, ElementType::P1];
+        let mesh = Mesh2d::new_mixed(vertices, elements, element_types);
+
+        let exact = |x: f64, y: f64| 1.0 + 2.0 * x - y;
+        let boundary = mesh.boundary_nodes();
+        let (mut a, mut b) = assemble_system_dense(&mesh, &|_, _| 0.0);
+        apply_dirichlet_dense(&mut a, &mut b, &boundary, &mesh, exact);
+        let (mut a_sparse, mut b_sparse) = assemble_system_sparse(&mesh, &|_, _| 0.0);
+        apply_dirichlet_sparse(&mut a_sparse, &mut b_sparse, &boundary, &mesh, exact);
+        assert_eq!(a.nrows(), 6);
+        assert!((DMatrix::from(&a_sparse) - &a).norm() < 1e-12);
+        assert!((b_sparse - &b).norm() < 1e-12);
+
+        let u = dense_solver(&a, &b).unwrap();
+        for (v, u_h) in mesh.vertices().iter().zip(u.iter()) {
+            assert!((u_h - exact(v.x, v.y)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_dirichlet_sparse_pattern() {
+        let (mesh, boundary) = unit_square(6, ElementType::Q1);
+        let nodes = boundary.all();
+        let g = |x: f64, y: f64| x - 2.0 * y;
+        let source = |x: f64, _: f64| x;
+
+        let (mut a, mut b) = assemble_system_dense(&mesh, &source);
+        apply_dirichlet_dense(&mut a, &mut b, &nodes, &mesh, g);
+        let (mut a_sparse, mut b_sparse) = assemble_system_sparse(&mesh, &source);
+        let nnz = a_sparse.nnz();
+        apply_dirichlet_sparse(&mut a_sparse, &mut b_sparse, &nodes, &mesh, g);
+
+        assert!((DMatrix::from(&a_sparse) - &a).norm() < 1e-12);
+        assert!((b_sparse - b).norm() < 1e-12);
+
+        // The eliminated entries are removed from the pattern and the matrix stays symmetric.
+        assert!(a_sparse.nnz() < nnz);
+        assert!(a_sparse.values().iter().all(|&v| v != 0.0));
+        assert_eq!(a_sparse.transpose(), a_sparse);
+    }
+
+    /// Dense system assembled element after element, without the rayon threads.
+    #[cfg(feature = "parallel")]
+    fn serial_assembly<B, F>(mesh: &Mesh2d, form: &B, source_fn: &F) -> (DMatrix<f64>, DVector<f64>)
+    where
+        B: BilinearForm,
+        F: Fn(f64, f64) -> f64,
+    {
+        let num_vertices = mesh.vertices().len();
+        let mut a = DMatrix::zeros(num_vertices, num_vertices);
+        let mut b = DVector::zeros(num_vertices);
+        let rules = element_rules(mesh);
+        let form_data = form.mesh_data(mesh).unwrap();
+        for (e, element) in mesh.elements().iter().enumerate() {
+            let (ke, fe) = element_system(mesh, &rules, form, &form_data, source_fn, e);
+            for (local_i, &global_i) in element.indices.iter().enumerate() {
+                b[global_i] += fe[local_i];
+                for (local_j, &global_j) in element.indices.iter().enumerate() {
+                    a[(global_i, global_j)] += ke[local_i][local_j];
+                }
+            }
+        }
+        (a, b)
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_assembly() {
+        let source = |x: f64, y: f64| (x * y).sin();
+        let kappa = Diffusion::scalar(|x, y| 1.0 + x * y);
+        let form = ConvectionDiffusionReaction::new(Diffusion::Constant(0.1))
+            .with_velocity(|_, y| Vector2::new(1.0, y))
+            .with_stabilization(Stabilization::Supg);
+        for element_type in [ElementType::P1, ElementType::Q2] {
+            let (mesh, _) = unit_square(8, element_type);
+
+            let (a, b) = serial_assembly(&mesh, &kappa, &source);
+            let (a_par, b_par) = assemble_form_dense(&mesh, &kappa, &source).unwrap();
+            assert!((a_par - &a).norm() < 1e-12 * a.norm());
+            assert!((b_par - &b).norm() < 1e-12 * b.norm());
+
+            let (a, b) = serial_assembly(&mesh, &form, &source);
+            let (a_par, b_par) = assemble_form_sparse(&mesh, &form, &source).unwrap();
+            assert!((DMatrix::from(&a_par) - &a).norm() < 1e-12 * a.norm());
+            assert!((b_par - &b).norm() < 1e-12 * b.norm());
+
+            let pattern = SystemPattern::new(&mesh);
+            let mut a_in_place = pattern.zeros();
+            let mut b_in_place = DVector::zeros(b.len());
+            reassemble_form_sparse(
+                &pattern,
+                &mesh,
+                &form,
+                &source,
+                &mut a_in_place,
+                &mut b_in_place,
+            )
+            .unwrap();
+            assert!((DMatrix::from(&a_in_place) - &a).norm() < 1e-12 * a.norm());
+            assert!((b_in_place - &b).norm() < 1e-12 * b.norm());
+        }
+    }
+
+    #[test]
+    fn test_reassembly_in_place() {
+        let (mesh, boundary) = unit_square(5, ElementType::P2);
+        let pattern = SystemPattern::new(&mesh);
+        let mut a = pattern.zeros();
+        let mut b = DVector::zeros(mesh.vertices().len());
+        let values_ptr = a.values().as_ptr();
+        let source = |x: f64, y: f64| x + y;
+
+        for kappa in [Diffusion::Constant(1.0), Diffusion::scalar(|x, _| 1.0 + x)] {
+            reassemble_form_sparse(&pattern, &mesh, &kappa, &source, &mut a, &mut b).unwrap();
+            let (a_ref, b_ref) = assemble_form_sparse(&mesh, &kappa, &source).unwrap();
+            assert_eq!(a.pattern(), a_ref.pattern());
+            assert!((DMatrix::from(&a) - DMatrix::from(&a_ref)).norm() < 1e-12);
+            assert!((&b - b_ref).norm() < 1e-12);
+            assert_eq!(a.values().as_ptr(), values_ptr);
+
+            // The in-place Dirichlet elimination gives the same system and keeps the pattern.
+            let (mut a_dir, mut b_dir) = (a_ref, b.clone());
+            apply_dirichlet_sparse(&mut a_dir, &mut b_dir, &boundary.all(), &mesh, |x, _| x);
+            let mut b_in_place = b.clone();
+            let mut a_in_place = a.clone();
+            apply_dirichlet_sparse_in_place(
+                &mut a_in_place,
+                &mut b_in_place,
+                &boundary.all(),
+                &mesh,
+                |x, _| x,
+            );
+            assert_eq!(a_in_place.pattern(), pattern.pattern());
+            assert!((DMatrix::from(&a_in_place) - DMatrix::from(&a_dir)).norm() < 1e-12);
+            assert!((b_in_place - b_dir).norm() < 1e-12);
+        }
+
+        let (other_mesh, _) = unit_square(4, ElementType::P2);
+        assert!(matches!(
+            reassemble_form_sparse(
+                &pattern,
+                &other_mesh,
+                &Diffusion::Constant(1.0),
+                &source,
+                &mut a,
+                &mut b
+            ),
+            Err(PoissonError::PatternMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_mass_matrix_and_load() {
+        // The entries of the mass matrix sum up to the area of the domain, and the load of
+        // f = 1 gives the row sums of the mass matrix.
+        for element_type in [ElementType::P1, ElementType::Q1, ElementType::P2] {
+            let (mesh, _) = crate::generators::rectangle(
+                Point2::new(0.0, 0.0),
+                Point2::new(2.0, 1.5),
+                3,
+                2,
+                element_type,
+            );
+            let m = assemble_mass_dense(&mesh);
+            assert!((m.sum() - 3.0).abs() < 1e-12);
+            assert!((&m - m.transpose()).norm() < 1e-14);
+            assert!((DMatrix::from(&assemble_mass_sparse(&mesh)) - &m).norm() < 1e-14);
+
+            let b = assemble_load_vector(&mesh, &|_, _| 1.0);
+            let ones = DVector::from_element(mesh.vertices().len(), 1.0);
+            assert!((b - &m * ones).norm() < 1e-14);
+        }
+    }
+
+    #[test]
+    fn test_quadratic_elements_convergence() {
+        use std::f64::consts::PI;
+        let exact = |x: f64, y: f64| (PI * x).sin() * (PI * y).sin();
+        let source = |x: f64, y: f64| 2.0 * PI * PI * exact(x, y);
+
+        // The L2 error decreases as h^2 for linear elements and h^3 for quadratic ones.
+        let cases = [
+            (ElementType::P1, 2.0),
+            (ElementType::Q1, 2.0),
+            (ElementType::P2, 3.0),
+            (ElementType::Q2, 3.0),
+            (ElementType::Q8, 3.0),
+        ];
+        for (element_type, expected_rate) in cases {
+            let errors: Vec<f64> = [4, 8, 16]
+                .iter()
+                .map(|&n| {
+                    let (mesh, boundary) = unit_square(n, element_type.clone());
+                    let (mut a, mut b) = assemble_system_sparse(&mesh, &source);
+                    apply_dirichlet_sparse(&mut a, &mut b, &boundary.all(), &mesh, |_, _| 0.0);
+                    let u = sparse_solver(&a, &b).unwrap();
+                    l2_error(&mesh, &u, exact)
+                })
+                .collect();
+            for pair in errors.windows(2) {
+                let rate = (pair[0] / pair[1]).log2();
+                assert!(
+                    (rate - expected_rate).abs() < 0.25,
+                    "{element_type:?}: rate {rate} (errors {errors:?})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_solver_errors() {
+        let (mesh, boundary) = unit_square(4, ElementType::P1);
+        let zero: fn(f64, f64) -> f64 = |_, _| 0.0;
+
+        // No boundary condition: the Neumann problem is singular.
+        assert!(matches!(
+            assemble_and_solve_dense(&mesh, &[], zero, zero),
+            Err(PoissonError::SingularSystem)
+        ));
+        assert!(matches!(
+            assemble_and_solve_dense(&mesh, &[0, 25], zero, zero),
+            Err(PoissonError::BoundaryNodeOutOfRange {
+                node: 25,
+                num_vertices: 25
+            })
+        ));
+
+        // Duplicate boundary nodes are accepted.
+        let mut nodes = boundary.all();
+        nodes.extend(boundary.all());
+        let u = assemble_and_solve_sparse(&mesh, &nodes, |x, _| x, zero).unwrap();
+        for (v, u_h) in mesh.vertices().iter().zip(u.iter()) {
+            assert!((u_h - v.x).abs() < 1e-8);
+        }
+
+        let u_cholesky =
+            assemble_and_solve_cholesky(&mesh, &nodes, |x, _| x, zero, Reordering::Natural)
+                .unwrap();
+        assert!((u_cholesky - u).norm() < 1e-8);
+        assert!(matches!(
+            assemble_and_solve_cholesky(&mesh, &[], zero, zero, Reordering::ReverseCuthillMcKee),
+            Err(PoissonError::SingularSystem)
+        ));
+
+        let (a, b) = assemble_system_sparse(&mesh, &|_, _| 1.0);
+        let config = IterativeConfig::default().with_max_iterations(3);
+        match solve_iterative(&a, &b, &config) {
+            Err(PoissonError::NotConverged {
+                iterations,
+                residual,
+            }) => {
+                assert_eq!(iterations, 3);
+                assert!(residual > 1e-10);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let kappa = Diffusion::from(PerRegion::new(1.0).with_region("air", 0.1));
+        assert!(matches!(
+            assemble_diffusion_system_sparse(&mesh, &kappa, &zero),
+            Err(PoissonError::UnknownRegion(name)) if name == "air"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_mesh() {
+        let vertices = vec![
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 0.0),
+            Point2::new(2.0, 0.0),
+        ];
+        let elements = vec![Element {
+            indices: vec![0, 1, 2],
+        }];
+        let mesh = Mesh2d::new(vertices, elements, ElementType::P1);
+        let zero = |_: f64, _: f64| 0.0;
+        assert!(matches!(
+            assemble_and_solve_sparse(&mesh, &[0, 1, 2], zero, zero),
+            Err(PoissonError::InvalidMesh(MeshError::DegenerateElement(0)))
+        ));
+    }
 }
 // ANCHOR_END: tests
@@ -0,0 +1,402 @@
+//! Module that exports meshes and solutions to VTK files for visualization in ParaView.
+//!
+//! Three formats are supported:
+//! - legacy `.vtk` files (ASCII),
+//! - XML `.vtu` unstructured grid files (ASCII),
+//! - `.pvd` collections referencing one `.vtu` file per time step.
+use crate::element::ElementType;
+use crate::mesh::Mesh2d;
+use nalgebra::DVector;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// VTK cell type identifier of an element type.
+///
+/// The VTK node ordering of quadratic cells matches the one of the mesh elements.
+fn vtk_cell_type(element_type: &ElementType) -> u8 {
+    match element_type {
+        ElementType::P1 => 5,
+        ElementType::Q1 => 9,
+        ElementType::P2 => 22,
+        ElementType::Q8 => 23,
+        ElementType::Q2 => 28,
+    }
+}
+
+/// Escapes the characters that are not allowed in XML attribute values.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// ANCHOR: vtk_writer
+/// Writer collecting a mesh and the fields to export with it.
+///
+/// Fields are borrowed, so the writer is meant to be built right before writing.
+pub struct VtkWriter<'a> {
+    mesh: &'a Mesh2d,
+    point_data: Vec<(String, &'a DVector<f64>)>,
+    cell_data: Vec<(String, &'a DVector<f64>)>,
+}
+
+impl<'a> VtkWriter<'a> {
+    pub fn new(mesh: &'a Mesh2d) -> Self {
+        Self {
+            mesh,
+            point_data: Vec::new(),
+            cell_data: Vec::new(),
+        }
+    }
+
+    /// Adds a nodal field, with one value per mesh vertex.
+    pub fn point_data(mut self, name: &str, values: &'a DVector<f64>) -> Self {
+        self.point_data.push((name.to_string(), values));
+        self
+    }
+
+    /// Adds an element field, with one value per mesh element.
+    pub fn cell_data(mut self, name: &str, values: &'a DVector<f64>) -> Self {
+        self.cell_data.push((name.to_string(), values));
+        self
+    }
+
+    /// Checks that every field has the expected number of values.
+    fn check_fields(&self) -> io::Result<()> {
+        let expected = [
+            (&self.point_data, self.mesh.vertices().len(), "vertices"),
+            (&self.cell_data, self.mesh.elements().len(), "elements"),
+        ];
+        for (fields, len, what) in expected {
+            for (name, values) in fields {
+                if values.len() != len {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "field \"{name}\" has {} values but the mesh has {len} {what}",
+                            values.len()
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the mesh and its fields to a legacy `.vtk` file.
+    pub fn write_vtk<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_legacy(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Writes the mesh and its fields to an XML `.vtu` file.
+    pub fn write_vtu<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_xml(&mut writer)?;
+        writer.flush()
+    }
+}
+// ANCHOR_END: vtk_writer
+
+// ANCHOR: write_legacy
+impl VtkWriter<'_> {
+    /// Writes the legacy VTK representation to any writer.
+    ///
+    /// Legacy files do not accept spaces in field names, they are replaced by underscores.
+    pub fn write_legacy<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.check_fields()?;
+        let vertices = self.mesh.vertices();
+        let elements = self.mesh.elements();
+
+        writeln!(w, "# vtk DataFile Version 3.0")?;
+        writeln!(w, "poisson_2d output")?;
+        writeln!(w, "ASCII")?;
+        writeln!(w, "DATASET UNSTRUCTURED_GRID")?;
+        writeln!(w, "POINTS {} double", vertices.len())?;
+        for v in vertices {
+            writeln!(w, "{} {} 0", v.x, v.y)?;
+        }
+
+        let size: usize = elements.iter().map(|e| e.indices.len() + 1).sum();
+        writeln!(w, "CELLS {} {size}", elements.len())?;
+        for element in elements {
+            write!(w, "{}", element.indices.len())?;
+            for i in &element.indices {
+                write!(w, " {i}")?;
+            }
+            writeln!(w)?;
+        }
+        writeln!(w, "CELL_TYPES {}", elements.len())?;
+        for element_type in self.mesh.element_types() {
+            writeln!(w, "{}", vtk_cell_type(element_type))?;
+        }
+
+        let sections = [
+            ("POINT_DATA", &self.point_data, vertices.len()),
+            ("CELL_DATA", &self.cell_data, elements.len()),
+        ];
+        for (keyword, fields, len) in sections {
+            if fields.is_empty() {
+                continue;
+            }
+            writeln!(w, "{keyword} {len}")?;
+            for (name, values) in fields {
+                writeln!(w, "SCALARS {} double 1", name.replace(' ', "_"))?;
+                writeln!(w, "LOOKUP_TABLE default")?;
+                for value in values.iter() {
+                    writeln!(w, "{value}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+// ANCHOR_END: write_legacy
+
+// ANCHOR: write_xml
+impl VtkWriter<'_> {
+    /// Writes the XML unstructured grid (`.vtu`) representation to any writer.
+    pub fn write_xml<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.check_fields()?;
+        let vertices = self.mesh.vertices();
+        let elements = self.mesh.elements();
+
+        writeln!(w, r#"<?xml version="1.0"?>"#)?;
+        writeln!(
+            w,
+            r#"<VTKFile type="UnstructuredGrid" version="0.1" byte_order="LittleEndian">"#
+        )?;
+        writeln!(w, "  <UnstructuredGrid>")?;
+        writeln!(
+            w,
+            r#"    <Piece NumberOfPoints="{}" NumberOfCells="{}">"#,
+            vertices.len(),
+            elements.len()
+        )?;
+
+        for (tag, fields) in [
+            ("PointData", &self.point_data),
+            ("CellData", &self.cell_data),
+        ] {
+            if fields.is_empty() {
+                continue;
+            }
+            writeln!(w, "      <{tag}>")?;
+            for (name, values) in fields {
+                writeln!(
+                    w,
+                    r#"        <DataArray type="Float64" Name="{}" format="ascii">"#,
+                    xml_escape(name)
+                )?;
+                for value in values.iter() {
+                    writeln!(w, "          {value}")?;
+                }
+                writeln!(w, "        </DataArray>")?;
+            }
+            writeln!(w, "      </{tag}>")?;
+        }
+
+        writeln!(w, "      <Points>")?;
+        writeln!(
+            w,
+            r#"        <DataArray type="Float64" NumberOfComponents="3" format="ascii">"#
+        )?;
+        for v in vertices {
+            writeln!(w, "          {} {} 0", v.x, v.y)?;
+        }
+        writeln!(w, "        </DataArray>")?;
+        writeln!(w, "      </Points>")?;
+
+        writeln!(w, "      <Cells>")?;
+        writeln!(
+            w,
+            r#"        <DataArray type="Int64" Name="connectivity" format="ascii">"#
+        )?;
+        for element in elements {
+            let indices: Vec<String> = element.indices.iter().map(|i| i.to_string()).collect();
+            writeln!(w, "          {}", indices.join(" "))?;
+        }
+        writeln!(w, "        </DataArray>")?;
+        writeln!(
+            w,
+            r#"        <DataArray type="Int64" Name="offsets" format="ascii">"#
+        )?;
+        let mut offset = 0;
+        for element in elements {
+            offset += element.indices.len();
+            writeln!(w, "          {offset}")?;
+        }
+        writeln!(w, "        </DataArray>")?;
+        writeln!(
+            w,
+            r#"        <DataArray type="UInt8" Name="types" format="ascii">"#
+        )?;
+        for element_type in self.mesh.element_types() {
+            writeln!(w, "          {}", vtk_cell_type(element_type))?;
+        }
+        writeln!(w, "        </DataArray>")?;
+        writeln!(w, "      </Cells>")?;
+
+        writeln!(w, "    </Piece>")?;
+        writeln!(w, "  </UnstructuredGrid>")?;
+        writeln!(w, "</VTKFile>")?;
+        Ok(())
+    }
+}
+// ANCHOR_END: write_xml
+
+// ANCHOR: pvd_collection
+/// Collection of `.vtu` files indexed by time, written as a ParaView `.pvd` file.
+#[derive(Clone, Debug, Default)]
+pub struct PvdCollection {
+    entries: Vec<(f64, String)>,
+}
+
+impl PvdCollection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the file written for time `time`.
+    ///
+    /// Paths are stored as given, relative paths being resolved by ParaView from the
+    /// location of the `.pvd` file.
+    pub fn add(&mut self, time: f64, file: &str) {
+        self.entries.push((time, file.to_string()));
+    }
+
+    /// Writes the data set of time `time` to `directory/file` and registers it.
+    pub fn write_step<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+        file: &str,
+        time: f64,
+        writer: &VtkWriter,
+    ) -> io::Result<()> {
+        writer.write_vtu(directory.as_ref().join(file))?;
+        self.add(time, file);
+        Ok(())
+    }
+
+    /// Writes the collection to a `.pvd` file.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_xml(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Writes the XML representation of the collection to any writer.
+    pub fn write_xml<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, r#"<?xml version="1.0"?>"#)?;
+        writeln!(
+            w,
+            r#"<VTKFile type="Collection" version="0.1" byte_order="LittleEndian">"#
+        )?;
+        writeln!(w, "  <Collection>")?;
+        for (time, file) in &self.entries {
+            writeln!(
+                w,
+                r#"    <DataSet timestep="{time}" group="" part="0" file="{}"/>"#,
+                xml_escape(file)
+            )?;
+        }
+        writeln!(w, "  </Collection>")?;
+        writeln!(w, "</VTKFile>")?;
+        Ok(())
+    }
+}
+// ANCHOR_END: pvd_collection
+
+// ANCHOR: tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::unit_square;
+
+    fn to_string<F>(write: F) -> String
+    where
+        F: FnOnce(&mut Vec<u8>) -> io::Result<()>,
+    {
+        let mut buffer = Vec::new();
+        write(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_write_legacy() {
+        let (mesh, _) = unit_square(1, ElementType::P1);
+        let u = DVector::from_vec(vec![0.0, 1.0, 2.0, 3.5]);
+        let kappa = DVector::from_vec(vec![1.0, 2.0]);
+        let writer = VtkWriter::new(&mesh)
+            .point_data("u", &u)
+            .cell_data("kappa value", &kappa);
+        let text = to_string(|w| writer.write_legacy(w));
+
+        assert!(text.starts_with("# vtk DataFile Version 3.0\n"));
+        assert!(text.contains("POINTS 4 double\n0 0 0\n1 0 0\n"));
+        assert!(text.contains("CELLS 2 8\n3 0 1 3\n3 0 3 2\n"));
+        assert!(text.contains("CELL_TYPES 2\n5\n5\n"));
+        assert!(
+            text.contains("POINT_DATA 4\nSCALARS u double 1\nLOOKUP_TABLE default\n0\n1\n2\n3.5\n")
+        );
+        assert!(text.contains("CELL_DATA 2\nSCALARS kappa_value double 1\n"));
+
+        let (quadratic, _) = unit_square(1, ElementType::Q2);
+        let text = to_string(|w| VtkWriter::new(&quadratic).write_legacy(w));
+        assert!(text.contains("CELLS 1 10\n9 "));
+        assert!(text.contains("CELL_TYPES 1\n28\n"));
+    }
+
+    #[test]
+    fn test_write_xml() {
+        let (mesh, _) = unit_square(2, ElementType::Q1);
+        let u = DVector::from_element(9, 1.0);
+        let writer = VtkWriter::new(&mesh).point_data("u & v", &u);
+        let text = to_string(|w| writer.write_xml(w));
+
+        assert!(text.contains(r#"<Piece NumberOfPoints="9" NumberOfCells="4">"#));
+        assert!(text.contains(r#"Name="u &amp; v""#));
+        assert!(!text.contains("<CellData>"));
+        assert_eq!(text.matches("          9\n").count(), 4);
+        assert!(text.contains("          0 1 4 3\n"));
+        assert!(text.contains("          16\n"));
+        assert!(text.trim_end().ends_with("</VTKFile>"));
+    }
+
+    #[test]
+    fn test_field_length_mismatch() {
+        let (mesh, _) = unit_square(2, ElementType::Q1);
+        let wrong = DVector::zeros(3);
+        let writer = VtkWriter::new(&mesh).cell_data("error", &wrong);
+        let err = writer.write_xml(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(writer.write_legacy(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_pvd_collection() {
+        let directory = std::env::temp_dir().join("poisson_2d_vtk_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let (mesh, _) = unit_square(2, ElementType::P1);
+
+        let mut collection = PvdCollection::new();
+        for step in 0..3 {
+            let u = DVector::from_element(9, step as f64);
+            let writer = VtkWriter::new(&mesh).point_data("u", &u);
+            let file = format!("step_{step}.vtu");
+            collection
+                .write_step(&directory, &file, 0.5 * step as f64, &writer)
+                .unwrap();
+        }
+        collection.write(directory.join("series.pvd")).unwrap();
+
+        let pvd = std::fs::read_to_string(directory.join("series.pvd")).unwrap();
+        assert_eq!(pvd.matches("<DataSet").count(), 3);
+        assert!(pvd.contains(r#"timestep="0.5" group="" part="0" file="step_1.vtu""#));
+        assert!(directory.join("step_2.vtu").exists());
+    }
+}
+// ANCHOR_END: tests